@@ -0,0 +1,34 @@
+pub mod create_pull_request;
+pub mod create_push;
+pub mod get_connection_data;
+pub mod get_item;
+pub mod get_items;
+pub mod get_pull_request;
+pub mod get_refs;
+pub mod update_pull_request;
+pub mod update_refs;
+
+pub use create_pull_request::create_pull_request;
+pub use create_push::create_push;
+pub use get_connection_data::get_connection_data;
+pub use get_item::get_item;
+pub use get_items::get_items;
+pub use get_pull_request::get_pull_request;
+pub use get_refs::get_refs;
+pub use update_pull_request::update_pull_request;
+pub use update_refs::update_refs;
+
+pub const API_VERSION: (&str, &str) = ("api-version", "7.0");
+
+/// Azure DevOps answers requests with a rejected token by redirecting to its
+/// sign-in page with a 203 status instead of a 401.
+pub async fn check(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, cargolifter_core::models::BackendError> {
+    if response.status() == reqwest::StatusCode::NON_AUTHORITATIVE_INFORMATION {
+        return Err(cargolifter_core::models::BackendError::Unauthorized(
+            format!("{}: token was not accepted", response.url()),
+        ));
+    }
+    cargolifter_core::models::BackendError::check(response).await
+}
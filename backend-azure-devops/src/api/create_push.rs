@@ -0,0 +1,18 @@
+pub async fn create_push(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    request: &crate::models::create_push::Request,
+) -> Result<(), cargolifter_core::models::BackendError> {
+    let url = format!("{}/pushes", repository_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .basic_auth(username, Some(token))
+        .query(&[super::API_VERSION])
+        .json(request)
+        .send()
+        .await?;
+    super::check(response).await?;
+    Ok(())
+}
@@ -0,0 +1,18 @@
+pub async fn get_connection_data(
+    organization_url: &str,
+    username: &str,
+    token: &str,
+) -> Result<crate::models::get_connection_data::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/_apis/connectionData", organization_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
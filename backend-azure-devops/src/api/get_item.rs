@@ -0,0 +1,29 @@
+pub async fn get_item(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    file: &str,
+    commit: &str,
+) -> Result<crate::models::get_item::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/items", repository_url);
+    let path = format!("/{}", file);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .query(&[
+            ("path", path.as_str()),
+            ("versionDescriptor.version", commit),
+            ("versionDescriptor.versionType", "commit"),
+            ("includeContent", "true"),
+            ("$format", "json"),
+            super::API_VERSION,
+        ])
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
@@ -0,0 +1,26 @@
+pub async fn get_items(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    commit: &str,
+) -> Result<crate::models::get_items::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/items", repository_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .query(&[
+            ("scopePath", "/"),
+            ("recursionLevel", "full"),
+            ("versionDescriptor.version", commit),
+            ("versionDescriptor.versionType", "commit"),
+            super::API_VERSION,
+        ])
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
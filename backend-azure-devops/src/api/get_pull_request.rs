@@ -0,0 +1,20 @@
+pub async fn get_pull_request(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    id: u64,
+) -> Result<crate::models::get_pull_request::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/pullrequests/{}", repository_url, id);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .query(&[super::API_VERSION])
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
@@ -0,0 +1,23 @@
+pub async fn get_refs(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    branch: &str,
+) -> Result<crate::models::get_refs::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/refs", repository_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .query(&[
+            ("filter", format!("heads/{}", branch).as_str()),
+            super::API_VERSION,
+        ])
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
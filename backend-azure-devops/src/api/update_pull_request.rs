@@ -0,0 +1,19 @@
+pub async fn update_pull_request(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    id: u64,
+    request: &crate::models::update_pull_request::Request,
+) -> Result<(), cargolifter_core::models::BackendError> {
+    let url = format!("{}/pullrequests/{}", repository_url, id);
+    let client = reqwest::Client::new();
+    let response = client
+        .patch(url)
+        .basic_auth(username, Some(token))
+        .query(&[super::API_VERSION])
+        .json(request)
+        .send()
+        .await?;
+    super::check(response).await?;
+    Ok(())
+}
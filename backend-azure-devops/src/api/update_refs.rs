@@ -0,0 +1,21 @@
+pub async fn update_refs(
+    repository_url: &str,
+    username: &str,
+    token: &str,
+    request: &[crate::models::update_refs::RefUpdate],
+) -> Result<crate::models::update_refs::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/refs", repository_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .basic_auth(username, Some(token))
+        .query(&[super::API_VERSION])
+        .json(request)
+        .send()
+        .await?;
+    super::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
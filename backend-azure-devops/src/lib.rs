@@ -0,0 +1,603 @@
+mod api;
+mod models;
+
+use async_trait::async_trait;
+use cargolifter_core::Backend;
+
+/// pull requests are merged via auto-complete, this is how often and how long to wait for it
+const MERGE_POLL_ATTEMPTS: u32 = 30;
+const MERGE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+const NULL_OBJECT_ID: &str = "0000000000000000000000000000000000000000";
+
+pub struct AzureDevOps {
+    cargolifter_token: Option<String>,
+    organization_url: String,
+    repository_url: String,
+    default_branch: String,
+    commit_mode: cargolifter_core::config::CommitMode,
+}
+
+impl AzureDevOps {
+    pub fn from(config: cargolifter_core::config::AzureDevOpsConfig) -> Self {
+        let host = config
+            .host
+            .unwrap_or_else(|| String::from("https://dev.azure.com"));
+        let organization_url = format!("{}/{}", host, config.organization);
+        Self {
+            cargolifter_token: config.cargolifter_token,
+            repository_url: format!(
+                "{}/{}/_apis/git/repositories/{}",
+                organization_url, config.project, config.repo
+            ),
+            organization_url,
+            default_branch: config
+                .default_branch
+                .unwrap_or_else(|| String::from("main")),
+            commit_mode: config.commit_mode.unwrap_or_default(),
+        }
+    }
+
+    /// personal access tokens are used as password, the user name is optional (`user:token`)
+    fn config(&self, token: &str) -> (String, String) {
+        match token.split_once(':') {
+            Some((username, password)) => (username.into(), password.into()),
+            None => (String::new(), token.into()),
+        }
+    }
+
+    fn merge_config(&self, token: &str) -> (String, String) {
+        self.config(self.cargolifter_token.as_deref().unwrap_or(token))
+    }
+
+    async fn branch_head(
+        &self,
+        username: &str,
+        password: &str,
+        branch_name: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        let ref_name = format!("refs/heads/{}", branch_name);
+        // the filter matches by prefix
+        api::get_refs(&self.repository_url, username, password, branch_name)
+            .await?
+            .value
+            .into_iter()
+            .find(|r| r.name == ref_name)
+            .map(|r| r.object_id)
+            .ok_or_else(|| {
+                cargolifter_core::models::BackendError::NotFound(format!(
+                    "branch '{}' does not exist",
+                    branch_name
+                ))
+            })
+    }
+
+    /// pushes a commit changing `crate_path` to `branch_name`, which is based on `current_sha`
+    /// as returned by `get_file` or on the default branch for new files
+    async fn push(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let (base_commit, change_type) = match current_sha {
+            Some(sha) => (sha.to_owned(), "edit"),
+            None => (
+                self.branch_head(&username, &password, &self.default_branch)
+                    .await?,
+                "add",
+            ),
+        };
+        let push_request = models::create_push::Request {
+            ref_updates: vec![models::create_push::RefUpdate {
+                name: format!("refs/heads/{}", branch_name),
+                old_object_id: base_commit,
+            }],
+            commits: vec![models::create_push::Commit {
+                comment: message.into(),
+                changes: vec![models::create_push::Change {
+                    change_type: change_type.into(),
+                    item: models::create_push::Item {
+                        path: format!("/{}", crate_path),
+                    },
+                    new_content: models::create_push::NewContent {
+                        content: content.into(),
+                        content_type: "rawtext".into(),
+                    },
+                }],
+            }],
+        };
+        api::create_push(&self.repository_url, &username, &password, &push_request).await
+    }
+}
+
+#[async_trait]
+impl Backend for AzureDevOps {
+    /// the returned sha is the head of the default branch the content was read at
+    async fn get_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+    ) -> Result<(String, String, String), cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let head = self
+            .branch_head(&username, &password, &self.default_branch)
+            .await?;
+        let item = api::get_item(
+            &self.repository_url,
+            &username,
+            &password,
+            crate_path,
+            &head,
+        )
+        .await?;
+        Ok((item.content, "utf-8".into(), head))
+    }
+
+    async fn create_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.push(token, crate_path, branch_name, content, message, None)
+            .await
+    }
+
+    async fn update_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.push(
+            token,
+            crate_path,
+            branch_name,
+            content,
+            message,
+            Some(current_sha),
+        )
+        .await
+    }
+
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let head = self.branch_head(&username, &password, branch_name).await?;
+        let update_request = [models::update_refs::RefUpdate {
+            name: format!("refs/heads/{}", branch_name),
+            old_object_id: head,
+            new_object_id: NULL_OBJECT_ID.into(),
+        }];
+        let response =
+            api::update_refs(&self.repository_url, &username, &password, &update_request).await?;
+
+        match response.value.into_iter().find(|result| !result.success) {
+            Some(result) => Err(cargolifter_core::models::BackendError::Conflict(format!(
+                "failed to delete '{}': {}",
+                result.name, result.update_status
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    async fn create_pull_request(
+        &self,
+        token: &str,
+        title: &str,
+        branch_name: &str,
+    ) -> Result<u64, cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let pull_request = models::create_pull_request::Request {
+            source_ref_name: format!("refs/heads/{}", branch_name),
+            target_ref_name: format!("refs/heads/{}", self.default_branch),
+            title: title.into(),
+        };
+        match api::create_pull_request(&self.repository_url, &username, &password, &pull_request)
+            .await
+        {
+            Ok(response) => Ok(response.pull_request_id),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// enables auto-complete and waits until the pull request got merged,
+    /// so branch policies like required builds are respected
+    async fn merge_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let (username, password) = self.merge_config(token);
+
+        let identity = api::get_connection_data(&self.organization_url, &username, &password)
+            .await?
+            .authenticated_user;
+        let auto_complete = models::update_pull_request::Request {
+            auto_complete_set_by: Some(models::update_pull_request::IdentityRef {
+                id: identity.id,
+            }),
+            completion_options: Some(models::update_pull_request::CompletionOptions {
+                merge_strategy: "noFastForward".into(),
+                delete_source_branch: false,
+            }),
+            ..Default::default()
+        };
+        api::update_pull_request(
+            &self.repository_url,
+            &username,
+            &password,
+            id,
+            &auto_complete,
+        )
+        .await?;
+
+        for _ in 0..MERGE_POLL_ATTEMPTS {
+            let pull_request =
+                api::get_pull_request(&self.repository_url, &username, &password, id).await?;
+            match (
+                pull_request.status.as_str(),
+                pull_request.merge_status.as_deref(),
+            ) {
+                ("completed", _) => return Ok(()),
+                ("abandoned", _) => {
+                    return Err(cargolifter_core::models::BackendError::Conflict(format!(
+                        "pull request {} was abandoned",
+                        id
+                    )))
+                }
+                (_, Some("conflicts")) => {
+                    return Err(cargolifter_core::models::BackendError::Conflict(format!(
+                        "pull request {} has conflicts",
+                        id
+                    )))
+                }
+                _ => tokio::time::sleep(MERGE_POLL_INTERVAL).await,
+            }
+        }
+
+        Err(cargolifter_core::models::BackendError::Conflict(format!(
+            "pull request {} did not complete, check the branch policies",
+            id
+        )))
+    }
+
+    async fn delete_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let abandon = models::update_pull_request::Request {
+            status: Some("abandoned".into()),
+            ..Default::default()
+        };
+        api::update_pull_request(&self.repository_url, &username, &password, id, &abandon).await
+    }
+
+    /// the push is rejected if the default branch moved past `current_sha`
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.push(
+            token,
+            crate_path,
+            &self.default_branch,
+            content,
+            message,
+            current_sha,
+        )
+        .await
+    }
+
+    async fn list_files(
+        &self,
+        token: &str,
+    ) -> Result<Vec<String>, cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        let head = self
+            .branch_head(&username, &password, &self.default_branch)
+            .await?;
+        let items = api::get_items(&self.repository_url, &username, &password, &head).await?;
+        Ok(items
+            .value
+            .into_iter()
+            .filter(|item| !item.is_folder)
+            .map(|item| item.path.trim_start_matches('/').into())
+            .collect())
+    }
+
+    async fn get_user(
+        &self,
+        token: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        let (username, password) = self.config(token);
+
+        match api::get_connection_data(&self.organization_url, &username, &password).await {
+            Ok(response) => Ok(response.authenticated_user.account()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargolifter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        None
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::{MetaData, PublishRequest};
+    use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const REPOSITORY: &str = "/org/proj/_apis/git/repositories/index";
+
+    async fn azure_devops() -> (MockServer, AzureDevOps) {
+        let server = MockServer::start().await;
+        let azure_devops = AzureDevOps::from(cargolifter_core::config::AzureDevOpsConfig {
+            host: Some(server.uri()),
+            organization: "org".into(),
+            project: "proj".into(),
+            repo: "index".into(),
+            cargolifter_token: None,
+            default_branch: None,
+            commit_mode: None,
+        });
+        Mock::given(method("GET"))
+            .and(path("/org/_apis/connectionData"))
+            // basic auth of ":secret"
+            .and(header("Authorization", "Basic OnNlY3JldA=="))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"authenticatedUser":{"id":"user-id","providerDisplayName":"Alice","properties":{"Account":{"$type":"System.String","$value":"alice@example.com"}}}}"#,
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/refs", REPOSITORY)))
+            // every branch exists, a prefix match of the default branch is listed first
+            .respond_with(|request: &wiremock::Request| {
+                let filter = request
+                    .url
+                    .query_pairs()
+                    .find(|(key, _)| key == "filter")
+                    .map(|(_, value)| value.into_owned())
+                    .unwrap_or_default();
+                ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"value":[{{"name":"refs/heads/main-old","objectId":"def"}},{{"name":"refs/heads/main","objectId":"abc"}},{{"name":"refs/{}","objectId":"456"}}],"count":3}}"#,
+                    filter
+                ))
+            })
+            .mount(&server)
+            .await;
+        (server, azure_devops)
+    }
+
+    #[test]
+    fn test_config() {
+        let azure_devops = AzureDevOps::from(cargolifter_core::config::AzureDevOpsConfig {
+            host: None,
+            organization: "org".into(),
+            project: "proj".into(),
+            repo: "index".into(),
+            cargolifter_token: Some("bot:merge".into()),
+            default_branch: None,
+            commit_mode: None,
+        });
+        assert_eq!(
+            "https://dev.azure.com/org/proj/_apis/git/repositories/index",
+            azure_devops.repository_url
+        );
+        assert_eq!(
+            (String::new(), String::from("pat")),
+            azure_devops.config("pat")
+        );
+        assert_eq!(
+            (String::from("alice"), String::from("pat")),
+            azure_devops.config("alice:pat")
+        );
+        assert_eq!(
+            (String::from("bot"), String::from("merge")),
+            azure_devops.merge_config("pat")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (server, azure_devops) = azure_devops().await;
+        // a new crate is checked against the names in the index
+        Mock::given(method("GET"))
+            .and(path(format!("{}/items", REPOSITORY)))
+            .and(query_param("recursionLevel", "full"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"value":[{"path":"/config.json","isFolder":false},{"path":"/3/b/bar","isFolder":false}]}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/items", REPOSITORY)))
+            .and(query_param("versionDescriptor.version", "abc"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pushes", REPOSITORY)))
+            .and(body_string_contains(
+                r#""refUpdates":[{"name":"refs/heads/foo-0.1.0-"#,
+            ))
+            .and(body_string_contains(r#""oldObjectId":"abc"}]"#))
+            .and(body_string_contains(
+                r#""changeType":"add","item":{"path":"/3/f/foo"}"#,
+            ))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pushes", REPOSITORY)))
+            .and(body_string_contains("alice@example.com"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pullrequests", REPOSITORY)))
+            .respond_with(ResponseTemplate::new(201).set_body_string(r#"{"pullRequestId":7}"#))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("{}/pullrequests/7", REPOSITORY)))
+            .and(body_string_contains(
+                r#""autoCompleteSetBy":{"id":"user-id"}"#,
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pullrequests/7", REPOSITORY)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"status":"completed","mergeStatus":"succeeded"}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/refs", REPOSITORY)))
+            .and(body_string_contains(NULL_OBJECT_ID))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"value":[{"name":"refs/heads/foo","success":true,"updateStatus":"succeeded"}]}"#,
+            ))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let request = PublishRequest {
+            meta: MetaData {
+                name: "foo".into(),
+                vers: "0.1.0".into(),
+                ..Default::default()
+            },
+            cksum: Default::default(),
+        };
+        let status =
+            cargolifter_core::commands::publish::execute(&azure_devops, "secret", &request)
+                .await
+                .unwrap();
+        assert!(matches!(
+            status,
+            cargolifter_core::models::PublishStatus::Published(versions) if versions.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_merge_conflict() {
+        let (server, azure_devops) = azure_devops().await;
+        Mock::given(method("PATCH"))
+            .and(path(format!("{}/pullrequests/7", REPOSITORY)))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/pullrequests/7", REPOSITORY)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"status":"active","mergeStatus":"conflicts"}"#),
+            )
+            .mount(&server)
+            .await;
+
+        assert!(matches!(
+            azure_devops.merge_pull_request("secret", 7).await,
+            Err(cargolifter_core::models::BackendError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_user() {
+        let (server, azure_devops) = azure_devops().await;
+        Mock::given(method("GET"))
+            .and(path("/org/_apis/connectionData"))
+            .respond_with(ResponseTemplate::new(203).set_body_string("<html></html>"))
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            "alice@example.com",
+            azure_devops.get_user("secret").await.unwrap()
+        );
+        assert!(matches!(
+            azure_devops.get_user("other").await,
+            Err(cargolifter_core::models::BackendError::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_commit_file() {
+        let (server, azure_devops) = azure_devops().await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pushes", REPOSITORY)))
+            .and(body_string_contains(
+                r#""refUpdates":[{"name":"refs/heads/main","oldObjectId":"abc"}]"#,
+            ))
+            .and(body_string_contains(r#""changeType":"add""#))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pushes", REPOSITORY)))
+            .and(body_string_contains(r#""oldObjectId":"old""#))
+            .respond_with(ResponseTemplate::new(409))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        azure_devops
+            .commit_file("secret", "3/f/foo", "", "message", None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            azure_devops
+                .commit_file("secret", "3/f/foo", "", "message", Some("old"))
+                .await,
+            Err(cargolifter_core::models::BackendError::Conflict(_))
+        ));
+    }
+}
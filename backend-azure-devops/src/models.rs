@@ -0,0 +1,9 @@
+pub mod create_pull_request;
+pub mod create_push;
+pub mod get_connection_data;
+pub mod get_item;
+pub mod get_items;
+pub mod get_pull_request;
+pub mod get_refs;
+pub mod update_pull_request;
+pub mod update_refs;
@@ -0,0 +1,42 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefUpdate {
+    pub name: String,
+    pub old_object_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Item {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewContent {
+    pub content: String,
+    pub content_type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// `add` for new files, `edit` for existing ones
+    pub change_type: String,
+    pub item: Item,
+    pub new_content: NewContent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Commit {
+    pub comment: String,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub ref_updates: Vec<RefUpdate>,
+    pub commits: Vec<Commit>,
+}
@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct Property {
+    #[serde(rename = "$value")]
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub id: String,
+    pub provider_display_name: String,
+    #[serde(default)]
+    pub properties: HashMap<String, Property>,
+}
+
+impl Identity {
+    /// account name (usually the mail address) of the user, which is stable unlike the display name
+    pub fn account(&self) -> String {
+        self.properties
+            .get("Account")
+            .and_then(|property| property.value.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| self.provider_display_name.clone())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub authenticated_user: Identity,
+    // omitted
+}
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub path: String,
+    #[serde(default)]
+    pub is_folder: bool,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub value: Vec<Item>,
+}
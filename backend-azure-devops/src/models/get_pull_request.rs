@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    /// `active`, `completed` or `abandoned`
+    pub status: String,
+    pub merge_status: Option<String>,
+    // omitted
+}
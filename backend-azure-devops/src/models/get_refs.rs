@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ref {
+    pub name: String,
+    pub object_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub value: Vec<Ref>,
+    // omitted
+}
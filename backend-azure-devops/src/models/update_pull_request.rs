@@ -0,0 +1,24 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct IdentityRef {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    pub merge_strategy: String,
+    pub delete_source_branch: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_complete_set_by: Option<IdentityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_options: Option<CompletionOptions>,
+}
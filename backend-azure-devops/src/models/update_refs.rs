@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefUpdate {
+    pub name: String,
+    pub old_object_id: String,
+    pub new_object_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefUpdateResult {
+    pub name: String,
+    pub success: bool,
+    pub update_status: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub value: Vec<RefUpdateResult>,
+    // omitted
+}
@@ -0,0 +1,223 @@
+use crate::models::cloud;
+use cargolifter_core::models::BackendError;
+
+fn repository_url(host: &str, workspace: &str, repo: &str) -> String {
+    format!("{}/2.0/repositories/{}/{}", host, workspace, repo)
+}
+
+pub async fn get_branch(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    branch: &str,
+) -> Result<cloud::Branch, BackendError> {
+    let url = format!(
+        "{}/refs/branches/{}",
+        repository_url(host, workspace, repo),
+        branch
+    );
+    let client = reqwest::Client::new();
+    let response = client.get(url).bearer_auth(token).send().await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn get_file(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    commit: &str,
+    file: &str,
+) -> Result<String, BackendError> {
+    let url = format!(
+        "{}/src/{}/{}",
+        repository_url(host, workspace, repo),
+        commit,
+        file
+    );
+    let client = reqwest::Client::new();
+    let response = client.get(url).bearer_auth(token).send().await?;
+    BackendError::check(response)
+        .await?
+        .text()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn commit_file(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    request: &cloud::CommitFileRequest,
+) -> Result<(), BackendError> {
+    let url = format!("{}/src", repository_url(host, workspace, repo));
+    let form = [
+        (format!("/{}", request.path), request.content.as_str()),
+        ("message".into(), request.message.as_str()),
+        ("branch".into(), request.branch.as_str()),
+        ("parents".into(), request.parents.as_str()),
+    ];
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .form(&form)
+        .send()
+        .await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+pub async fn delete_branch(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    branch: &str,
+) -> Result<(), BackendError> {
+    let url = format!(
+        "{}/refs/branches/{}",
+        repository_url(host, workspace, repo),
+        branch
+    );
+    let client = reqwest::Client::new();
+    let response = client.delete(url).bearer_auth(token).send().await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+pub async fn create_pull_request(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    request: &cloud::CreatePullRequest,
+) -> Result<cloud::PullRequest, BackendError> {
+    let url = format!("{}/pullrequests", repository_url(host, workspace, repo));
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(request)
+        .send()
+        .await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn merge_pull_request(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    id: u64,
+    request: &cloud::MergePullRequest,
+) -> Result<(), BackendError> {
+    let url = format!(
+        "{}/pullrequests/{}/merge",
+        repository_url(host, workspace, repo),
+        id
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(request)
+        .send()
+        .await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+pub async fn decline_pull_request(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    id: u64,
+) -> Result<(), BackendError> {
+    let url = format!(
+        "{}/pullrequests/{}/decline",
+        repository_url(host, workspace, repo),
+        id
+    );
+    let client = reqwest::Client::new();
+    let response = client.post(url).bearer_auth(token).send().await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+/// Lists the files at `commit` down to the depth of the crate index files.
+///
+/// That is the root and the prefix directories above the index files, owners
+/// files sit one directory deeper and are not listed. The following pages are
+/// requested with the `next` url of the last one, which has to point to `host`
+/// as the token is sent along.
+pub async fn list_files(
+    host: &str,
+    token: &str,
+    workspace: &str,
+    repo: &str,
+    commit: &str,
+    next: Option<&str>,
+) -> Result<cloud::SourcePage, BackendError> {
+    let client = reqwest::Client::new();
+    let request = match next {
+        Some(next) => client.get(same_origin(host, next)?),
+        None => {
+            let depth = cargolifter_core::get_crate_file_path("crate")
+                .matches('/')
+                .count()
+                + 1;
+            client
+                .get(format!(
+                    "{}/src/{}/",
+                    repository_url(host, workspace, repo),
+                    commit
+                ))
+                .query(&[("max_depth", depth.to_string()), ("pagelen", "100".into())])
+        }
+    };
+    let response = request.bearer_auth(token).send().await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+/// `url` if it has the origin of `host`
+fn same_origin(host: &str, url: &str) -> Result<reqwest::Url, BackendError> {
+    let parse = |url: &str| {
+        reqwest::Url::parse(url)
+            .map_err(|e| BackendError::InvalidResponse(format!("invalid url '{}': {}", url, e)))
+    };
+    let next = parse(url)?;
+    if next.origin() != parse(host)?.origin() {
+        return Err(BackendError::InvalidResponse(format!(
+            "next page '{}' is not on '{}'",
+            url, host
+        )));
+    }
+    Ok(next)
+}
+
+pub async fn get_user(host: &str, token: &str) -> Result<cloud::User, BackendError> {
+    let url = format!("{}/2.0/user", host);
+    let client = reqwest::Client::new();
+    let response = client.get(url).bearer_auth(token).send().await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
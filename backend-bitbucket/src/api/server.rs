@@ -0,0 +1,237 @@
+use crate::models::server;
+use cargolifter_core::models::BackendError;
+
+/// index files are json lines, so this never shows up in a form value
+const BOUNDARY: &str = "cargolifter-form-boundary";
+
+fn repository_url(host: &str, project: &str, repo: &str) -> String {
+    format!("{}/rest/api/1.0/projects/{}/repos/{}", host, project, repo)
+}
+
+/// the file edit api only accepts multipart forms
+fn multipart_form(fields: &[(&str, &str)]) -> String {
+    let mut body = String::new();
+    for (name, value) in fields {
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            BOUNDARY, name, value
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", BOUNDARY));
+    body
+}
+
+pub async fn get_last_commit(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    branch: &str,
+    file: &str,
+) -> Result<server::Page<server::Commit>, BackendError> {
+    let url = format!("{}/commits", repository_url(host, project, repo));
+    let until = format!("refs/heads/{}", branch);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .bearer_auth(token)
+        .query(&[("until", until.as_str()), ("path", file), ("limit", "1")])
+        .send()
+        .await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn get_file(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    commit: &str,
+    file: &str,
+) -> Result<String, BackendError> {
+    let url = format!("{}/raw/{}", repository_url(host, project, repo), file);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .bearer_auth(token)
+        .query(&[("at", commit)])
+        .send()
+        .await?;
+    BackendError::check(response)
+        .await?
+        .text()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn edit_file(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    file: &str,
+    request: &server::EditFileRequest,
+) -> Result<(), BackendError> {
+    let url = format!("{}/browse/{}", repository_url(host, project, repo), file);
+    let mut fields = vec![
+        ("branch", request.branch.as_str()),
+        ("content", request.content.as_str()),
+        ("message", request.message.as_str()),
+    ];
+    if let Some(source_branch) = &request.source_branch {
+        fields.push(("sourceBranch", source_branch));
+    }
+    if let Some(source_commit_id) = &request.source_commit_id {
+        fields.push(("sourceCommitId", source_commit_id));
+    }
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put(url)
+        .bearer_auth(token)
+        .header("X-Atlassian-Token", "no-check")
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(multipart_form(&fields))
+        .send()
+        .await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+pub async fn delete_branch(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    request: &server::DeleteBranchRequest,
+) -> Result<(), BackendError> {
+    let url = format!(
+        "{}/rest/branch-utils/1.0/projects/{}/repos/{}/branches",
+        host, project, repo
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .delete(url)
+        .bearer_auth(token)
+        .json(request)
+        .send()
+        .await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+pub async fn create_pull_request(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    request: &server::CreatePullRequest,
+) -> Result<server::PullRequest, BackendError> {
+    let url = format!("{}/pull-requests", repository_url(host, project, repo));
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(request)
+        .send()
+        .await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn get_pull_request(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    id: u64,
+) -> Result<server::PullRequest, BackendError> {
+    let url = format!(
+        "{}/pull-requests/{}",
+        repository_url(host, project, repo),
+        id
+    );
+    let client = reqwest::Client::new();
+    let response = client.get(url).bearer_auth(token).send().await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+/// `action` is either `merge` or `decline`, both require the current version of the pull request
+pub async fn complete_pull_request(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    pull_request: &server::PullRequest,
+    action: &str,
+) -> Result<(), BackendError> {
+    let url = format!(
+        "{}/pull-requests/{}/{}",
+        repository_url(host, project, repo),
+        pull_request.id,
+        action
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .header("X-Atlassian-Token", "no-check")
+        .query(&[("version", pull_request.version)])
+        .send()
+        .await?;
+    BackendError::check(response).await?;
+    Ok(())
+}
+
+/// lists the files at `commit`, starting at the given page offset
+pub async fn list_files(
+    host: &str,
+    token: &str,
+    project: &str,
+    repo: &str,
+    commit: &str,
+    start: u64,
+) -> Result<server::Page<String>, BackendError> {
+    let url = format!("{}/files", repository_url(host, project, repo));
+    let start = start.to_string();
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .bearer_auth(token)
+        .query(&[("at", commit), ("start", &start), ("limit", "1000")])
+        .send()
+        .await?;
+    BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
+
+/// Bitbucket Server has no endpoint for the current user, but names it in a header
+pub async fn get_user(host: &str, token: &str) -> Result<String, BackendError> {
+    let url = format!("{}/rest/api/1.0/application-properties", host);
+    let client = reqwest::Client::new();
+    let response = client.get(url).bearer_auth(token).send().await?;
+    let response = BackendError::check(response).await?;
+    response
+        .headers()
+        .get("X-AUSERNAME")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+        .ok_or_else(|| BackendError::Unauthorized("token does not belong to a user".into()))
+}
@@ -0,0 +1,760 @@
+mod api;
+mod models;
+
+use async_trait::async_trait;
+use cargolifter_core::Backend;
+
+pub struct Bitbucket {
+    cargolifter_token: Option<String>,
+    host: Option<String>,
+    project: String,
+    repo: String,
+    server: bool,
+    default_branch: String,
+    commit_mode: cargolifter_core::config::CommitMode,
+}
+
+impl Bitbucket {
+    pub fn from(config: cargolifter_core::config::BitbucketConfig) -> Self {
+        Self {
+            cargolifter_token: config.cargolifter_token,
+            host: config.host.clone(),
+            project: config.project,
+            repo: config.repo,
+            server: config.server.unwrap_or(false),
+            default_branch: config
+                .default_branch
+                .unwrap_or_else(|| String::from("main")),
+            commit_mode: config.commit_mode.unwrap_or_default(),
+        }
+    }
+
+    fn host(&self) -> String {
+        let default_host = String::from("https://api.bitbucket.org");
+        self.host.as_ref().unwrap_or(&default_host).into()
+    }
+
+    /// commits `content` to `crate_path` on `branch_name`, which is created from the default
+    /// branch if needed, `current_sha` is the value returned by `get_file` or none for new files
+    async fn commit_to_branch(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        if self.server {
+            let edit_request = models::server::EditFileRequest {
+                branch: branch_name.into(),
+                content: content.into(),
+                message: message.into(),
+                source_branch: (branch_name != self.default_branch)
+                    .then(|| self.default_branch.clone()),
+                source_commit_id: current_sha.map(String::from),
+            };
+            api::server::edit_file(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                crate_path,
+                &edit_request,
+            )
+            .await
+        } else {
+            let parents = match current_sha {
+                Some(sha) => sha.into(),
+                None => {
+                    let head = api::cloud::get_branch(
+                        &host,
+                        token,
+                        &self.project,
+                        &self.repo,
+                        &self.default_branch,
+                    )
+                    .await?
+                    .target
+                    .hash;
+                    // the src api would silently overwrite an existing file
+                    match api::cloud::get_file(
+                        &host,
+                        token,
+                        &self.project,
+                        &self.repo,
+                        &head,
+                        crate_path,
+                    )
+                    .await
+                    {
+                        Ok(_) => {
+                            return Err(cargolifter_core::models::BackendError::Conflict(format!(
+                                "'{}' already exists",
+                                crate_path
+                            )))
+                        }
+                        Err(cargolifter_core::models::BackendError::NotFound(_)) => head,
+                        Err(e) => return Err(e),
+                    }
+                }
+            };
+            let commit_request = models::cloud::CommitFileRequest {
+                path: crate_path.into(),
+                content: content.into(),
+                message: message.into(),
+                branch: branch_name.into(),
+                parents,
+            };
+            api::cloud::commit_file(&host, token, &self.project, &self.repo, &commit_request).await
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for Bitbucket {
+    /// the returned sha is the commit the content was read at, which is what Bitbucket
+    /// uses to detect concurrent changes
+    async fn get_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+    ) -> Result<(String, String, String), cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        let commit = if self.server {
+            let page = api::server::get_last_commit(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &self.default_branch,
+                crate_path,
+            )
+            .await?;
+            match page.values.into_iter().next() {
+                Some(commit) => commit.id,
+                None => {
+                    return Err(cargolifter_core::models::BackendError::NotFound(format!(
+                        "'{}' does not exist",
+                        crate_path
+                    )))
+                }
+            }
+        } else {
+            api::cloud::get_branch(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &self.default_branch,
+            )
+            .await?
+            .target
+            .hash
+        };
+
+        let content = if self.server {
+            api::server::get_file(&host, token, &self.project, &self.repo, &commit, crate_path)
+                .await?
+        } else {
+            api::cloud::get_file(&host, token, &self.project, &self.repo, &commit, crate_path)
+                .await?
+        };
+        Ok((content, "utf-8".into(), commit))
+    }
+
+    async fn create_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.commit_to_branch(token, crate_path, branch_name, content, message, None)
+            .await
+    }
+
+    async fn update_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.commit_to_branch(
+            token,
+            crate_path,
+            branch_name,
+            content,
+            message,
+            Some(current_sha),
+        )
+        .await
+    }
+
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        if self.server {
+            let delete_request = models::server::DeleteBranchRequest {
+                name: format!("refs/heads/{}", branch_name),
+                dry_run: false,
+            };
+            api::server::delete_branch(&host, token, &self.project, &self.repo, &delete_request)
+                .await
+        } else {
+            api::cloud::delete_branch(&host, token, &self.project, &self.repo, branch_name).await
+        }
+    }
+
+    async fn create_pull_request(
+        &self,
+        token: &str,
+        title: &str,
+        branch_name: &str,
+    ) -> Result<u64, cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        let pull_request = if self.server {
+            let create_request = models::server::CreatePullRequest {
+                title: title.into(),
+                from_ref: models::server::Ref {
+                    id: format!("refs/heads/{}", branch_name),
+                },
+                to_ref: models::server::Ref {
+                    id: format!("refs/heads/{}", self.default_branch),
+                },
+            };
+            api::server::create_pull_request(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &create_request,
+            )
+            .await?
+            .id
+        } else {
+            let create_request = models::cloud::CreatePullRequest {
+                title: title.into(),
+                source: models::cloud::PullRequestEndpoint {
+                    branch: models::cloud::BranchName {
+                        name: branch_name.into(),
+                    },
+                },
+                destination: models::cloud::PullRequestEndpoint {
+                    branch: models::cloud::BranchName {
+                        name: self.default_branch.clone(),
+                    },
+                },
+                close_source_branch: false,
+            };
+            api::cloud::create_pull_request(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &create_request,
+            )
+            .await?
+            .id
+        };
+        Ok(pull_request)
+    }
+
+    async fn merge_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let host = self.host();
+        let owned_token = token.to_owned();
+        let merge_token = self.cargolifter_token.as_ref().unwrap_or(&owned_token);
+
+        if self.server {
+            let pull_request =
+                api::server::get_pull_request(&host, merge_token, &self.project, &self.repo, id)
+                    .await?;
+            api::server::complete_pull_request(
+                &host,
+                merge_token,
+                &self.project,
+                &self.repo,
+                &pull_request,
+                "merge",
+            )
+            .await
+        } else {
+            let merge_request = models::cloud::MergePullRequest {
+                merge_strategy: "merge_commit".into(),
+                close_source_branch: false,
+            };
+            api::cloud::merge_pull_request(
+                &host,
+                merge_token,
+                &self.project,
+                &self.repo,
+                id,
+                &merge_request,
+            )
+            .await
+        }
+    }
+
+    async fn delete_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        if self.server {
+            let pull_request =
+                api::server::get_pull_request(&host, token, &self.project, &self.repo, id).await?;
+            api::server::complete_pull_request(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &pull_request,
+                "decline",
+            )
+            .await
+        } else {
+            api::cloud::decline_pull_request(&host, token, &self.project, &self.repo, id).await
+        }
+    }
+
+    /// the commit is rejected if the default branch moved past `current_sha`
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        self.commit_to_branch(
+            token,
+            crate_path,
+            &self.default_branch,
+            content,
+            message,
+            current_sha,
+        )
+        .await
+    }
+
+    async fn list_files(
+        &self,
+        token: &str,
+    ) -> Result<Vec<String>, cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        let mut files = Vec::new();
+        if self.server {
+            let at = format!("refs/heads/{}", self.default_branch);
+            let mut start = 0;
+            loop {
+                let page =
+                    api::server::list_files(&host, token, &self.project, &self.repo, &at, start)
+                        .await?;
+                files.extend(page.values);
+                match page.next_page_start {
+                    Some(next) if !page.is_last_page => start = next,
+                    _ => break,
+                }
+            }
+        } else {
+            let head = api::cloud::get_branch(
+                &host,
+                token,
+                &self.project,
+                &self.repo,
+                &self.default_branch,
+            )
+            .await?
+            .target
+            .hash;
+            let mut next = None;
+            loop {
+                let page = api::cloud::list_files(
+                    &host,
+                    token,
+                    &self.project,
+                    &self.repo,
+                    &head,
+                    next.as_deref(),
+                )
+                .await?;
+                files.extend(
+                    page.values
+                        .into_iter()
+                        .filter(|entry| entry.r#type == "commit_file")
+                        .map(|entry| entry.path),
+                );
+                match page.next {
+                    Some(url) => next = Some(url),
+                    None => break,
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    async fn get_user(
+        &self,
+        token: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        if self.server {
+            api::server::get_user(&host, token).await
+        } else {
+            Ok(api::cloud::get_user(&host, token).await?.username)
+        }
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargolifter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        None
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::{BackendError, MetaData, PublishRequest, YankRequest};
+    use wiremock::matchers::{body_string_contains, header, method, path, path_regex, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn bitbucket(server: bool) -> (MockServer, Bitbucket) {
+        let mock_server = MockServer::start().await;
+        let bitbucket = Bitbucket::from(cargolifter_core::config::BitbucketConfig {
+            host: Some(mock_server.uri()),
+            project: "ws".into(),
+            repo: "index".into(),
+            server: Some(server),
+            cargolifter_token: None,
+            default_branch: None,
+            commit_mode: None,
+        });
+        (mock_server, bitbucket)
+    }
+
+    async fn mock(server: &MockServer, verb: &str, url: &str, response: ResponseTemplate) {
+        Mock::given(method(verb))
+            .and(path(url))
+            .and(header("Authorization", "Bearer secret"))
+            .respond_with(response)
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_cloud_publish() {
+        let (server, bitbucket) = bitbucket(false).await;
+        let repository = "/2.0/repositories/ws/index";
+        mock(
+            &server,
+            "GET",
+            "/2.0/user",
+            ResponseTemplate::new(200).set_body_string(r#"{"username":"alice"}"#),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/refs/branches/main", repository)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"target":{"hash":"abc"}}"#),
+            )
+            .expect(5)
+            .mount(&server)
+            .await;
+        // a new crate is checked against the names in the index
+        Mock::given(method("GET"))
+            .and(path(format!("{}/src/abc/", repository)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"values":[{"type":"commit_file","path":"config.json"},{"type":"commit_file","path":"3/b/bar"}]}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // read before publishing and checked again before creating
+        for file in ["owners/3/f/foo", "3/f/foo"] {
+            Mock::given(method("GET"))
+                .and(path(format!("{}/src/abc/{}", repository, file)))
+                .respond_with(ResponseTemplate::new(404))
+                .expect(2)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path(format!("{}/src", repository)))
+            .and(body_string_contains("%2F3%2Ff%2Ffoo="))
+            .and(body_string_contains("branch=foo-0.1.0"))
+            .and(body_string_contains("parents=abc"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/src", repository)))
+            .and(body_string_contains("branch=foo-owners"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pullrequests", repository)))
+            .respond_with(ResponseTemplate::new(201).set_body_string(r#"{"id":7}"#))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pullrequests/7/merge", repository)))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+        for branch in ["foo-owners", "foo-0.1.0"] {
+            Mock::given(method("DELETE"))
+                .and(path_regex(format!(
+                    r"^{}/refs/branches/{}-\d+-\d+$",
+                    repository, branch
+                )))
+                .and(header("Authorization", "Bearer secret"))
+                .respond_with(ResponseTemplate::new(204))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let request = PublishRequest {
+            meta: MetaData {
+                name: "foo".into(),
+                vers: "0.1.0".into(),
+                ..Default::default()
+            },
+            cksum: Default::default(),
+        };
+        let status = cargolifter_core::commands::publish::execute(&bitbucket, "secret", &request)
+            .await
+            .unwrap();
+        assert!(matches!(
+            status,
+            cargolifter_core::models::PublishStatus::Published(versions) if versions.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cloud_list_files() {
+        let (server, bitbucket) = bitbucket(false).await;
+        let repository = "/2.0/repositories/ws/index";
+        Mock::given(method("GET"))
+            .and(path(format!("{}/refs/branches/main", repository)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"target":{"hash":"abc"}}"#),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/src/abc/", repository)))
+            .and(query_param("max_depth", "3"))
+            .and(query_param("page", "2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"values":[{"type":"commit_file","path":"3/b/bar"}]}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/src/abc/", repository)))
+            .and(query_param("max_depth", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"values":[{{"type":"commit_directory","path":"3"}},{{"type":"commit_file","path":"config.json"}}],"next":"{}{}/src/abc/?max_depth=3&page=2"}}"#,
+                server.uri(),
+                repository
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let files = bitbucket.list_files("secret").await.unwrap();
+        assert_eq!(files, vec!["config.json", "3/b/bar"]);
+
+        // the token is not sent to other hosts
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/refs/branches/main", repository)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"target":{"hash":"abc"}}"#),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/src/abc/", repository)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(
+                    r#"{"values":[],"next":"https://example.com/src/abc/?page=2"}"#,
+                ),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let result = bitbucket.list_files("secret").await;
+        assert!(matches!(result, Err(BackendError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_server_yank() {
+        let (server, bitbucket) = bitbucket(true).await;
+        let repository = "/rest/api/1.0/projects/ws/repos/index";
+        Mock::given(method("GET"))
+            .and(path(format!("{}/commits", repository)))
+            .and(query_param("until", "refs/heads/main"))
+            .and(query_param("path", "3/f/foo"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"values":[{"id":"abc"}]}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/raw/3/f/foo", repository)))
+            .and(query_param("at", "abc"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name":"foo","vers":"0.1.0","deps":[],"cksum":"00","features":{},"yanked":false,"links":null}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(format!("{}/browse/3/f/foo", repository)))
+            .and(header("X-Atlassian-Token", "no-check"))
+            .and(body_string_contains(
+                "name=\"sourceCommitId\"\r\n\r\nabc\r\n",
+            ))
+            .and(body_string_contains("name=\"branch\"\r\n\r\nfoo-0.1.0-"))
+            .and(body_string_contains(r#""yanked":true"#))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        mock(
+            &server,
+            "POST",
+            &format!("{}/pull-requests", repository),
+            ResponseTemplate::new(201).set_body_string(r#"{"id":7,"version":0}"#),
+        )
+        .await;
+        mock(
+            &server,
+            "GET",
+            &format!("{}/pull-requests/7", repository),
+            ResponseTemplate::new(200).set_body_string(r#"{"id":7,"version":2}"#),
+        )
+        .await;
+        Mock::given(method("POST"))
+            .and(path(format!("{}/pull-requests/7/merge", repository)))
+            .and(query_param("version", "2"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(
+                "/rest/branch-utils/1.0/projects/ws/repos/index/branches",
+            ))
+            .and(body_string_contains("refs/heads/foo-0.1.0"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        mock(
+            &server,
+            "GET",
+            "/rest/api/1.0/application-properties",
+            ResponseTemplate::new(200).insert_header("X-AUSERNAME", "alice"),
+        )
+        .await;
+
+        let request = YankRequest {
+            name: "foo".into(),
+            vers: "0.1.0".into(),
+            yank: true,
+        };
+        let versions = cargolifter_core::commands::yank::execute(&bitbucket, "secret", &request)
+            .await
+            .unwrap();
+        assert!(versions[0].yanked);
+    }
+
+    #[tokio::test]
+    async fn test_server_get_user() {
+        let (server, bitbucket) = bitbucket(true).await;
+        mock(
+            &server,
+            "GET",
+            "/rest/api/1.0/application-properties",
+            ResponseTemplate::new(200).insert_header("X-AUSERNAME", "alice"),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/api/1.0/application-properties"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        assert_eq!("alice", bitbucket.get_user("secret").await.unwrap());
+        assert!(matches!(
+            bitbucket.get_user("other").await,
+            Err(cargolifter_core::models::BackendError::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_commit_file() {
+        let (server, bitbucket) = bitbucket(true).await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/api/1.0/projects/ws/repos/index/browse/3/f/foo"))
+            .and(body_string_contains("name=\"branch\"\r\n\r\nmain\r\n"))
+            .and(body_string_contains(
+                "name=\"sourceCommitId\"\r\n\r\nabc\r\n",
+            ))
+            .respond_with(ResponseTemplate::new(409))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = bitbucket
+            .commit_file("secret", "3/f/foo", "", "message", Some("abc"))
+            .await;
+        assert!(matches!(
+            result,
+            Err(cargolifter_core::models::BackendError::Conflict(_))
+        ));
+        let requests = server.received_requests().await.unwrap();
+        assert!(!String::from_utf8_lossy(&requests[0].body).contains("sourceBranch"));
+    }
+}
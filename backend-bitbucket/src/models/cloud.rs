@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct Commit {
+    pub hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Branch {
+    pub target: Commit,
+}
+
+#[derive(Debug, Default)]
+pub struct CommitFileRequest {
+    pub path: String,
+    pub content: String,
+    pub message: String,
+    pub branch: String,
+    pub parents: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchName {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PullRequestEndpoint {
+    pub branch: BranchName,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatePullRequest {
+    pub title: String,
+    pub source: PullRequestEndpoint,
+    pub destination: PullRequestEndpoint,
+    pub close_source_branch: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub id: u64,
+    // omitted
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergePullRequest {
+    pub merge_strategy: String,
+    pub close_source_branch: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub username: String,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SourceEntry {
+    pub r#type: String,
+    pub path: String,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SourcePage {
+    pub values: Vec<SourceEntry>,
+    /// url of the next page
+    pub next: Option<String>,
+}
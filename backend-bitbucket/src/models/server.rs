@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct Commit {
+    pub id: String,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub values: Vec<T>,
+    #[serde(default = "last_page")]
+    pub is_last_page: bool,
+    pub next_page_start: Option<u64>,
+    // omitted
+}
+
+fn last_page() -> bool {
+    true
+}
+
+#[derive(Debug, Default)]
+pub struct EditFileRequest {
+    pub branch: String,
+    pub content: String,
+    pub message: String,
+    /// branch the new branch is created from
+    pub source_branch: Option<String>,
+    /// last commit that changed the file, none for new files
+    pub source_commit_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteBranchRequest {
+    pub name: String,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ref {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePullRequest {
+    pub title: String,
+    pub from_ref: Ref,
+    pub to_ref: Ref,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub id: u64,
+    pub version: i32,
+    // omitted
+}
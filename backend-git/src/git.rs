@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use cargolifter_core::models::BackendError;
+
+/// runs git in `work_dir` and returns what it printed to stdout
+pub async fn run(work_dir: &Path, args: &[&str]) -> Result<String, BackendError> {
+    let output = tokio::process::Command::new("git")
+        .current_dir(work_dir)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .map_err(|e| BackendError::Git(format!("failed to run git: {}", e)))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if output.status.success() {
+        Ok(stdout)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(map_error(args, &format!("{}{}", stdout, stderr)))
+    }
+}
+
+fn map_error(args: &[&str], output: &str) -> BackendError {
+    let detail = format!("git {}: {}", args.join(" "), output.trim());
+    if output.contains("Permission denied") || output.contains("Authentication failed") {
+        BackendError::Forbidden(detail)
+    } else if output.contains("CONFLICT")
+        || output.contains("[rejected]")
+        || output.contains("non-fast-forward")
+    {
+        BackendError::Conflict(detail)
+    } else if output.contains("not found") || output.contains("does not exist") {
+        BackendError::NotFound(detail)
+    } else {
+        BackendError::Git(detail)
+    }
+}
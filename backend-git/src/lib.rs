@@ -0,0 +1,864 @@
+mod git;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use cargolifter_core::models::BackendError;
+use cargolifter_core::Backend;
+
+const COMMITTER: [&str; 4] = [
+    "-c",
+    "user.name=cargolifter",
+    "-c",
+    "user.email=cargolifter@localhost",
+];
+
+/// Backend writing the index to a plain git repository.
+///
+/// Changes are committed on branches of a local clone. Pull requests only
+/// exist in memory and merging one merges its branch into the default branch,
+/// which is then pushed.
+pub struct Git {
+    remote: String,
+    work_dir: PathBuf,
+    users: HashMap<String, String>,
+    cargolifter_token: Option<String>,
+    default_branch: String,
+    push_branches: bool,
+    commit_mode: cargolifter_core::config::CommitMode,
+    lock: tokio::sync::Mutex<()>,
+    pull_requests: std::sync::Mutex<HashMap<u64, String>>,
+    next_pull_request: AtomicU64,
+}
+
+impl Git {
+    pub fn from(config: cargolifter_core::config::GitConfig) -> Self {
+        Self {
+            remote: config.remote,
+            work_dir: PathBuf::from(config.work_dir),
+            users: config.users,
+            cargolifter_token: config.cargolifter_token,
+            default_branch: config
+                .default_branch
+                .unwrap_or_else(|| String::from("main")),
+            push_branches: config.push_branches.unwrap_or(false),
+            commit_mode: config.commit_mode.unwrap_or_default(),
+            lock: tokio::sync::Mutex::new(()),
+            pull_requests: std::sync::Mutex::new(HashMap::new()),
+            next_pull_request: AtomicU64::new(1),
+        }
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<String, BackendError> {
+        git::run(&self.work_dir, args).await
+    }
+
+    fn origin(&self) -> String {
+        format!("origin/{}", self.default_branch)
+    }
+
+    fn user(&self, token: &str) -> Result<&str, BackendError> {
+        self.users
+            .get(token)
+            .map(String::as_str)
+            .ok_or_else(|| BackendError::Unauthorized("unknown token".into()))
+    }
+
+    /// clones the remote on first use and fetches its current state
+    async fn fetch(&self) -> Result<(), BackendError> {
+        if !self.work_dir.join(".git").exists() {
+            tracing::info!("cloning '{}' to '{}'", self.remote, self.work_dir.display());
+            std::fs::create_dir_all(&self.work_dir)
+                .map_err(|e| BackendError::Git(format!("failed to create work dir: {}", e)))?;
+            self.git(&["clone", "-q", &self.remote, "."]).await?;
+        }
+        self.git(&["fetch", "-q", "--prune", "origin"]).await?;
+        Ok(())
+    }
+
+    async fn blob_sha(&self, crate_path: &str) -> Result<String, BackendError> {
+        let object = format!("{}:{}", self.origin(), crate_path);
+        match self.git(&["rev-parse", "--verify", "-q", &object]).await {
+            Ok(sha) => Ok(sha.trim().into()),
+            Err(_) => Err(BackendError::NotFound(format!(
+                "'{}' does not exist",
+                crate_path
+            ))),
+        }
+    }
+
+    /// fails with a conflict if the file changed since `current_sha` was read or,
+    /// if none, the file already exists
+    async fn check_sha(
+        &self,
+        crate_path: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), BackendError> {
+        match (self.blob_sha(crate_path).await, current_sha) {
+            (Ok(_), None) => Err(BackendError::Conflict(format!(
+                "'{}' already exists",
+                crate_path
+            ))),
+            (Ok(sha), Some(current_sha)) if sha != current_sha => Err(BackendError::Conflict(
+                format!("'{}' changed since it was read", crate_path),
+            )),
+            (Err(e), Some(_)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// commits on `branch_name` based on the default branch, commits to the default branch
+    /// itself are pushed right away and rejected if someone else pushed in between
+    async fn commit_on_branch(
+        &self,
+        user: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<(), BackendError> {
+        self.git(&["checkout", "-q", "-B", branch_name, &self.origin()])
+            .await?;
+
+        let file_path = self.work_dir.join(crate_path);
+        write_file(&file_path, content)
+            .map_err(|e| BackendError::Git(format!("failed to write '{}': {}", crate_path, e)))?;
+        self.git(&["add", "--", crate_path]).await?;
+
+        let author = format!("{} <{}@cargolifter>", user, user);
+        let mut args = COMMITTER.to_vec();
+        args.extend(["commit", "-q", "--author", &author, "-m", message]);
+        self.git(&args).await?;
+
+        if branch_name == self.default_branch {
+            self.git(&["push", "-q", "origin", branch_name]).await?;
+        } else if self.push_branches {
+            self.git(&["push", "-q", "-f", "origin", branch_name])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for Git {
+    async fn get_file(
+        &self,
+        _token: &str,
+        crate_path: &str,
+    ) -> Result<(String, String, String), BackendError> {
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        let sha = self.blob_sha(crate_path).await?;
+        let content = self.git(&["cat-file", "blob", &sha]).await?;
+        Ok((content, "utf-8".into(), sha))
+    }
+
+    async fn create_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<(), BackendError> {
+        let user = self.user(token)?;
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        self.check_sha(crate_path, None).await?;
+        self.commit_on_branch(user, crate_path, branch_name, content, message)
+            .await
+    }
+
+    async fn update_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: &str,
+    ) -> Result<(), BackendError> {
+        let user = self.user(token)?;
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        self.check_sha(crate_path, Some(current_sha)).await?;
+        self.commit_on_branch(user, crate_path, branch_name, content, message)
+            .await
+    }
+
+    async fn delete_branch(&self, _token: &str, branch_name: &str) -> Result<(), BackendError> {
+        let _lock = self.lock.lock().await;
+
+        self.git(&["checkout", "-q", "--detach"]).await?;
+        self.git(&["branch", "-q", "-D", branch_name]).await?;
+        if self.push_branches {
+            self.git(&["push", "-q", "origin", "--delete", branch_name])
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn create_pull_request(
+        &self,
+        _token: &str,
+        title: &str,
+        branch_name: &str,
+    ) -> Result<u64, BackendError> {
+        let _lock = self.lock.lock().await;
+
+        let branch_ref = format!("refs/heads/{}", branch_name);
+        if self
+            .git(&["rev-parse", "--verify", "-q", &branch_ref])
+            .await
+            .is_err()
+        {
+            return Err(BackendError::NotFound(format!(
+                "branch '{}' does not exist",
+                branch_name
+            )));
+        }
+
+        let id = self.next_pull_request.fetch_add(1, Ordering::SeqCst);
+        tracing::info!("pull request {} '{}' for '{}'", id, title, branch_name);
+        self.pull_requests
+            .lock()
+            .unwrap()
+            .insert(id, branch_name.into());
+        Ok(id)
+    }
+
+    async fn merge_pull_request(&self, _token: &str, id: u64) -> Result<(), BackendError> {
+        let branch_name = match self.pull_requests.lock().unwrap().get(&id) {
+            Some(branch_name) => branch_name.clone(),
+            None => {
+                return Err(BackendError::NotFound(format!(
+                    "pull request {} does not exist",
+                    id
+                )))
+            }
+        };
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        self.git(&["checkout", "-q", "-B", &self.default_branch, &self.origin()])
+            .await?;
+        let message = format!("Merge branch '{}'", branch_name);
+        let mut args = COMMITTER.to_vec();
+        args.extend(["merge", "-q", "--no-ff", "-m", &message, &branch_name]);
+        if let Err(e) = self.git(&args).await {
+            let _ = self.git(&["merge", "--abort"]).await;
+            return Err(e);
+        }
+        self.git(&["push", "-q", "origin", &self.default_branch])
+            .await?;
+
+        self.pull_requests.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn delete_pull_request(&self, _token: &str, id: u64) -> Result<(), BackendError> {
+        self.pull_requests.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), BackendError> {
+        let user = self.user(token)?;
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        self.check_sha(crate_path, current_sha).await?;
+        self.commit_on_branch(user, crate_path, &self.default_branch, content, message)
+            .await
+    }
+
+    async fn list_files(&self, _token: &str) -> Result<Vec<String>, BackendError> {
+        let _lock = self.lock.lock().await;
+        self.fetch().await?;
+
+        let files = self
+            .git(&["ls-tree", "-r", "--name-only", &self.origin()])
+            .await?;
+        Ok(files.lines().map(String::from).collect())
+    }
+
+    async fn get_user(&self, token: &str) -> Result<String, BackendError> {
+        self.user(token).map(String::from)
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargolifter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        None
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+fn write_file(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::commands::utils::{open_pull_request, write_branch};
+    use cargolifter_core::config::CommitMode;
+    use cargolifter_core::journal::Journal;
+    use cargolifter_core::models::{MetaData, PublishRequest, YankRequest};
+
+    fn sh(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .current_dir(dir)
+            .args(COMMITTER)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn registry(root: &Path, commit_mode: Option<CommitMode>) -> Git {
+        let remote = root.join("index.git");
+        let seed = root.join("seed");
+        std::fs::create_dir_all(&remote).unwrap();
+        std::fs::create_dir_all(&seed).unwrap();
+        sh(&remote, &["init", "-q", "--bare", "-b", "main"]);
+        sh(&seed, &["init", "-q", "-b", "main"]);
+        std::fs::write(seed.join("config.json"), "{}").unwrap();
+        sh(&seed, &["add", "config.json"]);
+        sh(&seed, &["commit", "-q", "-m", "init"]);
+        sh(&seed, &["push", "-q", remote.to_str().unwrap(), "main"]);
+
+        Git::from(cargolifter_core::config::GitConfig {
+            remote: remote.to_str().unwrap().into(),
+            work_dir: root.join("work").to_str().unwrap().into(),
+            users: HashMap::from([
+                ("secret".into(), "alice".into()),
+                ("bob-secret".into(), "bob".into()),
+            ]),
+            cargolifter_token: None,
+            default_branch: None,
+            push_branches: None,
+            commit_mode,
+        })
+    }
+
+    fn publish_request(vers: &str) -> PublishRequest {
+        PublishRequest {
+            meta: MetaData {
+                name: "foo".into(),
+                vers: vers.into(),
+                ..Default::default()
+            },
+            cksum: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_yank() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+        let remote = root.path().join("index.git");
+
+        let status =
+            cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+                .await
+                .unwrap();
+        assert!(matches!(
+            status,
+            cargolifter_core::models::PublishStatus::Published(versions) if versions.len() == 1
+        ));
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.2.0"))
+            .await
+            .unwrap();
+
+        let versions = cargolifter_core::commands::yank::execute(
+            &git,
+            "secret",
+            &YankRequest {
+                name: "foo".into(),
+                vers: "0.1.0".into(),
+                yank: true,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(versions[0].yanked);
+        assert!(!versions[1].yanked);
+
+        let index = sh(&remote, &["show", "main:3/f/foo"]);
+        assert_eq!(index.lines().count(), 2);
+        assert!(index.contains(r#""yanked":true"#));
+        let author = sh(&remote, &["log", "-1", "--format=%an", "main^2"]);
+        assert_eq!(author.trim(), "alice");
+        assert!(sh(&remote, &["branch", "--list", "foo-*"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_republish() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+        let remote = root.path().join("index.git");
+
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+            .await
+            .unwrap();
+        let head = sh(&remote, &["rev-parse", "main"]);
+
+        // identical contents are accepted without touching the index
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+            .await
+            .unwrap();
+        assert_eq!(sh(&remote, &["rev-parse", "main"]), head);
+
+        // only owners get to retry
+        let result = cargolifter_core::commands::publish::execute(
+            &git,
+            "bob-secret",
+            &publish_request("0.1.0"),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(cargolifter_core::models::RegistryError::NotAnOwner(_, _))
+        ));
+
+        let mut request = publish_request("0.1.0");
+        request.cksum = "changed".into();
+        let result = cargolifter_core::commands::publish::execute(&git, "secret", &request).await;
+        assert!(matches!(
+            result,
+            Err(cargolifter_core::models::RegistryError::VersionExists(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_owners() {
+        use cargolifter_core::commands::owners;
+        use cargolifter_core::models::RegistryError;
+
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), Some(CommitMode::Direct));
+
+        // unpublished crates cannot be claimed
+        let result = owners::add(&git, "bob-secret", "foo", &["bob".into()]).await;
+        assert!(matches!(result, Err(RegistryError::NotFound(_))));
+
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+            .await
+            .unwrap();
+        assert_eq!(
+            owners::list(&git, "secret", "foo").await.unwrap().users,
+            vec!["alice"]
+        );
+        let result = owners::add(&git, "bob-secret", "foo", &["bob".into()]).await;
+        assert!(matches!(result, Err(RegistryError::NotAnOwner(_, _))));
+        let result = cargolifter_core::commands::publish::execute(
+            &git,
+            "bob-secret",
+            &publish_request("0.2.0"),
+        )
+        .await;
+        assert!(matches!(result, Err(RegistryError::NotAnOwner(_, _))));
+
+        owners::add(&git, "secret", "foo", &["bob".into()])
+            .await
+            .unwrap();
+        owners::remove(&git, "bob-secret", "foo", &["alice".into()])
+            .await
+            .unwrap();
+        assert_eq!(
+            owners::list(&git, "secret", "foo").await.unwrap().users,
+            vec!["bob"]
+        );
+        let result = owners::remove(&git, "bob-secret", "foo", &["bob".into()]).await;
+        assert!(matches!(result, Err(RegistryError::LastOwner(_))));
+    }
+
+    /// registry where `owner` is added right after the owners file is first read
+    struct ConcurrentOwners<'a> {
+        git: &'a Git,
+        owner: &'static str,
+        interfered: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl<'a> Backend for ConcurrentOwners<'a> {
+        async fn get_file(
+            &self,
+            token: &str,
+            crate_path: &str,
+        ) -> Result<(String, String, String), BackendError> {
+            let file = self.git.get_file(token, crate_path).await?;
+            if crate_path == cargolifter_core::get_owners_file_path("foo")
+                && !self.interfered.swap(true, Ordering::Relaxed)
+            {
+                cargolifter_core::commands::owners::add(
+                    self.git,
+                    token,
+                    "foo",
+                    &[self.owner.into()],
+                )
+                .await
+                .unwrap();
+            }
+            Ok(file)
+        }
+
+        async fn create_file(
+            &self,
+            token: &str,
+            crate_path: &str,
+            branch_name: &str,
+            content: &str,
+            message: &str,
+        ) -> Result<(), BackendError> {
+            self.git
+                .create_file(token, crate_path, branch_name, content, message)
+                .await
+        }
+
+        async fn update_file(
+            &self,
+            token: &str,
+            crate_path: &str,
+            branch_name: &str,
+            content: &str,
+            message: &str,
+            current_sha: &str,
+        ) -> Result<(), BackendError> {
+            self.git
+                .update_file(
+                    token,
+                    crate_path,
+                    branch_name,
+                    content,
+                    message,
+                    current_sha,
+                )
+                .await
+        }
+
+        async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), BackendError> {
+            self.git.delete_branch(token, branch_name).await
+        }
+
+        async fn create_pull_request(
+            &self,
+            token: &str,
+            title: &str,
+            branch_name: &str,
+        ) -> Result<u64, BackendError> {
+            self.git
+                .create_pull_request(token, title, branch_name)
+                .await
+        }
+
+        async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), BackendError> {
+            self.git.merge_pull_request(token, id).await
+        }
+
+        async fn delete_pull_request(&self, token: &str, id: u64) -> Result<(), BackendError> {
+            self.git.delete_pull_request(token, id).await
+        }
+
+        async fn commit_file(
+            &self,
+            token: &str,
+            crate_path: &str,
+            content: &str,
+            message: &str,
+            current_sha: Option<&str>,
+        ) -> Result<(), BackendError> {
+            self.git
+                .commit_file(token, crate_path, content, message, current_sha)
+                .await
+        }
+
+        fn commit_mode(&self) -> CommitMode {
+            self.git.commit_mode()
+        }
+
+        async fn list_files(&self, token: &str) -> Result<Vec<String>, BackendError> {
+            self.git.list_files(token).await
+        }
+
+        async fn get_user(&self, token: &str) -> Result<String, BackendError> {
+            self.git.get_user(token).await
+        }
+
+        fn cargolifter_token(&self) -> Option<String> {
+            self.git.cargolifter_token()
+        }
+
+        fn webhook_repository(&self) -> Option<String> {
+            self.git.webhook_repository()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_owner_changes() {
+        use cargolifter_core::commands::owners;
+
+        for commit_mode in [CommitMode::Direct, CommitMode::PullRequest] {
+            let root = tempfile::tempdir().unwrap();
+            let git = registry(root.path(), Some(commit_mode));
+            cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+                .await
+                .unwrap();
+            owners::add(&git, "secret", "foo", &["bob".into()])
+                .await
+                .unwrap();
+
+            // adding carol makes the first attempt conflict, the retry keeps the change
+            let concurrent = ConcurrentOwners {
+                git: &git,
+                owner: "carol",
+                interfered: Default::default(),
+            };
+            owners::add(&concurrent, "secret", "foo", &["dave".into()])
+                .await
+                .unwrap();
+            assert_eq!(
+                owners::list(&git, "secret", "foo").await.unwrap().users,
+                vec!["alice", "bob", "carol", "dave"]
+            );
+
+            let concurrent = ConcurrentOwners {
+                git: &git,
+                owner: "erin",
+                interfered: Default::default(),
+            };
+            owners::remove(&concurrent, "secret", "foo", &["bob".into()])
+                .await
+                .unwrap();
+            assert_eq!(
+                owners::list(&git, "secret", "foo").await.unwrap().users,
+                vec!["alice", "carol", "dave", "erin"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_owners_without_index_file() {
+        use cargolifter_core::commands::owners;
+        use cargolifter_core::models::Owners;
+
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), Some(CommitMode::Direct));
+
+        // left behind by a publish whose index update failed
+        let stale = Owners {
+            users: vec!["alice".into()],
+        };
+        owners::write(&git, "secret", "foo", &stale, None)
+            .await
+            .unwrap();
+
+        cargolifter_core::commands::publish::execute(&git, "bob-secret", &publish_request("0.1.0"))
+            .await
+            .unwrap();
+        assert_eq!(
+            owners::list(&git, "secret", "foo").await.unwrap().users,
+            vec!["bob"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_similar_name() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+
+        let mut request = publish_request("0.1.0");
+        request.meta.name = "foo-bar".into();
+        cargolifter_core::commands::publish::execute(&git, "secret", &request)
+            .await
+            .unwrap();
+
+        // checked against the index, not only against the catalog
+        request.meta.name = "Foo_Bar".into();
+        let result = cargolifter_core::commands::publish::execute(&git, "secret", &request).await;
+        assert!(matches!(
+            result,
+            Err(cargolifter_core::models::RegistryError::NameTaken(_, existing)) if existing == "foo-bar"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_catalog_from_index() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+        for name in ["foo", "foobar"] {
+            let mut request = publish_request("0.1.0");
+            request.meta.name = name.into();
+            cargolifter_core::commands::publish::execute(&git, "secret", &request)
+                .await
+                .unwrap();
+        }
+
+        // owners files and config.json are no crates
+        let crates = cargolifter_core::commands::list::execute(&git, "secret")
+            .await
+            .unwrap();
+        let mut catalog = cargolifter_core::catalog::Catalog::new();
+        catalog.rebuild(&crates);
+        let response = catalog.search(&cargolifter_core::models::SearchRequest {
+            q: "foo".into(),
+            per_page: None,
+        });
+        let names = response
+            .crates
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["foo", "foobar"], names);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_token() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+
+        let result =
+            cargolifter_core::commands::publish::execute(&git, "other", &publish_request("0.1.0"))
+                .await;
+        assert!(result.is_err());
+        assert!(matches!(
+            git.get_user("other").await,
+            Err(BackendError::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stale_update_conflicts() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), None);
+
+        let (_, _, sha) = git.get_file("secret", "config.json").await.unwrap();
+        git.update_file("secret", "config.json", "a", "{\"a\":1}", "a", &sha)
+            .await
+            .unwrap();
+        let id = git.create_pull_request("secret", "a", "a").await.unwrap();
+        git.merge_pull_request("secret", id).await.unwrap();
+
+        let result = git
+            .update_file("secret", "config.json", "b", "{\"b\":1}", "b", &sha)
+            .await;
+        assert!(matches!(result, Err(BackendError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_direct_commit() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), Some(CommitMode::Direct));
+        let remote = root.path().join("index.git");
+
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+            .await
+            .unwrap();
+        cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.2.0"))
+            .await
+            .unwrap();
+
+        let index = sh(&remote, &["show", "main:3/f/foo"]);
+        assert_eq!(index.lines().count(), 2);
+        assert!(sh(&remote, &["log", "--merges", "--oneline", "main"]).is_empty());
+        assert_eq!(
+            sh(
+                &remote,
+                &["for-each-ref", "--format=%(refname:short)", "refs/heads"]
+            )
+            .trim(),
+            "main"
+        );
+
+        let (_, _, sha) = git.get_file("secret", "3/f/foo").await.unwrap();
+        git.commit_file("secret", "3/f/foo", "", "a", Some(&sha))
+            .await
+            .unwrap();
+        assert!(matches!(
+            git.commit_file("secret", "3/f/foo", "", "b", Some(&sha))
+                .await,
+            Err(BackendError::Conflict(_))
+        ));
+        assert!(matches!(
+            git.commit_file("secret", "config.json", "", "c", None)
+                .await,
+            Err(BackendError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_review_keeps_pull_request_open() {
+        let root = tempfile::tempdir().unwrap();
+        let git = registry(root.path(), Some(CommitMode::Review));
+        let remote = root.path().join("index.git");
+
+        let status =
+            cargolifter_core::commands::publish::execute(&git, "secret", &publish_request("0.1.0"))
+                .await
+                .unwrap();
+        match status {
+            cargolifter_core::models::PublishStatus::Pending(pending) => {
+                assert!(pending.branch.starts_with("foo-0.1.0-"));
+                assert_eq!(pending.meta.vers, "0.1.0");
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+        assert!(git.get_file("secret", "3/f/foo").await.is_err());
+        assert!(!sh(&remote, &["show", "main:owners/3/f/foo"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_after_crash() {
+        let root = tempfile::tempdir().unwrap();
+        let mut git = registry(root.path(), None);
+        git.cargolifter_token = Some("secret".into());
+        let path = root.path().join("journal.json");
+        let path = path.to_str().unwrap();
+
+        // the process dies while two versions wait for their pull requests
+        let journal = Journal::open(path);
+        for branch in ["foo-0.1.0-1", "foo-0.2.0-2"] {
+            let operation = journal.begin(branch).await;
+            let backend = journal.backend(&git, operation);
+            write_branch(&backend, "secret", "3/f/foo", branch, "{}", "add", None)
+                .await
+                .unwrap();
+            open_pull_request(&backend, "secret", branch).await.unwrap();
+        }
+        drop(journal);
+
+        // the second version was handed over for review
+        let journal = Journal::open(path);
+        cargolifter_core::journal::rollback(&journal, &git, |branch| branch == "foo-0.2.0-2").await;
+        assert_eq!(
+            sh(
+                &root.path().join("work"),
+                &["for-each-ref", "--format=%(refname:short)", "refs/heads"]
+            ),
+            "foo-0.2.0-2\nmain\n"
+        );
+        assert!(Journal::open(path).take_unfinished(|_| true).is_empty());
+    }
+}
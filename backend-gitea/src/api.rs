@@ -3,6 +3,8 @@ pub mod create_file;
 pub mod create_merge_request;
 pub mod delete_branch;
 pub mod get_file;
+pub mod get_tree;
+pub mod get_user;
 pub mod update_file;
 
 pub use accept_merge_request::accept_merge_request;
@@ -10,4 +12,6 @@ pub use create_file::create_file;
 pub use create_merge_request::create_merge_request;
 pub use delete_branch::delete_branch;
 pub use get_file::get_file;
+pub use get_tree::get_tree;
+pub use get_user::get_user;
 pub use update_file::update_file;
@@ -4,19 +4,19 @@ pub async fn accept_merge_request(
     project_id: &str,
     merge_request_iid: u64,
     request: &crate::models::accept_merge_request::Request,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), cargolifter_core::models::BackendError> {
     let url = format!(
         "{}/api/v1/repos/{}/pulls/{}/merge",
         host, project_id, merge_request_iid
     );
     let client = reqwest::Client::new();
-    client
+    let response = client
         .post(url)
         .header("Authorization", format!("token {}", token))
         .header("user-agent", "cargolifter")
         .json(request)
         .send()
-        .await?
-        .error_for_status()?;
+        .await?;
+    cargolifter_core::models::BackendError::check(response).await?;
     Ok(())
 }
@@ -3,17 +3,19 @@ pub async fn create_merge_request(
     token: &str,
     project_id: &str,
     request: &crate::models::create_merge_request::Request,
-) -> Result<crate::models::create_merge_request::Response, reqwest::Error> {
+) -> Result<crate::models::create_merge_request::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/api/v1/repos/{}/pulls", host, project_id);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .post(url)
         .header("Authorization", format!("token {}", token))
         .header("user-agent", "cargolifter")
         .json(request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
@@ -5,15 +5,15 @@ pub async fn delete_branch(
     token: &str,
     project_id: &str,
     branch: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), cargolifter_core::models::BackendError> {
     let url = format!("{}/api/v1/repos/{}/branches/{}", host, project_id, branch);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .delete(url)
         .header("Authorization", format!("token {}", token))
         .header("user-agent", "cargolifter")
         .send()
-        .await?
-        .error_for_status()?;
+        .await?;
+    cargolifter_core::models::BackendError::check(response).await?;
     Ok(())
 }
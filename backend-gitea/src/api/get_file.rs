@@ -4,17 +4,19 @@ pub async fn get_file(
     project_id: &str,
     file: &str,
     branch: &str,
-) -> Result<crate::models::get_file::Response, reqwest::Error> {
+) -> Result<crate::models::get_file::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/api/v1/repos/{}/contents/{}", host, project_id, file);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .get(url)
         .header("Authorization", format!("token {}", token))
         .header("user-agent", "cargolifter")
         .query(&[("ref", branch)])
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
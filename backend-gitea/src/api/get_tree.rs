@@ -0,0 +1,22 @@
+pub async fn get_tree(
+    host: &str,
+    token: &str,
+    project_id: &str,
+    branch: &str,
+    page: usize,
+) -> Result<crate::models::get_tree::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/api/v1/repos/{}/git/trees/{}", host, project_id, branch);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .header("Authorization", format!("token {}", token))
+        .header("user-agent", "cargolifter")
+        .query(&[("recursive", "true"), ("page", &page.to_string())])
+        .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
@@ -0,0 +1,18 @@
+pub async fn get_user(
+    host: &str,
+    token: &str,
+) -> Result<crate::models::get_user::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/api/v1/user", host);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .header("Authorization", format!("token {}", token))
+        .header("user-agent", "cargolifter")
+        .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
@@ -2,7 +2,6 @@ mod api;
 mod models;
 
 use async_trait::async_trait;
-use cargolifter_core::models::PublishedVersion;
 use cargolifter_core::Backend;
 
 pub struct Gitea {
@@ -10,6 +9,7 @@ pub struct Gitea {
     project_id: String,
     host: String,
     default_branch: String,
+    commit_mode: cargolifter_core::config::CommitMode,
 }
 
 impl Gitea {
@@ -21,6 +21,7 @@ impl Gitea {
             default_branch: config
                 .default_branch
                 .unwrap_or_else(|| String::from("main")),
+            commit_mode: config.commit_mode.unwrap_or_default(),
         }
     }
 }
@@ -31,7 +32,7 @@ impl Backend for Gitea {
         &self,
         token: &str,
         crate_path: &str,
-    ) -> Result<(String, String, String), reqwest::Error> {
+    ) -> Result<(String, String, String), cargolifter_core::models::BackendError> {
         match api::get_file(
             &self.host,
             token,
@@ -51,18 +52,14 @@ impl Backend for Gitea {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        initial_version: &PublishedVersion,
-    ) -> Result<(), reqwest::Error> {
-        let json = serde_json::to_string(&initial_version).unwrap();
-        let encoded_content = base64::encode(json);
+        content: &str,
+        message: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let create_request = crate::models::create_file::Request {
             branch: Some(self.default_branch.clone()),
             new_branch: Some(branch_name.into()),
-            content: encoded_content,
-            message: Some(format!(
-                "Adding {} {}",
-                initial_version.name, initial_version.vers
-            )),
+            content: base64::encode(content),
+            message: Some(message.into()),
         };
 
         match api::create_file(
@@ -84,20 +81,15 @@ impl Backend for Gitea {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        versions: &[PublishedVersion],
+        content: &str,
+        message: &str,
         current_sha: &str,
-    ) -> Result<(), reqwest::Error> {
-        let new_content = versions
-            .iter()
-            .map(|v| serde_json::to_string(v).unwrap())
-            .collect::<Vec<String>>()
-            .join("\n");
-
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let update_request = crate::models::update_file::Request {
             new_branch: Some(branch_name.into()),
             branch: Some(self.default_branch.clone()),
-            content: base64::encode(new_content),
-            message: Some(format!("Adding {} {}", versions[0].name, versions[0].vers)),
+            content: base64::encode(content),
+            message: Some(message.into()),
             sha: current_sha.into(),
         };
         match api::update_file(
@@ -114,7 +106,11 @@ impl Backend for Gitea {
         }
     }
 
-    async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), reqwest::Error> {
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         match api::delete_branch(&self.host, token, &self.project_id, branch_name).await {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
@@ -126,7 +122,7 @@ impl Backend for Gitea {
         token: &str,
         title: &str,
         branch_name: &str,
-    ) -> Result<u64, reqwest::Error> {
+    ) -> Result<u64, cargolifter_core::models::BackendError> {
         let merge_request = models::create_merge_request::Request {
             title: title.into(),
             head: branch_name.into(),
@@ -138,7 +134,11 @@ impl Backend for Gitea {
         }
     }
 
-    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error> {
+    async fn merge_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let owned_token = token.to_owned();
         let merge_token = self.cargolifter_token.as_ref().unwrap_or(&owned_token);
 
@@ -157,11 +157,192 @@ impl Backend for Gitea {
         .await
         {
             Ok(_) => Ok(()),
+            // Gitea rejects merging a pull request that conflicts with the default branch
+            Err(cargolifter_core::models::BackendError::UnexpectedStatus(405, detail)) => {
+                Err(cargolifter_core::models::BackendError::Conflict(detail))
+            }
             Err(e) => Err(e),
         }
     }
 
-    async fn delete_pull_request(&self, _token: &str, _id: u64) -> Result<(), reqwest::Error> {
+    async fn delete_pull_request(
+        &self,
+        _token: &str,
+        _id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         Ok(())
     }
+
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        match current_sha {
+            Some(sha) => {
+                let update_request = crate::models::update_file::Request {
+                    branch: Some(self.default_branch.clone()),
+                    content: base64::encode(content),
+                    message: Some(message.into()),
+                    sha: sha.into(),
+                    ..Default::default()
+                };
+                api::update_file(
+                    &self.host,
+                    token,
+                    &self.project_id,
+                    crate_path,
+                    &update_request,
+                )
+                .await
+                .map(|_| ())
+            }
+            None => {
+                let create_request = crate::models::create_file::Request {
+                    branch: Some(self.default_branch.clone()),
+                    content: base64::encode(content),
+                    message: Some(message.into()),
+                    ..Default::default()
+                };
+                api::create_file(
+                    &self.host,
+                    token,
+                    &self.project_id,
+                    crate_path,
+                    &create_request,
+                )
+                .await
+                .map(|_| ())
+            }
+        }
+    }
+
+    async fn list_files(
+        &self,
+        token: &str,
+    ) -> Result<Vec<String>, cargolifter_core::models::BackendError> {
+        let mut files = Vec::new();
+        for page in 1.. {
+            let tree = api::get_tree(
+                &self.host,
+                token,
+                &self.project_id,
+                &self.default_branch,
+                page,
+            )
+            .await?;
+            files.extend(
+                tree.tree
+                    .into_iter()
+                    .filter(|entry| entry.r#type == "blob")
+                    .map(|entry| entry.path),
+            );
+            if !tree.truncated {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    async fn get_user(
+        &self,
+        token: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        match api::get_user(&self.host, token).await {
+            Ok(response) => Ok(response.login),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargolifter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        Some(self.project_id.clone())
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::BackendError;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const FILE: &str = "/api/v1/repos/owner/index/contents/3/f/foo";
+
+    async fn gitea() -> (MockServer, Gitea) {
+        let mock_server = MockServer::start().await;
+        let gitea = Gitea::from(cargolifter_core::config::GiteaConfig {
+            host: mock_server.uri(),
+            owner: "owner".into(),
+            repo: "index".into(),
+            cargolifter_token: None,
+            default_branch: None,
+            commit_mode: Some(cargolifter_core::config::CommitMode::Direct),
+        });
+        (mock_server, gitea)
+    }
+
+    fn message(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(serde_json::json!({ "message": message }))
+    }
+
+    #[tokio::test]
+    async fn test_commit_file() {
+        let (server, gitea) = gitea().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .and(header("Authorization", "token secret"))
+            .and(body_string_contains(r#""sha":"abc""#))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"commit":{"sha":"123","url":""}}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        gitea
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", Some("abc"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_file_conflicts() {
+        let (server, gitea) = gitea().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .respond_with(message(
+                422,
+                "sha does not match [given: stale, expected: abc]",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(FILE))
+            .respond_with(message(
+                422,
+                "repository file already exists [path: 3/f/foo]",
+            ))
+            .mount(&server)
+            .await;
+
+        let stale = gitea
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", Some("stale"))
+            .await;
+        assert!(matches!(stale, Err(BackendError::Conflict(_))));
+        let existing = gitea
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", None)
+            .await;
+        assert!(matches!(existing, Err(BackendError::Conflict(_))));
+    }
 }
@@ -2,4 +2,6 @@ pub mod accept_merge_request;
 pub mod create_file;
 pub mod create_merge_request;
 pub mod get_file;
+pub mod get_tree;
+pub mod get_user;
 pub mod update_file;
@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub r#type: String,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    #[serde(default)]
+    pub tree: Vec<Entry>,
+    /// more entries are on the next page
+    pub truncated: bool,
+    // omitted
+}
@@ -4,6 +4,8 @@ pub mod create_pull_request;
 pub mod delete_branch;
 pub mod get_branch;
 pub mod get_file;
+pub mod get_tree;
+pub mod get_user;
 pub mod merge_pull_request;
 pub mod update_file;
 
@@ -13,5 +15,7 @@ pub use create_pull_request::create_pull_request;
 pub use delete_branch::delete_branch;
 pub use get_branch::get_branch;
 pub use get_file::get_file;
+pub use get_tree::get_tree;
+pub use get_user::get_user;
 pub use merge_pull_request::merge_pull_request;
 pub use update_file::update_file;
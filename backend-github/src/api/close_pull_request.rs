@@ -4,21 +4,23 @@ pub async fn close_pull_request(
     token: &str,
     project_id: &str,
     pull_id: u64,
-) -> Result<crate::models::close_pull_request::Response, reqwest::Error> {
+) -> Result<crate::models::close_pull_request::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/repos/{}/pulls/{}", url, project_id, pull_id);
     let client = reqwest::Client::new();
     let request = crate::models::close_pull_request::Request {
         state: "closed".into(),
     };
-    client
-        .put(url)
+    let response = client
+        .patch(url)
         .basic_auth(username, Some(token))
         .header("Accept", "application/vnd.github.v3+json")
         .header("user-agent", "cargolifter")
         .json(&request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
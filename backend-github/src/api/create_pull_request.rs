@@ -4,18 +4,20 @@ pub async fn create_pull_request(
     token: &str,
     project_id: &str,
     request: crate::models::create_pull_request::Request,
-) -> Result<crate::models::create_pull_request::Response, reqwest::Error> {
+) -> Result<crate::models::create_pull_request::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/repos/{}/pulls", url, project_id);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .post(url)
         .basic_auth(username, Some(token))
         .header("Accept", "application/vnd.github.v3+json")
         .header("user-agent", "cargolifter")
         .json(&request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
@@ -4,18 +4,20 @@ pub async fn delete_branch(
     token: &str,
     project_id: &str,
     branch: &str,
-) -> Result<String, reqwest::Error> {
+) -> Result<String, cargolifter_core::models::BackendError> {
     let url = format!("{}/repos/{}/git/refs/heads/{}", url, project_id, branch);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .delete(url)
         .basic_auth(username, Some(token))
         .header("Accept", "application/vnd.github.v3+json")
         .header("user-agent", "cargolifter")
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .text()
         .await
+        .map_err(Into::into)
     // Ok(())
 }
@@ -6,17 +6,19 @@ pub async fn get_branch(
     token: &str,
     project_id: &str,
     branch: &str,
-) -> Result<crate::models::get_branch::Response, reqwest::Error> {
+) -> Result<crate::models::get_branch::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/repos/{}/branches/{}", url, project_id, branch);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .get(url)
         .basic_auth(username, Some(token))
         .header("Accept", "application/vnd.github.v3+json")
         .header("user-agent", "cargolifter")
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
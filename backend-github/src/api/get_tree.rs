@@ -0,0 +1,23 @@
+pub async fn get_tree(
+    url: &str,
+    username: &str,
+    token: &str,
+    project_id: &str,
+    branch: &str,
+) -> Result<crate::models::get_tree::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/repos/{}/git/trees/{}", url, project_id, branch);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .header("Accept", "application/vnd.github.v3+json")
+        .header("user-agent", "cargolifter")
+        .query(&[("recursive", "1")])
+        .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
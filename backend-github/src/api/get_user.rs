@@ -0,0 +1,20 @@
+pub async fn get_user(
+    url: &str,
+    username: &str,
+    token: &str,
+) -> Result<crate::models::get_user::Response, cargolifter_core::models::BackendError> {
+    let url = format!("{}/user", url);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .basic_auth(username, Some(token))
+        .header("Accept", "application/vnd.github.v3+json")
+        .header("user-agent", "cargolifter")
+        .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
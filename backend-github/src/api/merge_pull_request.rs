@@ -5,18 +5,20 @@ pub async fn merge_pull_request(
     project_id: &str,
     pull_id: u64,
     request: crate::models::merge_pull_request::Request,
-) -> Result<crate::models::merge_pull_request::Response, reqwest::Error> {
+) -> Result<crate::models::merge_pull_request::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/repos/{}/pulls/{}/merge", url, project_id, pull_id);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .put(url)
         .basic_auth(username, Some(token))
         .header("Accept", "application/vnd.github.v3+json")
         .header("user-agent", "cargolifter")
         .json(&request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
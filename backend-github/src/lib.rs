@@ -2,7 +2,6 @@ mod api;
 mod models;
 
 use async_trait::async_trait;
-use cargolifter_core::models::PublishedVersion;
 use cargolifter_core::Backend;
 
 pub struct Github {
@@ -10,6 +9,7 @@ pub struct Github {
     project_id: String,
     host: Option<String>,
     default_branch: String,
+    commit_mode: cargolifter_core::config::CommitMode,
 }
 
 impl Github {
@@ -21,6 +21,7 @@ impl Github {
             default_branch: config
                 .default_branch
                 .unwrap_or_else(|| String::from("main")),
+            commit_mode: config.commit_mode.unwrap_or_default(),
         }
     }
 
@@ -56,7 +57,7 @@ impl Backend for Github {
         &self,
         token: &str,
         crate_path: &str,
-    ) -> Result<(String, String, String), reqwest::Error> {
+    ) -> Result<(String, String, String), cargolifter_core::models::BackendError> {
         let (username, password, host) = self.config(token);
 
         match api::get_file(
@@ -79,8 +80,9 @@ impl Backend for Github {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        initial_version: &PublishedVersion,
-    ) -> Result<(), reqwest::Error> {
+        content: &str,
+        message: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let (username, token, host) = self.config(token);
 
         let main_branch = api::get_branch(
@@ -103,12 +105,10 @@ impl Backend for Github {
         )
         .await?;
 
-        let json = serde_json::to_string(&initial_version).unwrap();
-        let encoded_content = base64::encode(json);
         let create_request = crate::models::update_file::Request {
             branch: Some(branch_name.into()),
-            content: encoded_content,
-            message: format!("Adding {} {}", initial_version.name, initial_version.vers),
+            content: base64::encode(content),
+            message: message.into(),
             ..Default::default()
         };
 
@@ -132,21 +132,16 @@ impl Backend for Github {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        versions: &[PublishedVersion],
+        content: &str,
+        message: &str,
         current_sha: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let (username, token, host) = self.config(token);
 
-        let new_content = versions
-            .iter()
-            .map(|v| serde_json::to_string(v).unwrap())
-            .collect::<Vec<String>>()
-            .join("\n");
-
         let update_request = crate::models::update_file::Request {
             branch: Some(branch_name.into()),
-            content: base64::encode(new_content),
-            message: format!("Adding {} {}", versions[0].name, versions[0].vers),
+            content: base64::encode(content),
+            message: message.into(),
             sha: Some(current_sha.into()),
             ..Default::default()
         };
@@ -186,7 +181,11 @@ impl Backend for Github {
         }
     }
 
-    async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), reqwest::Error> {
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let (username, token, host) = self.config(token);
 
         match api::delete_branch(&host, &username, &token, &self.project_id, branch_name).await {
@@ -200,7 +199,7 @@ impl Backend for Github {
         token: &str,
         title: &str,
         branch_name: &str,
-    ) -> Result<u64, reqwest::Error> {
+    ) -> Result<u64, cargolifter_core::models::BackendError> {
         let (username, token, host) = self.config(token);
 
         let pull_request = models::create_pull_request::Request {
@@ -218,7 +217,11 @@ impl Backend for Github {
         }
     }
 
-    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error> {
+    async fn merge_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let (username, token, host) = self.merge_config(token);
 
         let merge_request = crate::models::merge_pull_request::Request::default();
@@ -234,15 +237,231 @@ impl Backend for Github {
         .await
         {
             Ok(_) => Ok(()),
+            // GitHub rejects merging a pull request that conflicts with the default branch
+            Err(cargolifter_core::models::BackendError::UnexpectedStatus(405, detail)) => {
+                Err(cargolifter_core::models::BackendError::Conflict(detail))
+            }
             Err(e) => Err(e),
         }
     }
 
-    async fn delete_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error> {
+    async fn delete_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let (username, token, host) = self.config(token);
 
+        // pull requests can't be deleted on GitHub, closing them is all there is
         api::close_pull_request(&host, &username, &token, &self.project_id, id).await?;
+        Ok(())
+    }
+
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let (username, token, host) = self.config(token);
+
+        // GitHub rejects the change if the sha is outdated or missing for an existing file
+        let commit_request = crate::models::update_file::Request {
+            branch: Some(self.default_branch.clone()),
+            content: base64::encode(content),
+            message: message.into(),
+            sha: current_sha.map(String::from),
+            ..Default::default()
+        };
+
+        match api::update_file(
+            &host,
+            &username,
+            &token,
+            &self.project_id,
+            crate_path,
+            &commit_request,
+        )
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list_files(
+        &self,
+        token: &str,
+    ) -> Result<Vec<String>, cargolifter_core::models::BackendError> {
+        let (username, token, host) = self.config(token);
+
+        let tree = api::get_tree(
+            &host,
+            &username,
+            &token,
+            &self.project_id,
+            &self.default_branch,
+        )
+        .await?;
+        if tree.truncated {
+            tracing::warn!("GitHub only listed the first {} files", tree.tree.len());
+        }
+        Ok(tree
+            .tree
+            .into_iter()
+            .filter(|entry| entry.r#type == "blob")
+            .map(|entry| entry.path)
+            .collect())
+    }
+
+    async fn get_user(
+        &self,
+        token: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        let (username, token, host) = self.config(token);
+
+        match api::get_user(&host, &username, &token).await {
+            Ok(response) => Ok(response.login),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargoliter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        Some(self.project_id.clone())
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::BackendError;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const FILE: &str = "/repos/owner/index/contents/3/f/foo";
+
+    async fn github() -> (MockServer, Github) {
+        let mock_server = MockServer::start().await;
+        let github = Github::from(cargolifter_core::config::GithubConfig {
+            host: Some(mock_server.uri()),
+            owner: "owner".into(),
+            repo: "index".into(),
+            cargolifter_token: None,
+            default_branch: None,
+            commit_mode: Some(cargolifter_core::config::CommitMode::Direct),
+        });
+        (mock_server, github)
+    }
+
+    fn message(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(serde_json::json!({ "message": message }))
+    }
+
+    fn commit_response() -> serde_json::Value {
+        let user = serde_json::json!({"name": "alice", "email": "alice@example.com", "date": ""});
+        serde_json::json!({
+            "content": {
+                "name": "foo",
+                "path": "3/f/foo",
+                "sha": "def",
+                "size": 2,
+                "url": "",
+                "git_url": "",
+                "html_url": "",
+                "download_url": "",
+                "type": "file"
+            },
+            "commit": {
+                "sha": "123",
+                "node_id": "",
+                "url": "",
+                "html_url": "",
+                "committer": user,
+                "author": user,
+                "message": "Adding foo",
+                "tree": {"url": "", "sha": ""},
+                "parents": [],
+                "verification": {"verified": false, "reason": "unsigned"}
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_commit_file() {
+        let (server, github) = github().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .and(header("Authorization", "Basic YWxpY2U6c2VjcmV0"))
+            .and(body_string_contains(r#""sha":"abc""#))
+            .and(body_string_contains(r#""branch":"main""#))
+            .respond_with(ResponseTemplate::new(200).set_body_json(commit_response()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        github
+            .commit_file("alice:secret", "3/f/foo", "{}", "Adding foo", Some("abc"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_merge_conflict() {
+        let (server, github) = github().await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/owner/index/pulls/7/merge"))
+            .respond_with(message(405, "Pull Request is not mergeable"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/repos/owner/index/pulls/7"))
+            .and(body_string_contains(r#""state":"closed""#))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"id":1007,"state":"closed"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // the pull request of a conflicting change is closed before retrying
+        let result = github.merge_pull_request("alice:secret", 7).await;
+        assert!(matches!(result, Err(BackendError::Conflict(_))));
+        github.delete_pull_request("alice:secret", 7).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_file_conflicts() {
+        let (server, github) = github().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .and(body_string_contains(r#""sha":"stale""#))
+            .respond_with(message(409, "3/f/foo does not match stale"))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .respond_with(message(422, "Invalid request.\n\n\"sha\" wasn't supplied."))
+            .mount(&server)
+            .await;
 
-        todo!()
+        let stale = github
+            .commit_file("alice:secret", "3/f/foo", "{}", "Adding foo", Some("stale"))
+            .await;
+        assert!(matches!(stale, Err(BackendError::Conflict(_))));
+        let existing = github
+            .commit_file("alice:secret", "3/f/foo", "{}", "Adding foo", None)
+            .await;
+        assert!(matches!(existing, Err(BackendError::Conflict(_))));
     }
 }
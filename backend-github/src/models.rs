@@ -3,5 +3,7 @@ pub mod create_branch;
 pub mod create_pull_request;
 pub mod get_branch;
 pub mod get_file;
+pub mod get_tree;
+pub mod get_user;
 pub mod merge_pull_request;
 pub mod update_file;
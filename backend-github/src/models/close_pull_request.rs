@@ -6,6 +6,6 @@ pub struct Request {
 }
 #[derive(Debug, Deserialize)]
 pub struct Response {
-    pub id: String,
-    pub state: bool,
+    pub id: u64,
+    pub state: String,
 }
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub r#type: String,
+    // omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub tree: Vec<Entry>,
+    pub truncated: bool,
+    // omitted
+}
@@ -4,19 +4,21 @@ pub async fn accept_merge_request(
     project_id: usize,
     merge_request_iid: u64,
     request: &crate::models::accept_merge_request::Request,
-) -> Result<crate::models::accept_merge_request::Response, reqwest::Error> {
+) -> Result<crate::models::accept_merge_request::Response, cargolifter_core::models::BackendError> {
     let url = format!(
         "{}/api/v4/projects/{}/merge_requests/{}/merge",
         host, project_id, merge_request_iid
     );
     let client = reqwest::Client::new();
-    client
+    let response = client
         .put(url)
         .header("PRIVATE-TOKEN", token)
         .json(request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
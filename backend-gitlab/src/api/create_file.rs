@@ -4,7 +4,7 @@ pub async fn create_file(
     project_id: usize,
     file: &str,
     request: &crate::models::create_file::Request,
-) -> Result<crate::models::create_file::Response, reqwest::Error> {
+) -> Result<crate::models::create_file::Response, cargolifter_core::models::BackendError> {
     let url = format!(
         "{}/api/v4/projects/{}/repository/files/{}",
         host,
@@ -12,13 +12,15 @@ pub async fn create_file(
         urlencoding::encode(file)
     );
     let client = reqwest::Client::new();
-    client
+    let response = client
         .post(url)
         .header("PRIVATE-TOKEN", token)
         .json(request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
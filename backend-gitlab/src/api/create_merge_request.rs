@@ -3,16 +3,18 @@ pub async fn create_merge_request(
     token: &str,
     project_id: usize,
     request: &crate::models::create_merge_request::Request,
-) -> Result<crate::models::create_merge_request::Response, reqwest::Error> {
+) -> Result<crate::models::create_merge_request::Response, cargolifter_core::models::BackendError> {
     let url = format!("{}/api/v4/projects/{}/merge_requests", host, project_id);
     let client = reqwest::Client::new();
-    client
+    let response = client
         .post(url)
         .header("PRIVATE-TOKEN", token)
         .json(request)
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
@@ -5,17 +5,17 @@ pub async fn delete_branch(
     token: &str,
     project_id: usize,
     branch: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), cargolifter_core::models::BackendError> {
     let url = format!(
         "{}/api/v4/projects/{}/repository/branches/{}",
         host, project_id, branch
     );
     let client = reqwest::Client::new();
-    client
+    let response = client
         .delete(url)
         .header("PRIVATE-TOKEN", token)
         .send()
-        .await?
-        .error_for_status()?;
+        .await?;
+    cargolifter_core::models::BackendError::check(response).await?;
     Ok(())
 }
@@ -4,7 +4,7 @@ pub async fn get_file(
     project_id: usize,
     file: &str,
     branch: &str,
-) -> Result<crate::models::get_file::Response, reqwest::Error> {
+) -> Result<crate::models::get_file::Response, cargolifter_core::models::BackendError> {
     let url = format!(
         "{}/api/v4/projects/{}/repository/files/{}",
         host,
@@ -12,14 +12,16 @@ pub async fn get_file(
         urlencoding::encode(file)
     );
     let client = reqwest::Client::new();
-    client
+    let response = client
         .get(url)
         .header("PRIVATE-TOKEN", token)
         .header("user-agent", "cargolifter")
         .query(&[("ref", branch)])
         .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
         .await?
-        .error_for_status()?
         .json()
         .await
+        .map_err(Into::into)
 }
@@ -0,0 +1,29 @@
+pub const PER_PAGE: usize = 100;
+
+pub async fn get_tree(
+    host: &str,
+    token: &str,
+    project_id: usize,
+    branch: &str,
+    page: usize,
+) -> Result<Vec<crate::models::get_tree::Entry>, cargolifter_core::models::BackendError> {
+    let url = format!("{}/api/v4/projects/{}/repository/tree", host, project_id);
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .header("PRIVATE-TOKEN", token)
+        .header("user-agent", "cargolifter")
+        .query(&[
+            ("ref", branch),
+            ("recursive", "true"),
+            ("per_page", &PER_PAGE.to_string()),
+            ("page", &page.to_string()),
+        ])
+        .send()
+        .await?;
+    cargolifter_core::models::BackendError::check(response)
+        .await?
+        .json()
+        .await
+        .map_err(Into::into)
+}
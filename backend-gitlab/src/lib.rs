@@ -2,14 +2,17 @@ mod api;
 mod models;
 
 use async_trait::async_trait;
-use cargolifter_core::models::PublishedVersion;
 use cargolifter_core::Backend;
 
+/// messages of bad requests caused by an outdated `last_commit_id` or an existing file
+const CONFLICT_MESSAGES: &[&str] = &["already exists", "has changed"];
+
 pub struct Gitlab {
     cargolifter_token: Option<String>,
     project_id: usize,
     host: Option<String>,
     default_branch: String,
+    commit_mode: cargolifter_core::config::CommitMode,
 }
 
 impl Gitlab {
@@ -21,6 +24,7 @@ impl Gitlab {
             default_branch: config
                 .default_branch
                 .unwrap_or_else(|| String::from("main")),
+            commit_mode: config.commit_mode.unwrap_or_default(),
         }
     }
 
@@ -36,7 +40,7 @@ impl Backend for Gitlab {
         &self,
         token: &str,
         crate_path: &str,
-    ) -> Result<(String, String, String), reqwest::Error> {
+    ) -> Result<(String, String, String), cargolifter_core::models::BackendError> {
         let host = self.host();
 
         match api::get_file(
@@ -48,7 +52,8 @@ impl Backend for Gitlab {
         )
         .await
         {
-            Ok(response) => Ok((response.content, response.encoding, response.content_sha256)),
+            // the last commit id lets GitLab detect changes made since the file was read
+            Ok(response) => Ok((response.content, response.encoding, response.last_commit_id)),
             Err(e) => Err(e),
         }
     }
@@ -58,18 +63,17 @@ impl Backend for Gitlab {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        initial_version: &PublishedVersion,
-    ) -> Result<(), reqwest::Error> {
+        content: &str,
+        message: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let host = self.host();
 
-        let json = serde_json::to_string(&initial_version).unwrap();
-        let encoded_content = base64::encode(json);
         let create_request = crate::models::create_file::Request {
             branch: branch_name.into(),
             start_branch: Some(self.default_branch.clone()),
-            content: encoded_content,
+            content: base64::encode(content),
             encoding: Some("base64".into()),
-            commit_message: format!("Adding {} {}", initial_version.name, initial_version.vers),
+            commit_message: message.into(),
             ..Default::default()
         };
 
@@ -84,23 +88,19 @@ impl Backend for Gitlab {
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        versions: &[PublishedVersion],
-        _current_sha: &str,
-    ) -> Result<(), reqwest::Error> {
+        content: &str,
+        message: &str,
+        current_sha: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let host = self.host();
 
-        let new_content = versions
-            .iter()
-            .map(|v| serde_json::to_string(v).unwrap())
-            .collect::<Vec<String>>()
-            .join("\n");
-
         let update_request = crate::models::update_file::Request {
             branch: branch_name.into(),
             start_branch: Some(self.default_branch.clone()),
-            content: base64::encode(new_content),
+            content: base64::encode(content),
             encoding: Some("base64".into()),
-            commit_message: format!("Adding {} {}", versions[0].name, versions[0].vers),
+            commit_message: message.into(),
+            last_commit_id: Some(current_sha.into()),
             ..Default::default()
         };
         match api::update_file(&host, token, self.project_id, crate_path, &update_request).await {
@@ -109,7 +109,11 @@ impl Backend for Gitlab {
         }
     }
 
-    async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), reqwest::Error> {
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let host = self.host();
 
         match api::delete_branch(&host, token, self.project_id, branch_name).await {
@@ -123,7 +127,7 @@ impl Backend for Gitlab {
         token: &str,
         title: &str,
         branch_name: &str,
-    ) -> Result<u64, reqwest::Error> {
+    ) -> Result<u64, cargolifter_core::models::BackendError> {
         let host = self.host();
 
         let merge_request = models::create_merge_request::Request {
@@ -139,7 +143,11 @@ impl Backend for Gitlab {
         }
     }
 
-    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error> {
+    async fn merge_pull_request(
+        &self,
+        token: &str,
+        id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         let host = self.host();
 
         let owned_token = token.to_owned();
@@ -154,11 +162,211 @@ impl Backend for Gitlab {
             .await
         {
             Ok(_) => Ok(()),
+            // GitLab rejects merging a merge request that conflicts with the default branch
+            Err(cargolifter_core::models::BackendError::UnexpectedStatus(405 | 406, detail)) => {
+                Err(cargolifter_core::models::BackendError::Conflict(detail))
+            }
             Err(e) => Err(e),
         }
     }
 
-    async fn delete_pull_request(&self, _token: &str, _id: u64) -> Result<(), reqwest::Error> {
+    async fn delete_pull_request(
+        &self,
+        _token: &str,
+        _id: u64,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
         Ok(())
     }
+
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        let result = match current_sha {
+            Some(sha) => {
+                let update_request = crate::models::update_file::Request {
+                    branch: self.default_branch.clone(),
+                    content: base64::encode(content),
+                    encoding: Some("base64".into()),
+                    commit_message: message.into(),
+                    last_commit_id: Some(sha.into()),
+                    ..Default::default()
+                };
+                api::update_file(&host, token, self.project_id, crate_path, &update_request)
+                    .await
+                    .map(|_| ())
+            }
+            None => {
+                let create_request = crate::models::create_file::Request {
+                    branch: self.default_branch.clone(),
+                    content: base64::encode(content),
+                    encoding: Some("base64".into()),
+                    commit_message: message.into(),
+                    ..Default::default()
+                };
+                api::create_file(&host, token, self.project_id, crate_path, &create_request)
+                    .await
+                    .map(|_| ())
+            }
+        };
+
+        // GitLab answers outdated changes and existing files with a bad request
+        match result {
+            Err(cargolifter_core::models::BackendError::UnexpectedStatus(400, detail))
+                if CONFLICT_MESSAGES
+                    .iter()
+                    .any(|conflict| detail.contains(conflict)) =>
+            {
+                Err(cargolifter_core::models::BackendError::Conflict(detail))
+            }
+            result => result,
+        }
+    }
+
+    async fn list_files(
+        &self,
+        token: &str,
+    ) -> Result<Vec<String>, cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        let mut files = Vec::new();
+        for page in 1.. {
+            let entries =
+                api::get_tree(&host, token, self.project_id, &self.default_branch, page).await?;
+            let last_page = entries.len() < api::get_tree::PER_PAGE;
+            files.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.r#type == "blob")
+                    .map(|entry| entry.path),
+            );
+            if last_page {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    async fn get_user(
+        &self,
+        token: &str,
+    ) -> Result<String, cargolifter_core::models::BackendError> {
+        let host = self.host();
+
+        match api::get_user(&host, token).await {
+            Ok(response) => Ok(response.username),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.cargolifter_token.clone()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        Some(self.project_id.to_string())
+    }
+
+    fn commit_mode(&self) -> cargolifter_core::config::CommitMode {
+        self.commit_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::BackendError;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const FILE: &str = "/api/v4/projects/1/repository/files/3%2Ff%2Ffoo";
+
+    async fn gitlab() -> (MockServer, Gitlab) {
+        let mock_server = MockServer::start().await;
+        let gitlab = Gitlab::from(cargolifter_core::config::GitlabConfig {
+            host: Some(mock_server.uri()),
+            project_id: 1,
+            cargolifter_token: None,
+            default_branch: None,
+            commit_mode: Some(cargolifter_core::config::CommitMode::Direct),
+        });
+        (mock_server, gitlab)
+    }
+
+    fn message(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_string(format!(r#"{{"message":"{}"}}"#, message))
+    }
+
+    #[tokio::test]
+    async fn test_commit_file() {
+        let (server, gitlab) = gitlab().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .and(header("PRIVATE-TOKEN", "secret"))
+            .and(body_string_contains(r#""last_commit_id":"abc""#))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"file_path":"3/f/foo","branch":"main"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        gitlab
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", Some("abc"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_file_conflicts() {
+        let (server, gitlab) = gitlab().await;
+        Mock::given(method("PUT"))
+            .and(path(FILE))
+            .respond_with(message(
+                400,
+                "You are attempting to update a file that has changed since you started editing it.",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(FILE))
+            .respond_with(message(400, "A file with this name already exists"))
+            .mount(&server)
+            .await;
+
+        let stale = gitlab
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", Some("stale"))
+            .await;
+        assert!(matches!(stale, Err(BackendError::Conflict(_))));
+        let existing = gitlab
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", None)
+            .await;
+        assert!(matches!(existing, Err(BackendError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_commit_file_bad_request() {
+        let (server, gitlab) = gitlab().await;
+        Mock::given(method("POST"))
+            .and(path(FILE))
+            .respond_with(message(400, "branch is missing"))
+            .mount(&server)
+            .await;
+
+        // only outdated changes are retried
+        let result = gitlab
+            .commit_file("secret", "3/f/foo", "{}", "Adding foo", None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(BackendError::UnexpectedStatus(400, _))
+        ));
+    }
 }
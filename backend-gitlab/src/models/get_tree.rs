@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub r#type: String,
+    // omitted
+}
@@ -1,7 +1,13 @@
 use argh::FromArgs;
+use cargolifter_backend_azure_devops::AzureDevOps;
+use cargolifter_backend_bitbucket::Bitbucket;
+use cargolifter_backend_git::Git;
 use cargolifter_backend_gitea::Gitea;
 use cargolifter_backend_github::Github;
 use cargolifter_backend_gitlab::Gitlab;
+use cargolifter_core::catalog::Catalog;
+use cargolifter_core::journal::Journal;
+use cargolifter_core::review::PendingVersions;
 use cargolifter_core::{BackendService, StorageService};
 use cargolifter_storage_filesystem::FileSystemStorage;
 use cargolifter_storage_s3::S3Storage;
@@ -13,6 +19,10 @@ struct Arguments {
     /// path to config file
     #[argh(option, short = 'c')]
     config: String,
+
+    /// move crate files of the storage to the current layout and exit
+    #[argh(switch)]
+    migrate_storage: bool,
 }
 
 #[tokio::main]
@@ -27,20 +37,72 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config: cargolifter_core::config::CargoLifterConfig =
         serde_json::from_reader(std::io::BufReader::new(file))?;
 
+    if args.migrate_storage {
+        let report = match config.storage {
+            cargolifter_core::config::StorageType::FileSystem(config) => {
+                FileSystemStorage::new(&config.path).migrate()?
+            }
+            cargolifter_core::config::StorageType::S3(config) => {
+                S3Storage::new(config).await.migrate().await?
+            }
+        };
+        tracing::info!(
+            "storage migration finished: {} migrated, {} skipped",
+            report.migrated,
+            report.skipped
+        );
+        return Ok(());
+    }
+
+    let catalog = match &config.catalog {
+        Some(config) => Catalog::open(&config.path),
+        None => Catalog::new(),
+    };
+    let pending = match config
+        .review
+        .as_ref()
+        .and_then(|config| config.path.as_ref())
+    {
+        Some(path) => PendingVersions::open(path),
+        None => PendingVersions::new(),
+    };
+    let service = config.service.unwrap_or_default();
+    let journal = std::sync::Arc::new(match &config.journal {
+        Some(config) => Journal::open(&config.path),
+        None => Journal::new(),
+    });
+
     let (backend_handle, backend_sender) = match config.backend {
+        cargolifter_core::config::BackendType::AzureDevOps(config) => {
+            let azure_devops = AzureDevOps::from(config);
+            let backend =
+                BackendService::new(azure_devops, catalog, pending, service, journal.clone());
+            backend.run()
+        }
+        cargolifter_core::config::BackendType::Bitbucket(config) => {
+            let bitbucket = Bitbucket::from(config);
+            let backend =
+                BackendService::new(bitbucket, catalog, pending, service, journal.clone());
+            backend.run()
+        }
+        cargolifter_core::config::BackendType::Git(config) => {
+            let git = Git::from(config);
+            let backend = BackendService::new(git, catalog, pending, service, journal.clone());
+            backend.run()
+        }
         cargolifter_core::config::BackendType::Gitea(config) => {
             let gitlab = Gitea::from(config);
-            let backend = BackendService::new(gitlab);
+            let backend = BackendService::new(gitlab, catalog, pending, service, journal.clone());
             backend.run()
         }
         cargolifter_core::config::BackendType::Github(config) => {
             let github = Github::from(config);
-            let backend = BackendService::new(github);
+            let backend = BackendService::new(github, catalog, pending, service, journal.clone());
             backend.run()
         }
         cargolifter_core::config::BackendType::Gitlab(config) => {
             let gitlab = Gitlab::from(config);
-            let backend = BackendService::new(gitlab);
+            let backend = BackendService::new(gitlab, catalog, pending, service, journal.clone());
             backend.run()
         }
     };
@@ -53,12 +115,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         cargolifter_core::config::StorageType::S3(config) => {
             let s3 = S3Storage::new(config).await;
+            // uploads of a crashed run are never completed, but S3 keeps their parts
+            if let Err(e) = s3.abort_staged_uploads().await {
+                tracing::error!("failed to abort unfinished uploads: {}", e);
+            }
             let storage = StorageService::new(s3);
             storage.run()
         }
     };
 
-    let web = WebService::new(backend_sender, storage_sender, config.web);
+    cargolifter_core::transaction::recover(&journal, &backend_sender, &storage_sender).await;
+
+    let web = WebService::new(
+        backend_sender,
+        storage_sender,
+        config.web,
+        config.mirror,
+        config.review,
+        journal,
+        config.publish.unwrap_or_default(),
+    );
     web.run().await;
     let _ = futures::join!(backend_handle, storage_handle);
 
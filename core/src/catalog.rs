@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use crate::models::{
+    MetaData, PublishedVersion, SearchRequest, SearchResponse, SearchResultCrate, SearchResultMeta,
+};
+use crate::persist::JsonFile;
+
+const DEFAULT_PER_PAGE: usize = 10;
+const MAX_PER_PAGE: usize = 100;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatalogVersion {
+    pub vers: String,
+    pub yanked: bool,
+    #[serde(default)]
+    pub rust_version: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub versions: Vec<CatalogVersion>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+impl CatalogEntry {
+    /// highest non-yanked version, falling back to the highest yanked one
+    pub fn max_version(&self) -> Option<&CatalogVersion> {
+        let highest = |yanked: bool| {
+            self.versions
+                .iter()
+                .filter(|v| yanked || !v.yanked)
+                .max_by(|a, b| compare_versions(&a.vers, &b.vers))
+        };
+        highest(false).or_else(|| highest(true))
+    }
+
+    fn rank(&self, query: &str) -> Option<u8> {
+        let name = self.name.to_lowercase();
+        if query.is_empty() || name == query {
+            Some(0)
+        } else if name.contains(query) {
+            Some(1)
+        } else if self
+            .keywords
+            .iter()
+            .chain(self.categories.iter())
+            .any(|k| k.to_lowercase().contains(query))
+        {
+            Some(2)
+        } else if self
+            .description
+            .as_ref()
+            .map(|d| d.to_lowercase().contains(query))
+            .unwrap_or(false)
+        {
+            Some(3)
+        } else {
+            None
+        }
+    }
+}
+
+/// Searchable list of all crates published to the registry.
+///
+/// The catalog is rebuilt from the index on startup and then fed from the index
+/// entries the backend writes and from the metadata sent on publish. If a path
+/// is given it is persisted as JSON, which keeps descriptions and keywords the
+/// index doesn't contain across restarts.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: BTreeMap<String, CatalogEntry>,
+    file: Option<JsonFile>,
+    /// crates updated since a rebuild started, their entries are newer than the index read
+    rebuilding: Option<HashSet<String>>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let entries = match std::fs::File::open(&path) {
+            Ok(file) => match serde_json::from_reader(std::io::BufReader::new(file)) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::error!("failed to read catalog '{}': {}", path.display(), e);
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => {
+                tracing::info!("catalog '{}' not found - starting empty", path.display());
+                BTreeMap::new()
+            }
+        };
+
+        Self {
+            entries,
+            file: Some(JsonFile::new(path)),
+            rebuilding: None,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CatalogEntry> {
+        self.entries.get(name)
+    }
+
+    /// name of a known crate that only differs from `name` in case or in `-` and `_`
+    pub fn find_similar(&self, name: &str) -> Option<&str> {
+        let canonical = crate::validation::canonical_name(name);
+        self.entries
+            .keys()
+            .find(|known| {
+                known.as_str() != name && crate::validation::canonical_name(known) == canonical
+            })
+            .map(String::as_str)
+    }
+
+    /// replaces the known versions of a crate with the given index entries and
+    /// takes description, keywords and categories from `meta` if available
+    pub fn update(&mut self, versions: &[PublishedVersion], meta: Option<&MetaData>) {
+        if let (Some(rebuilding), Some(version)) = (&mut self.rebuilding, versions.first()) {
+            rebuilding.insert(version.name.clone());
+        }
+        self.apply(versions, meta);
+        self.save();
+    }
+
+    /// starts reading the index for `rebuild`, the catalog stays usable meanwhile
+    pub fn begin_rebuild(&mut self) {
+        self.rebuilding = Some(HashSet::new());
+    }
+
+    /// replaces the catalog with the crates found in the index, metadata of
+    /// crates that are still in the index is kept
+    ///
+    /// Crates updated since `begin_rebuild` keep their entry, as the index may
+    /// have been read before the update.
+    pub fn rebuild(&mut self, crates: &[Vec<PublishedVersion>]) {
+        let updated = self.rebuilding.take().unwrap_or_default();
+        let names = crates
+            .iter()
+            .filter_map(|versions| versions.first())
+            .map(|version| version.name.as_str())
+            .collect::<HashSet<_>>();
+        self.entries
+            .retain(|name, _| names.contains(name.as_str()) || updated.contains(name));
+        for versions in crates {
+            match versions.first() {
+                Some(version) if updated.contains(&version.name) => {}
+                _ => self.apply(versions, None),
+            }
+        }
+        self.save();
+    }
+
+    /// gives up a rebuild, e.g. because the index couldn't be read
+    pub fn cancel_rebuild(&mut self) {
+        self.rebuilding = None;
+    }
+
+    fn apply(&mut self, versions: &[PublishedVersion], meta: Option<&MetaData>) {
+        let name = match versions.first() {
+            Some(version) => version.name.clone(),
+            None => return,
+        };
+
+        let entry = self
+            .entries
+            .entry(name.clone())
+            .or_insert_with(|| CatalogEntry {
+                name,
+                ..Default::default()
+            });
+        entry.versions = versions
+            .iter()
+            .map(|v| CatalogVersion {
+                vers: v.vers.clone(),
+                yanked: v.yanked,
+                rust_version: v.rust_version.clone(),
+            })
+            .collect();
+        if let Some(meta) = meta {
+            entry.description = meta.description.clone();
+            entry.keywords = meta.keywords.clone();
+            entry.categories = meta.categories.clone();
+        }
+    }
+
+    pub fn search(&self, request: &SearchRequest) -> SearchResponse {
+        let query = request.q.trim().to_lowercase();
+        let per_page = request
+            .per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE);
+
+        let mut matches = self
+            .entries
+            .values()
+            .filter_map(|entry| entry.rank(&query).map(|rank| (rank, entry)))
+            .collect::<Vec<_>>();
+        matches.sort_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank).then(a.name.cmp(&b.name)));
+
+        SearchResponse {
+            meta: SearchResultMeta {
+                total: matches.len(),
+            },
+            crates: matches
+                .into_iter()
+                .take(per_page)
+                .map(|(_, entry)| {
+                    let max_version = entry.max_version();
+                    SearchResultCrate {
+                        name: entry.name.clone(),
+                        max_version: max_version.map(|v| v.vers.clone()).unwrap_or_default(),
+                        description: entry.description.clone(),
+                        rust_version: max_version.and_then(|v| v.rust_version.clone()),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn save(&mut self) {
+        if let Some(file) = &mut self.file {
+            file.save(&self.entries);
+        }
+    }
+}
+
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(name: &str, vers: &str, yanked: bool) -> PublishedVersion {
+        PublishedVersion {
+            name: name.into(),
+            vers: vers.into(),
+            yanked,
+            ..Default::default()
+        }
+    }
+
+    fn meta(description: &str, keywords: &[&str]) -> MetaData {
+        MetaData {
+            description: Some(description.into()),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog.update(
+            &[
+                version("serde", "1.0.9", false),
+                version("serde", "1.0.10", false),
+                version("serde", "1.1.0", true),
+            ],
+            Some(&meta("A serialization framework", &["serialization"])),
+        );
+        catalog.update(
+            &[version("serde_json", "1.0.0", false)],
+            Some(&meta("JSON support for serde", &["json"])),
+        );
+        catalog.update(
+            &[version("toml", "0.5.0", false)],
+            Some(&meta("A TOML parser", &["encoding", "serialization"])),
+        );
+        catalog
+    }
+
+    #[test]
+    fn test_search_ranks_exact_name_first() {
+        let response = catalog().search(&SearchRequest {
+            q: "serde".into(),
+            per_page: None,
+        });
+        let names = response
+            .crates
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["serde", "serde_json"], names);
+        assert_eq!(2, response.meta.total);
+    }
+
+    #[test]
+    fn test_search_skips_yanked_max_version() {
+        let response = catalog().search(&SearchRequest {
+            q: "serde".into(),
+            per_page: Some(1),
+        });
+        assert_eq!(1, response.crates.len());
+        assert_eq!("1.0.10", response.crates[0].max_version);
+        assert_eq!(2, response.meta.total);
+    }
+
+    #[test]
+    fn test_search_matches_keywords_and_description() {
+        let response = catalog().search(&SearchRequest {
+            q: "Serialization".into(),
+            per_page: None,
+        });
+        let names = response
+            .crates
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["serde", "toml"], names);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut catalog = catalog();
+        catalog.rebuild(&[
+            vec![
+                version("serde", "1.0.10", false),
+                version("serde", "1.1.0", false),
+            ],
+            vec![version("rand", "0.8.0", false)],
+        ]);
+
+        let serde = catalog.get("serde").unwrap();
+        assert_eq!("1.1.0", serde.max_version().unwrap().vers);
+        assert_eq!(
+            Some("A serialization framework"),
+            serde.description.as_deref()
+        );
+        assert!(catalog.get("rand").is_some());
+        assert!(catalog.get("toml").is_none());
+    }
+
+    #[test]
+    fn test_update_during_rebuild() {
+        let mut catalog = catalog();
+        catalog.begin_rebuild();
+        // published while the index is read, which still has the old versions
+        catalog.update(&[version("serde", "1.2.0", false)], None);
+        catalog.update(&[version("toml", "0.5.0", false)], None);
+        catalog.rebuild(&[vec![version("serde", "1.0.10", false)]]);
+
+        assert_eq!(
+            "1.2.0",
+            catalog.get("serde").unwrap().max_version().unwrap().vers
+        );
+        assert!(catalog.get("toml").is_some());
+        assert!(catalog.get("serde_json").is_none());
+
+        // without a rebuild in progress, the index is taken as is
+        catalog.rebuild(&[vec![version("serde", "1.0.10", false)]]);
+        assert!(catalog.get("toml").is_none());
+    }
+
+    #[test]
+    fn test_find_similar() {
+        let catalog = catalog();
+        assert_eq!(Some("serde_json"), catalog.find_similar("Serde-Json"));
+        assert_eq!(None, catalog.find_similar("serde_json"));
+        assert_eq!(None, catalog.find_similar("serde_yaml"));
+    }
+}
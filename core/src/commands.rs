@@ -1,4 +1,5 @@
-pub mod is_published;
+pub mod list;
+pub mod owners;
 pub mod publish;
 pub mod utils;
 pub mod yank;
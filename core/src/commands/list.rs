@@ -0,0 +1,53 @@
+/// Reads the versions of all crates in the index.
+///
+/// Files that are not the index file of a crate, like `config.json` or owners
+/// files, are skipped, as are index files that can't be read.
+pub async fn execute(
+    backend: &impl crate::Backend,
+    token: &str,
+) -> Result<Vec<Vec<crate::models::PublishedVersion>>, crate::models::RegistryError> {
+    let mut crates = Vec::new();
+    for path in backend.list_files(token).await? {
+        let name = match crate_file_name(&path) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let versions = match backend.get_file(token, &path).await {
+            Ok((content, encoding, _)) => super::utils::read_versions(&content, &encoding),
+            Err(e) => Err(e),
+        };
+        match versions {
+            Ok(versions) if !versions.is_empty() => crates.push(versions),
+            Ok(_) => tracing::warn!("index file of '{}' is empty", name),
+            Err(e) => tracing::error!("failed to read index file '{}': {}", path, e),
+        }
+    }
+    Ok(crates)
+}
+
+/// name of a crate in the index that differs from `name` only in `-` and `_`,
+/// crates only differing in case share the same index file
+pub async fn find_similar(
+    backend: &impl crate::Backend,
+    token: &str,
+    name: &str,
+) -> Result<Option<String>, crate::models::RegistryError> {
+    let canonical = crate::validation::canonical_name(name);
+    let own_path = crate::get_crate_file_path(name);
+    Ok(backend
+        .list_files(token)
+        .await?
+        .iter()
+        .filter(|path| **path != own_path)
+        .filter_map(|path| crate_file_name(path))
+        .find(|known| crate::validation::canonical_name(known) == canonical)
+        .map(String::from))
+}
+
+/// name of the crate if `path` is the index file of a crate
+fn crate_file_name(path: &str) -> Option<&str> {
+    path.rsplit('/')
+        .next()
+        .filter(|name| crate::get_crate_file_path(name) == path)
+}
@@ -0,0 +1,174 @@
+use crate::models::{BackendError, Owners, RegistryError};
+
+/// reads the owners file of a crate, `None` if the crate has no owners file yet
+pub async fn read(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+) -> Result<Option<(Owners, String)>, RegistryError> {
+    let owners_path = crate::get_owners_file_path(crate_name);
+
+    match backend.get_file(token, &owners_path).await {
+        Ok((content, encoding, sha)) => {
+            let content = super::utils::decode_content(&content, &encoding)?;
+            let owners = serde_json::from_str(&content).map_err(RegistryError::InvalidOwners)?;
+            Ok(Some((owners, sha)))
+        }
+        Err(BackendError::NotFound(_)) => {
+            tracing::info!("'{}' not found => crate has no owners", owners_path);
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// reads the owners of a crate in the index, the owners file of a crate without index
+/// file is left over from a failed publish and not trusted
+async fn read_published(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+) -> Result<Option<(Owners, String)>, RegistryError> {
+    let crate_path = crate::get_crate_file_path(crate_name);
+    match backend.get_file(token, &crate_path).await {
+        Ok(_) => read(backend, token, crate_name).await,
+        Err(BackendError::NotFound(_)) => {
+            tracing::warn!("'{}' not found => crate is not published", crate_path);
+            Err(RegistryError::NotFound(format!(
+                "crate '{}' not found",
+                crate_name
+            )))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// crates without owners file are only protected by the write access to the index
+pub fn check(
+    owners: &Option<(Owners, String)>,
+    user: &str,
+    crate_name: &str,
+) -> Result<(), RegistryError> {
+    match owners {
+        Some((owners, _)) if !owners.contains(user) => {
+            tracing::warn!("'{}' is not an owner of '{}'", user, crate_name);
+            Err(RegistryError::NotAnOwner(user.into(), crate_name.into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub async fn write(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+    owners: &Owners,
+    current_sha: Option<&str>,
+) -> Result<(), RegistryError> {
+    let owners_path = crate::get_owners_file_path(crate_name);
+    let branch_name = super::utils::unique_branch_name(&format!("{}-owners", crate_name));
+    let content = serde_json::to_string(owners).map_err(RegistryError::InvalidOwners)?;
+    let message = format!("Updating owners of {}", crate_name);
+
+    super::utils::write_file(
+        backend,
+        token,
+        &owners_path,
+        &branch_name,
+        &content,
+        &message,
+        current_sha,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn list(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+) -> Result<Owners, RegistryError> {
+    Ok(read(backend, token, crate_name)
+        .await?
+        .map(|(owners, _)| owners)
+        .unwrap_or_default())
+}
+
+pub async fn add(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+    users: &[String],
+) -> Result<(), RegistryError> {
+    let user = backend.get_user(token).await?;
+    super::utils::retry_on_conflict(|| add_attempt(backend, token, crate_name, users, &user)).await
+}
+
+/// adds the users to the owners file as read at the start of the attempt
+async fn add_attempt(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+    users: &[String],
+    user: &str,
+) -> Result<(), RegistryError> {
+    let current = read_published(backend, token, crate_name).await?;
+    check(&current, user, crate_name)?;
+
+    // whoever may publish a crate without owners file claims it by adding owners
+    let (mut owners, sha) = match current {
+        Some((owners, sha)) => (owners, Some(sha)),
+        None => (
+            Owners {
+                users: vec![user.into()],
+            },
+            None,
+        ),
+    };
+    users.iter().for_each(|u| {
+        if !owners.contains(u) {
+            owners.users.push(u.clone());
+        }
+    });
+
+    write(backend, token, crate_name, &owners, sha.as_deref()).await
+}
+
+pub async fn remove(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+    users: &[String],
+) -> Result<(), RegistryError> {
+    let user = backend.get_user(token).await?;
+    super::utils::retry_on_conflict(|| remove_attempt(backend, token, crate_name, users, &user))
+        .await
+}
+
+/// removes the users from the owners file as read at the start of the attempt
+async fn remove_attempt(
+    backend: &impl crate::Backend,
+    token: &str,
+    crate_name: &str,
+    users: &[String],
+    user: &str,
+) -> Result<(), RegistryError> {
+    let current = read_published(backend, token, crate_name).await?;
+    check(&current, user, crate_name)?;
+
+    let (mut owners, sha) = match current {
+        Some(current) => current,
+        None => {
+            tracing::warn!("'{}' has no owners - nothing to remove", crate_name);
+            return Ok(());
+        }
+    };
+    owners
+        .users
+        .retain(|o| !users.iter().any(|u| u.eq_ignore_ascii_case(o)));
+    if owners.users.is_empty() {
+        return Err(RegistryError::LastOwner(crate_name.into()));
+    }
+
+    write(backend, token, crate_name, &owners, Some(&sha)).await
+}
@@ -2,50 +2,135 @@ pub async fn execute(
     backend: &impl crate::Backend,
     token: &str,
     request: &crate::models::PublishRequest,
-) -> Result<(), reqwest::Error> {
+) -> Result<crate::models::PublishStatus, crate::models::RegistryError> {
+    crate::validation::validate_meta(&request.meta)?;
+
+    let user = backend.get_user(token).await?;
+    super::utils::retry_on_conflict(|| attempt(backend, token, request, &user)).await
+}
+
+/// adds the version to the index file as read at the start of the attempt
+async fn attempt(
+    backend: &impl crate::Backend,
+    token: &str,
+    request: &crate::models::PublishRequest,
+    user: &str,
+) -> Result<crate::models::PublishStatus, crate::models::RegistryError> {
     let crate_path = crate::get_crate_file_path(&request.meta.name);
-    let branch_name = format!("{}-{}", request.meta.name, request.meta.vers);
+    let branch_name =
+        super::utils::unique_branch_name(&format!("{}-{}", request.meta.name, request.meta.vers));
+    let message = format!("Adding {} {}", request.meta.name, request.meta.vers);
+
+    let owners = super::owners::read(backend, token, &request.meta.name).await?;
 
-    match backend.get_file(token, &crate_path).await {
+    let (versions, current_sha) = match backend.get_file(token, &crate_path).await {
         Ok((content, encoding, sha)) => {
             tracing::info!("'{}' already found! updating!", crate_path);
-            let mut versions = super::utils::read_versions(&content, &encoding);
+            super::owners::check(&owners, user, &request.meta.name)?;
+            let mut versions = super::utils::read_versions(&content, &encoding)?;
+
+            if let Some(existing) = versions.iter().find(|v| v.name != request.meta.name) {
+                return Err(crate::models::RegistryError::NameTaken(
+                    request.meta.name.clone(),
+                    existing.name.clone(),
+                ));
+            }
 
             let new_version: crate::models::PublishedVersion = request.into();
-            if versions.iter().any(|v| v.vers == new_version.vers) {
+            if let Some(existing) = versions.iter().find(|v| v.vers == new_version.vers) {
+                if existing.cksum == new_version.cksum {
+                    // a retried publish, there is nothing left to do
+                    tracing::info!(
+                        "{} version '{}' already published with identical contents",
+                        new_version.name,
+                        new_version.vers
+                    );
+                    return Ok(crate::models::PublishStatus::Published(versions));
+                }
                 tracing::warn!(
-                    "{} version '{}' already existing! !",
+                    "{} version '{}' already existing with different contents!",
                     new_version.name,
                     new_version.vers
                 );
-            } else {
-                versions.push(new_version);
+                return Err(crate::models::RegistryError::VersionExists(
+                    new_version.name,
+                    new_version.vers,
+                ));
             }
+            versions.push(new_version);
 
-            if let Err(e) = backend
-                .update_file(token, &crate_path, &branch_name, &versions, &sha)
-                .await
-            {
-                tracing::error!(
-                    "Failed to update file'{}' - deleting branch if exists!",
-                    crate_path
-                );
-                let _ = backend.delete_branch(token, &branch_name).await;
-                return Err(e);
-            }
+            (versions, Some(sha))
         }
-        Err(_) => {
+        Err(crate::models::BackendError::NotFound(_)) => {
             tracing::info!("'{}' not found! creating!", crate_path);
-            let initial_version: crate::models::PublishedVersion = request.into();
-            if let Err(e) = backend
-                .create_file(token, &crate_path, &branch_name, &initial_version)
-                .await
+            // the catalog may miss crates published by other instances
+            if let Some(existing) =
+                super::list::find_similar(backend, token, &request.meta.name).await?
             {
-                let _ = backend.delete_branch(token, &branch_name).await;
-                return Err(e);
+                return Err(crate::models::RegistryError::NameTaken(
+                    request.meta.name.clone(),
+                    existing,
+                ));
             }
+            // an owners file without index file is left behind by a publish whose index
+            // update failed, it doesn't reserve the name and gets replaced
+            let stale_sha = owners.map(|(owners, sha)| {
+                tracing::warn!(
+                    "replacing owners {:?} of unpublished crate '{}'",
+                    owners.users,
+                    request.meta.name
+                );
+                sha
+            });
+            let owners = crate::models::Owners {
+                users: vec![user.into()],
+            };
+            super::owners::write(
+                backend,
+                token,
+                &request.meta.name,
+                &owners,
+                stale_sha.as_deref(),
+            )
+            .await?;
+
+            (vec![request.into()], None)
         }
+        Err(e) => return Err(e.into()),
+    };
+
+    let content = super::utils::write_versions(&versions);
+    if backend.commit_mode() == crate::config::CommitMode::Review {
+        super::utils::write_branch(
+            backend,
+            token,
+            &crate_path,
+            &branch_name,
+            &content,
+            &message,
+            current_sha.as_deref(),
+        )
+        .await?;
+        let pull_request = super::utils::open_pull_request(backend, token, &branch_name).await?;
+        return Ok(crate::models::PublishStatus::Pending(Box::new(
+            crate::models::PendingVersion {
+                branch: branch_name,
+                pull_request,
+                meta: request.meta.clone(),
+            },
+        )));
     }
 
-    super::utils::merge_branch(backend, token, &branch_name).await
+    super::utils::write_file(
+        backend,
+        token,
+        &crate_path,
+        &branch_name,
+        &content,
+        &message,
+        current_sha.as_deref(),
+    )
+    .await?;
+
+    Ok(crate::models::PublishStatus::Published(versions))
 }
@@ -1,22 +1,82 @@
+/// attempts of a change that keeps conflicting with concurrent changes
+pub const MAX_ATTEMPTS: u32 = 5;
+/// wait before the second attempt, doubled for each further one
+const INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+
+static BRANCH_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// branch name that is not used by any earlier attempt, so leftovers of failed changes don't collide
+pub fn unique_branch_name(prefix: &str) -> String {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let counter = BRANCH_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    format!("{}-{}-{}", prefix, millis, counter)
+}
+
+/// Runs a change again as long as it conflicts with a concurrent change.
+///
+/// Each attempt has to read the index file again and reapply its change, as the
+/// conflict means the file it was based on is outdated.
+pub async fn retry_on_conflict<T, F, Fut>(mut attempt: F) -> Result<T, crate::models::RegistryError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, crate::models::RegistryError>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    for remaining in (0..MAX_ATTEMPTS).rev() {
+        match attempt().await {
+            Err(crate::models::RegistryError::Conflict(detail)) if remaining > 0 => {
+                tracing::warn!(
+                    "conflicting change, retrying in {}ms: {}",
+                    backoff.as_millis(),
+                    detail
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
 pub async fn merge_branch(
     backend: &impl crate::Backend,
     token: &str,
     branch_name: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), crate::models::BackendError> {
+    let pull_id = open_pull_request(backend, token, branch_name).await?;
+    match backend.merge_pull_request(token, pull_id).await {
+        Ok(_) => {
+            // the change is merged, a left over branch doesn't undo that
+            if let Err(e) = backend.delete_branch(token, branch_name).await {
+                tracing::warn!("failed to delete merged branch '{}': {}", branch_name, e);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("failed to merge pull request - deleting pull request and branch");
+            let _ = backend.delete_pull_request(token, pull_id).await;
+            let _ = backend.delete_branch(token, branch_name).await;
+            Err(e)
+        }
+    }
+}
+
+/// opens a pull request for a branch, which is deleted if that fails
+pub async fn open_pull_request(
+    backend: &impl crate::Backend,
+    token: &str,
+    branch_name: &str,
+) -> Result<u64, crate::models::BackendError> {
     tracing::info!("creating pull request for branch '{}'!", branch_name);
     match backend
         .create_pull_request(token, branch_name, branch_name)
         .await
     {
-        Ok(pull_id) => match backend.merge_pull_request(token, pull_id).await {
-            Ok(_) => backend.delete_branch(token, branch_name).await,
-            Err(e) => {
-                tracing::error!("failed to merge pull request - deleting pull request and branch");
-                let _ = backend.delete_pull_request(token, pull_id).await;
-                let _ = backend.delete_branch(token, branch_name).await;
-                Err(e)
-            }
-        },
+        Ok(pull_id) => Ok(pull_id),
         Err(e) => {
             tracing::error!("failed to create pull request - deleting branch");
             let _ = backend.delete_branch(token, branch_name).await;
@@ -25,17 +85,176 @@ pub async fn merge_branch(
     }
 }
 
-pub fn read_versions(content: &str, encoding: &str) -> Vec<crate::models::PublishedVersion> {
-    let content = content.replace("\n", "");
-    let content = if encoding == "base64" {
-        let content_bytes = base64::decode(content).unwrap();
-        String::from_utf8(content_bytes).unwrap()
-    } else {
-        content
+/// writes a file to a new branch, which is deleted if that fails,
+/// `current_sha` is the value returned by `get_file` or none for new files
+pub async fn write_branch(
+    backend: &impl crate::Backend,
+    token: &str,
+    path: &str,
+    branch_name: &str,
+    content: &str,
+    message: &str,
+    current_sha: Option<&str>,
+) -> Result<(), crate::models::BackendError> {
+    let result = match current_sha {
+        Some(sha) => {
+            backend
+                .update_file(token, path, branch_name, content, message, sha)
+                .await
+        }
+        None => {
+            backend
+                .create_file(token, path, branch_name, content, message)
+                .await
+        }
     };
+    if let Err(e) = result {
+        tracing::error!("Failed to write '{}' - deleting branch if exists!", path);
+        let _ = backend.delete_branch(token, branch_name).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// writes a file to the default branch, either directly or by merging a new branch,
+/// `current_sha` is the value returned by `get_file` or none for new files
+pub async fn write_file(
+    backend: &impl crate::Backend,
+    token: &str,
+    path: &str,
+    branch_name: &str,
+    content: &str,
+    message: &str,
+    current_sha: Option<&str>,
+) -> Result<(), crate::models::BackendError> {
+    if backend.commit_mode() == crate::config::CommitMode::Direct {
+        return backend
+            .commit_file(token, path, content, message, current_sha)
+            .await;
+    }
+
+    write_branch(
+        backend,
+        token,
+        path,
+        branch_name,
+        content,
+        message,
+        current_sha,
+    )
+    .await?;
+    merge_branch(backend, token, branch_name).await
+}
 
-    content
+/// decodes a file read from the backend, only base64 content is wrapped into lines
+pub fn decode_content(
+    content: &str,
+    encoding: &str,
+) -> Result<String, crate::models::BackendError> {
+    if encoding == "base64" {
+        let content_bytes = base64::decode(content.replace('\n', ""))
+            .map_err(|e| crate::models::BackendError::InvalidIndex(e.to_string()))?;
+        String::from_utf8(content_bytes)
+            .map_err(|e| crate::models::BackendError::InvalidIndex(e.to_string()))
+    } else {
+        Ok(content.into())
+    }
+}
+
+/// parses an index file, blank lines are skipped
+pub fn read_versions(
+    content: &str,
+    encoding: &str,
+) -> Result<Vec<crate::models::PublishedVersion>, crate::models::BackendError> {
+    decode_content(content, encoding)?
         .lines()
-        .map(|s| serde_json::from_str::<crate::models::PublishedVersion>(s).unwrap())
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str::<crate::models::PublishedVersion>(line).map_err(|e| {
+                crate::models::BackendError::InvalidIndex(format!("line {}: {}", i + 1, e))
+            })
+        })
         .collect()
 }
+
+pub fn write_versions(versions: &[crate::models::PublishedVersion]) -> String {
+    versions
+        .iter()
+        .map(|v| serde_json::to_string(v).unwrap())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_on_conflict() {
+        let mut attempts = 0;
+        let result = retry_on_conflict(|| {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                if attempt < 3 {
+                    Err(crate::models::RegistryError::Conflict("stale".into()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(3, result.unwrap());
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_on_conflict(|| {
+            attempts += 1;
+            async { Err(crate::models::RegistryError::Conflict("stale".into())) }
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(crate::models::RegistryError::Conflict(_))
+        ));
+        assert_eq!(MAX_ATTEMPTS, attempts);
+
+        assert_ne!(unique_branch_name("foo"), unique_branch_name("foo"));
+    }
+
+    const LINE: &str = r#"{"name":"foo","vers":"0.1.0","deps":[{"name":"bar","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal","registry":null,"package":null,"public":false}],"cksum":"abc","features":{},"yanked":false,"links":null,"v":2,"features2":{"serde":["dep:serde"]},"rust_version":"1.60","pubtime":"2022-01-01T00:00:00Z"}"#;
+
+    #[test]
+    fn test_read_write_versions_round_trip() {
+        let content = format!("{}\n\n  \n{}\n", LINE, LINE.replace("0.1.0", "0.2.0"));
+        let versions = read_versions(&content, "utf-8").unwrap();
+        assert_eq!(2, versions.len());
+        assert_eq!(Some(2), versions[0].v);
+        assert_eq!(Some("1.60".into()), versions[0].rust_version);
+
+        let written = write_versions(&versions);
+        let line = written.lines().next().unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(LINE).unwrap(),
+            serde_json::from_str::<serde_json::Value>(line).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_versions_reports_line() {
+        let content = format!("{}\n{{\"name\":\n", LINE);
+        match read_versions(&content, "utf-8") {
+            Err(crate::models::BackendError::InvalidIndex(detail)) => {
+                assert!(detail.starts_with("line 2:"), "{}", detail)
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(read_versions("not base64!", "base64").is_err());
+        assert_eq!(
+            1,
+            read_versions(&base64::encode(LINE), "base64")
+                .unwrap()
+                .len()
+        );
+    }
+}
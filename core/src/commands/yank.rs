@@ -2,43 +2,81 @@ pub async fn execute(
     backend: &impl crate::Backend,
     token: &str,
     request: &crate::models::YankRequest,
-) -> Result<(), reqwest::Error> {
+) -> Result<Vec<crate::models::PublishedVersion>, crate::models::RegistryError> {
+    let user = backend.get_user(token).await?;
+    super::utils::retry_on_conflict(|| attempt(backend, token, request, &user)).await
+}
+
+/// changes the yanked flag in the index file as read at the start of the attempt
+async fn attempt(
+    backend: &impl crate::Backend,
+    token: &str,
+    request: &crate::models::YankRequest,
+    user: &str,
+) -> Result<Vec<crate::models::PublishedVersion>, crate::models::RegistryError> {
     let crate_path = crate::get_crate_file_path(&request.name);
-    let branch_name = format!("{}-{}", request.name, request.vers);
+    let branch_name =
+        super::utils::unique_branch_name(&format!("{}-{}", request.name, request.vers));
+    let message = format!(
+        "{} {} {}",
+        if request.yank { "Yanking" } else { "Unyanking" },
+        request.name,
+        request.vers
+    );
 
-    match backend.get_file(token, &crate_path).await {
+    let owners = super::owners::read(backend, token, &request.name).await?;
+    super::owners::check(&owners, user, &request.name)?;
+
+    let versions = match backend.get_file(token, &crate_path).await {
         Ok((content, encoding, sha)) => {
-            let mut versions = super::utils::read_versions(&content, &encoding);
-            let mut version_found = false;
-            versions.iter_mut().for_each(|v| {
-                if v.name == request.name && v.vers == request.vers && v.yanked != request.yank {
-                    version_found = true;
-                    v.yanked = request.yank;
+            let mut versions = super::utils::read_versions(&content, &encoding)?;
+            let version = match versions
+                .iter_mut()
+                .find(|v| v.name.eq_ignore_ascii_case(&request.name) && v.vers == request.vers)
+            {
+                Some(version) => version,
+                None => {
+                    tracing::warn!("Crate {} has no version {}!", request.name, request.vers);
+                    return Err(crate::models::RegistryError::NotFound(format!(
+                        "crate '{}' has no version '{}'",
+                        request.name, request.vers
+                    )));
                 }
-            });
+            };
 
-            if version_found {
+            if version.yanked == request.yank {
                 tracing::warn!(
-                    "Crate {} has no version {} or yanked status won't change - skipping yank!",
+                    "Crate {} version {} yanked status won't change - skipping yank!",
                     request.name,
                     request.vers
                 );
-                return Ok(());
+                return Ok(versions);
             }
+            version.yanked = request.yank;
 
-            if backend
-                .update_file(token, &crate_path, &branch_name, &versions, &sha)
-                .await
-                .is_err()
-            {
-                let _ = backend.delete_branch(token, &branch_name).await;
-            }
+            let content = super::utils::write_versions(&versions);
+            super::utils::write_file(
+                backend,
+                token,
+                &crate_path,
+                &branch_name,
+                &content,
+                &message,
+                Some(&sha),
+            )
+            .await?;
+
+            versions
         }
-        Err(e) => {
+        Err(crate::models::BackendError::NotFound(_)) => {
             tracing::error!("crate {} not found", request.name);
-            return Err(e);
+            return Err(crate::models::RegistryError::NotFound(format!(
+                "crate '{}' not found",
+                request.name
+            )));
         }
-    }
+        Err(e) => return Err(e.into()),
+    };
 
-    super::utils::merge_branch(backend, token, &branch_name).await
+    Ok(versions)
 }
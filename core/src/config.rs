@@ -1,19 +1,37 @@
+pub mod azure_devops;
 pub mod backend;
+pub mod bitbucket;
 pub mod cargolifter;
+pub mod catalog;
 pub mod filesystem;
+pub mod git;
 pub mod gitea;
 pub mod github;
 pub mod gitlab;
+pub mod journal;
+pub mod mirror;
+pub mod publish;
+pub mod review;
 pub mod s3;
+pub mod service;
 pub mod storage;
 pub mod web;
 
+pub use azure_devops::*;
 pub use backend::*;
+pub use bitbucket::*;
 pub use cargolifter::*;
+pub use catalog::*;
 pub use filesystem::*;
+pub use git::*;
 pub use gitea::*;
 pub use github::*;
 pub use gitlab::*;
+pub use journal::*;
+pub use mirror::*;
+pub use publish::*;
+pub use review::*;
 pub use s3::*;
+pub use service::*;
 pub use storage::*;
 pub use web::*;
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct AzureDevOpsConfig {
+    /// defaults to https://dev.azure.com, the collection url for Azure DevOps Server
+    pub host: Option<String>,
+    pub organization: String,
+    pub project: String,
+    pub repo: String,
+    pub cargolifter_token: Option<String>,
+    pub default_branch: Option<String>,
+    /// defaults to `pull_request`
+    pub commit_mode: Option<crate::config::CommitMode>,
+}
@@ -2,7 +2,23 @@ use serde::Deserialize;
 
 #[derive(Clone, Deserialize, Debug)]
 pub enum BackendType {
+    AzureDevOps(crate::config::AzureDevOpsConfig),
+    Bitbucket(crate::config::BitbucketConfig),
+    Git(crate::config::GitConfig),
     Gitea(crate::config::GiteaConfig),
     Github(crate::config::GithubConfig),
     Gitlab(crate::config::GitlabConfig),
 }
+
+/// how changes reach the default branch of the index repository
+#[derive(Clone, Copy, Default, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitMode {
+    /// commit straight to the default branch
+    Direct,
+    /// commit to a new branch and merge it with a pull request
+    #[default]
+    PullRequest,
+    /// like `pull_request`, but publishing leaves the pull request open until someone merges it
+    Review,
+}
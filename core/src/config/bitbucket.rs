@@ -0,0 +1,16 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct BitbucketConfig {
+    /// defaults to https://api.bitbucket.org, required for Bitbucket Server
+    pub host: Option<String>,
+    /// workspace on Bitbucket Cloud, project key on Bitbucket Server
+    pub project: String,
+    pub repo: String,
+    /// use the REST api of Bitbucket Server (Data Center) instead of Bitbucket Cloud
+    pub server: Option<bool>,
+    pub cargolifter_token: Option<String>,
+    pub default_branch: Option<String>,
+    /// defaults to `pull_request`
+    pub commit_mode: Option<crate::config::CommitMode>,
+}
@@ -5,4 +5,10 @@ pub struct CargoLifterConfig {
     pub backend: crate::config::BackendType,
     pub web: crate::config::WebServiceConfig,
     pub storage: crate::config::StorageType,
+    pub catalog: Option<crate::config::CatalogConfig>,
+    pub mirror: Option<crate::config::MirrorConfig>,
+    pub review: Option<crate::config::ReviewConfig>,
+    pub service: Option<crate::config::ServiceConfig>,
+    pub journal: Option<crate::config::JournalConfig>,
+    pub publish: Option<crate::config::PublishConfig>,
 }
@@ -0,0 +1,6 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct CatalogConfig {
+    pub path: String,
+}
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct GitConfig {
+    /// path or url of the registry repository, e.g. a bare repository on disk
+    pub remote: String,
+    /// local clone used to prepare commits
+    pub work_dir: String,
+    /// maps the tokens used with `cargo login` to user names
+    pub users: HashMap<String, String>,
+    pub cargolifter_token: Option<String>,
+    pub default_branch: Option<String>,
+    /// defaults to `pull_request`
+    pub commit_mode: Option<crate::config::CommitMode>,
+    /// push branches before merging them, otherwise only the default branch is pushed
+    pub push_branches: Option<bool>,
+}
@@ -7,4 +7,6 @@ pub struct GithubConfig {
     pub repo: String,
     pub cargolifter_token: Option<String>,
     pub default_branch: Option<String>,
+    /// defaults to `pull_request`
+    pub commit_mode: Option<crate::config::CommitMode>,
 }
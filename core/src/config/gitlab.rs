@@ -6,4 +6,6 @@ pub struct GitlabConfig {
     pub project_id: usize,
     pub cargolifter_token: Option<String>,
     pub default_branch: Option<String>,
+    /// defaults to `pull_request`
+    pub commit_mode: Option<crate::config::CommitMode>,
 }
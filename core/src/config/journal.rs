@@ -0,0 +1,7 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct JournalConfig {
+    /// file unfinished operations are written to, they are only kept in memory if not set
+    pub path: String,
+}
@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct MirrorConfig {
+    /// url cargolifter is reachable at, used for the download url of the mirror index
+    pub public_url: String,
+    /// sparse index to mirror, defaults to crates.io
+    pub upstream_index: Option<String>,
+}
@@ -0,0 +1,27 @@
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize, Debug)]
+pub struct PublishConfig {
+    /// bytes the body of a publish request may have
+    pub max_upload_size: Option<u64>,
+    /// bytes a compressed crate file may have
+    pub max_crate_size: Option<u64>,
+    /// bytes the files of a crate may have once unpacked
+    pub max_unpacked_size: Option<u64>,
+}
+
+impl PublishConfig {
+    /// defaults to the crate size and some room for the metadata
+    pub fn max_upload_size(&self) -> u64 {
+        self.max_upload_size
+            .unwrap_or_else(|| self.max_crate_size() + 1024 * 1024)
+    }
+
+    pub fn max_crate_size(&self) -> u64 {
+        self.max_crate_size.unwrap_or(10 * 1024 * 1024)
+    }
+
+    pub fn max_unpacked_size(&self) -> u64 {
+        self.max_unpacked_size.unwrap_or(512 * 1024 * 1024)
+    }
+}
@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct ReviewConfig {
+    /// secret configured for the webhook at the forge
+    pub webhook_secret: String,
+    /// file pending versions are persisted to, they are only kept in memory if not set
+    pub path: Option<String>,
+}
@@ -0,0 +1,19 @@
+use serde::Deserialize;
+
+#[derive(Clone, Default, Deserialize, Debug)]
+pub struct ServiceConfig {
+    /// commands handled concurrently, commands of the same crate are never handled in parallel
+    pub workers: Option<usize>,
+    /// commands waiting in each queue, further commands are rejected
+    pub queue_size: Option<usize>,
+}
+
+impl ServiceConfig {
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or(4).max(1)
+    }
+
+    pub fn queue_size(&self) -> usize {
+        self.queue_size.unwrap_or(16).max(1)
+    }
+}
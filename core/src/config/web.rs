@@ -3,4 +3,6 @@ use serde::Deserialize;
 #[derive(Deserialize, Debug)]
 pub struct WebServiceConfig {
     pub port: i32,
+    /// seconds index files served via the sparse protocol are cached
+    pub index_cache_ttl: Option<u64>,
 }
@@ -0,0 +1,560 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::models::BackendError;
+
+/// number of ended operations after which the file is rewritten without them
+const COMPACT_AFTER: usize = 1000;
+
+/// progress of an operation, recorded before the step is taken if it can't be undone otherwise
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Begin(String),
+    Staged(StagedFile),
+    /// checksum of the staged crate file, recorded once it is completely uploaded
+    Uploaded(String),
+    Committed,
+    Branch(String),
+    PullRequest(u64),
+    PullRequestClosed(u64),
+    BranchDeleted(String),
+    End,
+}
+
+/// crate file uploaded to the staging area by a publish transaction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagedFile {
+    pub name: String,
+    pub vers: String,
+    pub staging_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    operation: u64,
+    step: Step,
+}
+
+/// operation that was started but not finished before the journal was opened
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Operation {
+    pub id: u64,
+    pub steps: Vec<Step>,
+}
+
+impl Operation {
+    pub fn description(&self) -> &str {
+        self.steps
+            .iter()
+            .find_map(|step| match step {
+                Step::Begin(description) => Some(description.as_str()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// crate file uploaded to the staging area
+    pub fn staged(&self) -> Option<&StagedFile> {
+        self.steps.iter().find_map(|step| match step {
+            Step::Staged(staged) => Some(staged),
+            _ => None,
+        })
+    }
+
+    /// checksum of the staged crate file, tells whether the indexed version is the staged one
+    pub fn uploaded(&self) -> Option<&str> {
+        self.steps.iter().find_map(|step| match step {
+            Step::Uploaded(cksum) => Some(cksum.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn committed(&self) -> bool {
+        self.steps.contains(&Step::Committed)
+    }
+
+    /// branches that may still exist
+    pub fn branches(&self) -> Vec<&str> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Branch(branch)
+                    if !self.steps.contains(&Step::BranchDeleted(branch.clone())) =>
+                {
+                    Some(branch.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// pull requests that may still be open
+    pub fn pull_requests(&self) -> Vec<u64> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::PullRequest(id) if !self.steps.contains(&Step::PullRequestClosed(*id)) => {
+                    Some(*id)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Append only log of operations that change the index or the storage.
+///
+/// Each step is written as a JSON line and synced before the next one is taken,
+/// so after a crash the operations lacking an end show what has to be rolled
+/// back or finished. Writes happen on a blocking thread. Once enough operations
+/// ended, the file is rewritten with the open ones only. Without a path nothing
+/// is recorded.
+#[derive(Debug, Default)]
+pub struct Journal {
+    path: Option<PathBuf>,
+    log: Arc<Mutex<Log>>,
+    unfinished: Mutex<Vec<Operation>>,
+    next: AtomicU64,
+}
+
+/// file of the journal with the steps of the operations that didn't end yet
+#[derive(Debug, Default)]
+struct Log {
+    file: Option<std::fs::File>,
+    open: BTreeMap<u64, Vec<Step>>,
+    /// operations that ended since the file was last rewritten
+    ended: usize,
+    compact_after: usize,
+}
+
+impl Log {
+    fn append(&mut self, path: &Path, record: Record) -> std::io::Result<()> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        file.sync_data()?;
+
+        if record.step == Step::End {
+            self.open.remove(&record.operation);
+            self.ended += 1;
+        } else {
+            self.open
+                .entry(record.operation)
+                .or_default()
+                .push(record.step);
+        }
+        if self.ended >= self.compact_after {
+            self.file = Some(Journal::rewrite(path, &self.operations())?);
+            self.ended = 0;
+        }
+        Ok(())
+    }
+
+    fn operations(&self) -> Vec<Operation> {
+        self.open
+            .iter()
+            .map(|(id, steps)| Operation {
+                id: *id,
+                steps: steps.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reads unfinished operations and rewrites the file with only them
+    pub fn open(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let mut operations = BTreeMap::<u64, Operation>::new();
+        if let Ok(file) = std::fs::File::open(&path) {
+            for (i, line) in std::io::BufReader::new(file).lines().enumerate() {
+                // a crash may leave the last line incomplete
+                match line.map_err(|e| e.to_string()).and_then(|line| {
+                    serde_json::from_str::<Record>(&line).map_err(|e| e.to_string())
+                }) {
+                    Ok(record) => {
+                        let operation = operations.entry(record.operation).or_default();
+                        operation.id = record.operation;
+                        operation.steps.push(record.step);
+                    }
+                    Err(e) => tracing::warn!(
+                        "skipping line {} of journal '{}': {}",
+                        i + 1,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+        let next = operations.keys().next_back().map(|id| id + 1).unwrap_or(0);
+        let unfinished = operations
+            .into_values()
+            .filter(|operation| !operation.steps.contains(&Step::End))
+            .collect::<Vec<_>>();
+
+        let file = Self::rewrite(&path, &unfinished)
+            .map_err(|e| tracing::error!("failed to write journal '{}': {}", path.display(), e))
+            .ok();
+        let log = Log {
+            file,
+            open: unfinished
+                .iter()
+                .map(|operation| (operation.id, operation.steps.clone()))
+                .collect(),
+            ended: 0,
+            compact_after: COMPACT_AFTER,
+        };
+
+        Self {
+            path: Some(path),
+            log: Arc::new(Mutex::new(log)),
+            unfinished: Mutex::new(unfinished),
+            next: AtomicU64::new(next),
+        }
+    }
+
+    fn rewrite(path: &Path, operations: &[Operation]) -> std::io::Result<std::fs::File> {
+        let temp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        for operation in operations {
+            for step in &operation.steps {
+                let record = Record {
+                    operation: operation.id,
+                    step: step.clone(),
+                };
+                writeln!(file, "{}", serde_json::to_string(&record)?)?;
+            }
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        std::fs::OpenOptions::new().append(true).open(path)
+    }
+
+    /// operations found unfinished when the journal was opened, each is only returned once
+    pub fn take_unfinished(&self, filter: impl Fn(&Operation) -> bool) -> Vec<Operation> {
+        let mut unfinished = self.unfinished.lock().unwrap();
+        let (taken, kept) = unfinished
+            .drain(..)
+            .partition(|operation| filter(operation));
+        *unfinished = kept;
+        taken
+    }
+
+    pub async fn begin(&self, description: &str) -> u64 {
+        let operation = self.next.fetch_add(1, Ordering::Relaxed);
+        self.record(operation, Step::Begin(description.into()))
+            .await;
+        operation
+    }
+
+    pub async fn record(&self, operation: u64, step: Step) {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let log = self.log.clone();
+        let record = Record { operation, step };
+        let result = tokio::task::spawn_blocking(move || {
+            let result = log.lock().unwrap().append(&path, record);
+            result.map_err(|e| (path, e))
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err((path, e))) => {
+                tracing::error!("failed to write journal '{}': {}", path.display(), e)
+            }
+            Err(e) => tracing::error!("failed to write journal: {}", e),
+        }
+    }
+
+    pub async fn end(&self, operation: u64) {
+        self.record(operation, Step::End).await;
+    }
+
+    /// backend recording its branches and pull requests as steps of `operation`
+    pub fn backend<'a, T: crate::Backend + Sync + Send>(
+        &'a self,
+        backend: &'a T,
+        operation: u64,
+    ) -> JournaledBackend<'a, T> {
+        JournaledBackend {
+            backend,
+            journal: self,
+            operation,
+        }
+    }
+}
+
+/// Rolls back branches and pull requests of unfinished operations.
+///
+/// Branches of versions waiting for review are kept as their pull request is
+/// meant to stay open. Needs the cargolifter token of the backend.
+pub async fn rollback(
+    journal: &Journal,
+    backend: &impl crate::Backend,
+    keep_branch: impl Fn(&str) -> bool,
+) {
+    for operation in journal.take_unfinished(|operation| operation.staged().is_none()) {
+        tracing::warn!(
+            "rolling back unfinished operation {} '{}'",
+            operation.id,
+            operation.description()
+        );
+        let token = match backend.cargolifter_token() {
+            Some(token) => token,
+            None => {
+                tracing::error!(
+                    "no token to roll back operation {}, branches {:?} may be left over",
+                    operation.id,
+                    operation.branches()
+                );
+                continue;
+            }
+        };
+        let branches = operation
+            .branches()
+            .into_iter()
+            .filter(|branch| !keep_branch(branch))
+            .collect::<Vec<_>>();
+        if branches.len() == operation.branches().len() {
+            for id in operation.pull_requests() {
+                if let Err(e) = backend.delete_pull_request(&token, id).await {
+                    tracing::info!("failed to delete pull request {}: {}", id, e);
+                }
+            }
+        }
+        for branch in branches {
+            if let Err(e) = backend.delete_branch(&token, branch).await {
+                tracing::info!("failed to delete branch '{}': {}", branch, e);
+            }
+        }
+        journal.end(operation.id).await;
+    }
+}
+
+pub struct JournaledBackend<'a, T: crate::Backend + Sync + Send> {
+    backend: &'a T,
+    journal: &'a Journal,
+    operation: u64,
+}
+
+#[async_trait]
+impl<'a, T: crate::Backend + Sync + Send> crate::Backend for JournaledBackend<'a, T> {
+    async fn get_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+    ) -> Result<(String, String, String), BackendError> {
+        self.backend.get_file(token, crate_path).await
+    }
+
+    async fn create_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<(), BackendError> {
+        self.journal
+            .record(self.operation, Step::Branch(branch_name.into()))
+            .await;
+        self.backend
+            .create_file(token, crate_path, branch_name, content, message)
+            .await
+    }
+
+    async fn update_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        branch_name: &str,
+        content: &str,
+        message: &str,
+        current_sha: &str,
+    ) -> Result<(), BackendError> {
+        self.journal
+            .record(self.operation, Step::Branch(branch_name.into()))
+            .await;
+        self.backend
+            .update_file(
+                token,
+                crate_path,
+                branch_name,
+                content,
+                message,
+                current_sha,
+            )
+            .await
+    }
+
+    async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), BackendError> {
+        self.backend.delete_branch(token, branch_name).await?;
+        self.journal
+            .record(self.operation, Step::BranchDeleted(branch_name.into()))
+            .await;
+        Ok(())
+    }
+
+    async fn create_pull_request(
+        &self,
+        token: &str,
+        title: &str,
+        branch_name: &str,
+    ) -> Result<u64, BackendError> {
+        let id = self
+            .backend
+            .create_pull_request(token, title, branch_name)
+            .await?;
+        self.journal
+            .record(self.operation, Step::PullRequest(id))
+            .await;
+        Ok(id)
+    }
+
+    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), BackendError> {
+        self.backend.merge_pull_request(token, id).await?;
+        self.journal
+            .record(self.operation, Step::PullRequestClosed(id))
+            .await;
+        Ok(())
+    }
+
+    async fn delete_pull_request(&self, token: &str, id: u64) -> Result<(), BackendError> {
+        self.backend.delete_pull_request(token, id).await?;
+        self.journal
+            .record(self.operation, Step::PullRequestClosed(id))
+            .await;
+        Ok(())
+    }
+
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), BackendError> {
+        self.backend
+            .commit_file(token, crate_path, content, message, current_sha)
+            .await
+    }
+
+    fn commit_mode(&self) -> crate::config::CommitMode {
+        self.backend.commit_mode()
+    }
+
+    async fn list_files(&self, token: &str) -> Result<Vec<String>, BackendError> {
+        self.backend.list_files(token).await
+    }
+
+    async fn get_user(&self, token: &str) -> Result<String, BackendError> {
+        self.backend.get_user(token).await
+    }
+
+    fn cargolifter_token(&self) -> Option<String> {
+        self.backend.cargolifter_token()
+    }
+
+    fn webhook_repository(&self) -> Option<String> {
+        self.backend.webhook_repository()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reopen_keeps_unfinished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+
+        let journal = Journal::open(path);
+        let finished = journal.begin("finished").await;
+        journal.record(finished, Step::Branch("a".into())).await;
+        journal.end(finished).await;
+        let crashed = journal.begin("crashed").await;
+        journal.record(crashed, Step::Branch("b".into())).await;
+        journal.record(crashed, Step::PullRequest(7)).await;
+        journal.record(crashed, Step::Branch("c".into())).await;
+        journal
+            .record(crashed, Step::BranchDeleted("c".into()))
+            .await;
+        drop(journal);
+        // the crash interrupted writing a step
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        write!(file, "{{\"operation\":1,\"st").unwrap();
+        drop(file);
+
+        let journal = Journal::open(path);
+        assert_eq!(2, journal.begin("next").await);
+        let unfinished = journal.take_unfinished(|_| true);
+        assert_eq!(1, unfinished.len());
+        assert_eq!("crashed", unfinished[0].description());
+        assert_eq!(vec!["b"], unfinished[0].branches());
+        assert_eq!(vec![7], unfinished[0].pull_requests());
+        assert!(journal.take_unfinished(|_| true).is_empty());
+
+        let content = std::fs::read_to_string(path).unwrap();
+        assert!(!content.contains("finished"));
+        assert!(content.contains("crashed"));
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+
+        let journal = Journal::open(path);
+        journal.log.lock().unwrap().compact_after = 2;
+        let running = journal.begin("running").await;
+        journal.record(running, Step::Branch("a".into())).await;
+        for description in ["first", "second"] {
+            let operation = journal.begin(description).await;
+            journal.end(operation).await;
+        }
+
+        // only the running operation is left after the second one ended
+        let content = std::fs::read_to_string(path).unwrap();
+        assert_eq!(2, content.lines().count());
+        assert!(!content.contains("first"));
+        let operation = journal.begin("third").await;
+        journal.record(running, Step::Committed).await;
+        drop(journal);
+
+        let journal = Journal::open(path);
+        let unfinished = journal.take_unfinished(|_| true);
+        assert_eq!(2, unfinished.len());
+        assert_eq!(running, unfinished[0].id);
+        assert!(unfinished[0].committed());
+        assert_eq!(vec!["a"], unfinished[0].branches());
+        assert_eq!(operation, unfinished[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_without_path() {
+        let journal = Journal::new();
+        let operation = journal.begin("publish").await;
+        journal.record(operation, Step::Committed).await;
+        journal.end(operation).await;
+        assert!(journal.take_unfinished(|_| true).is_empty());
+    }
+}
@@ -1,28 +1,109 @@
+pub mod catalog;
 pub mod commands;
 pub mod config;
+pub mod journal;
+pub mod metrics;
+pub mod migration;
+pub mod mirror;
 pub mod models;
+mod persist;
+pub mod review;
+pub mod tarball;
+pub mod transaction;
+pub mod upload;
+pub mod validation;
 
 use async_trait::async_trait;
-use commands::{is_published, publish, yank};
-use models::PublishedVersion;
+use catalog::Catalog;
+use commands::{owners, publish, yank};
+use journal::Journal;
+use review::PendingVersions;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub enum BackendCommand {
     Publish(
         String,
         Box<models::PublishRequest>,
-        tokio::sync::oneshot::Sender<bool>,
+        tokio::sync::oneshot::Sender<Result<models::PublishStatus, models::RegistryError>>,
     ),
     Yank(
         String,
         Box<models::YankRequest>,
-        tokio::sync::oneshot::Sender<bool>,
+        tokio::sync::oneshot::Sender<Result<(), models::RegistryError>>,
     ),
-    IsVersionPublished(String, String, String, tokio::sync::oneshot::Sender<bool>),
+    /// resolves the user of a token, so requests are authenticated before their body is read
+    Authenticate(
+        String,
+        tokio::sync::oneshot::Sender<Result<String, models::RegistryError>>,
+    ),
+    GetIndexFile(
+        Option<String>,
+        String,
+        tokio::sync::oneshot::Sender<Result<models::IndexFile, models::RegistryError>>,
+    ),
+    Search(
+        Box<models::SearchRequest>,
+        tokio::sync::oneshot::Sender<models::SearchResponse>,
+    ),
+    ListOwners(
+        String,
+        String,
+        tokio::sync::oneshot::Sender<Result<models::Owners, models::RegistryError>>,
+    ),
+    AddOwners(
+        String,
+        String,
+        Vec<String>,
+        tokio::sync::oneshot::Sender<Result<(), models::RegistryError>>,
+    ),
+    RemoveOwners(
+        String,
+        String,
+        Vec<String>,
+        tokio::sync::oneshot::Sender<Result<(), models::RegistryError>>,
+    ),
+    /// finalizes a pending version once its pull request got merged or closed,
+    /// replies with the version if the event belonged to one
+    Review(
+        models::ReviewEvent,
+        tokio::sync::oneshot::Sender<Result<Option<models::PendingVersion>, models::RegistryError>>,
+    ),
+    Metrics(tokio::sync::oneshot::Sender<models::ServiceMetrics>),
+}
+
+impl BackendCommand {
+    /// answers the command with an error instead of handling it
+    fn reject(self, error: models::RegistryError) {
+        let sent = match self {
+            BackendCommand::Publish(_, _, sender) => sender.send(Err(error)).is_ok(),
+            BackendCommand::Yank(_, _, sender)
+            | BackendCommand::AddOwners(_, _, _, sender)
+            | BackendCommand::RemoveOwners(_, _, _, sender) => sender.send(Err(error)).is_ok(),
+            BackendCommand::Authenticate(_, sender) => sender.send(Err(error)).is_ok(),
+            BackendCommand::GetIndexFile(_, _, sender) => sender.send(Err(error)).is_ok(),
+            BackendCommand::ListOwners(_, _, sender) => sender.send(Err(error)).is_ok(),
+            BackendCommand::Review(_, sender) => sender.send(Err(error)).is_ok(),
+            // answered without a queue, so they are never rejected
+            BackendCommand::Search(_, _) | BackendCommand::Metrics(_) => true,
+        };
+        if !sent {
+            tracing::error!("Failed to send rejection!");
+        }
+    }
 }
 
 pub enum StorageCommand {
     Get(models::StorageGetRequest),
     Put(models::StoragePutRequest),
+    Delete(models::StorageFileRequest),
+    GetMirror(models::StorageGetRequest),
+    PutMirror(models::StoragePutRequest),
+    AppendStaged(models::StagedPutRequest),
+    CompleteStaged(models::StagedFileRequest),
+    Promote(models::StagedFileRequest),
+    DeleteStaged(models::StagedFileRequest),
 }
 
 #[async_trait]
@@ -31,37 +112,70 @@ pub trait Backend {
         &self,
         token: &str,
         crate_path: &str,
-    ) -> Result<(String, String, String), reqwest::Error>;
+    ) -> Result<(String, String, String), models::BackendError>;
 
     async fn create_file(
         &self,
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        version: &PublishedVersion,
-    ) -> Result<(), reqwest::Error>;
+        content: &str,
+        message: &str,
+    ) -> Result<(), models::BackendError>;
 
     async fn update_file(
         &self,
         token: &str,
         crate_path: &str,
         branch_name: &str,
-        versions: &[PublishedVersion],
+        content: &str,
+        message: &str,
         current_sha: &str,
-    ) -> Result<(), reqwest::Error>;
+    ) -> Result<(), models::BackendError>;
 
-    async fn delete_branch(&self, token: &str, branch_name: &str) -> Result<(), reqwest::Error>;
+    async fn delete_branch(
+        &self,
+        token: &str,
+        branch_name: &str,
+    ) -> Result<(), models::BackendError>;
 
     async fn create_pull_request(
         &self,
         token: &str,
         title: &str,
         branch_name: &str,
-    ) -> Result<u64, reqwest::Error>;
+    ) -> Result<u64, models::BackendError>;
+
+    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), models::BackendError>;
+
+    async fn delete_pull_request(&self, token: &str, id: u64) -> Result<(), models::BackendError>;
+
+    /// commits straight to the default branch, fails with a conflict if `current_sha` as returned
+    /// by `get_file` is outdated or, if none, the file already exists
+    async fn commit_file(
+        &self,
+        token: &str,
+        crate_path: &str,
+        content: &str,
+        message: &str,
+        current_sha: Option<&str>,
+    ) -> Result<(), models::BackendError>;
+
+    /// whether changes are committed directly or merged with pull requests
+    fn commit_mode(&self) -> config::CommitMode;
+
+    /// paths of all files on the default branch
+    async fn list_files(&self, token: &str) -> Result<Vec<String>, models::BackendError>;
+
+    /// login of the user the token belongs to
+    async fn get_user(&self, token: &str) -> Result<String, models::BackendError>;
 
-    async fn merge_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error>;
+    /// configured token of cargolifter itself, used when a request carries no token
+    fn cargolifter_token(&self) -> Option<String>;
 
-    async fn delete_pull_request(&self, token: &str, id: u64) -> Result<(), reqwest::Error>;
+    /// index repository as identified in pull request webhooks, `None` if the
+    /// forge sends no webhooks
+    fn webhook_repository(&self) -> Option<String>;
 }
 
 #[async_trait]
@@ -77,98 +191,534 @@ pub trait Storage {
         crate_version: &str,
         data: &[u8],
     ) -> Result<(), models::StorageError>;
+    /// removes a crate file whose version never made it into the index
+    async fn delete(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+    ) -> Result<(), models::StorageError>;
+
+    /// crate file fetched from the upstream registry, see `get_mirror_storage_key`
+    async fn get_mirror(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+    ) -> Result<Vec<u8>, models::StorageError>;
+    async fn put_mirror(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        data: &[u8],
+    ) -> Result<(), models::StorageError>;
+
+    /// appends a chunk to a crate file that is not published yet, the first one
+    /// creates it, see `get_staging_storage_key`
+    async fn append_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+        data: &[u8],
+    ) -> Result<(), models::StorageError>;
+    /// called once all chunks of a staged crate file are appended
+    async fn complete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), models::StorageError>;
+    /// renames a staged crate file to its final key once the version is in the index
+    async fn promote(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), models::StorageError>;
+    async fn delete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), models::StorageError>;
 }
 
+/// index directory of a crate, names are lowercased as cargo expects lowercase index paths
 pub fn get_crate_path(name: &str) -> String {
-    match name.len() {
+    get_crate_prefix(&name.to_ascii_lowercase())
+}
+
+/// index directory of a crate keeping the case of the name
+pub fn get_crate_prefix(name: &str) -> String {
+    let prefix = |start: usize, len: usize| name.chars().skip(start).take(len).collect::<String>();
+    match name.chars().count() {
         1 => "1".into(),
         2 => "2".into(),
-        3 => format!("3/{}", name[0..1].to_string()),
-        _ => {
-            format!("{}/{}", name[0..2].to_string(), name[2..4].to_string())
-        }
+        3 => format!("3/{}", prefix(0, 1)),
+        _ => format!("{}/{}", prefix(0, 2), prefix(2, 2)),
     }
 }
 
 pub fn get_crate_file_path(name: &str) -> String {
-    format!("{}/{}", get_crate_path(name), name)
+    format!("{}/{}", get_crate_path(name), name.to_ascii_lowercase())
+}
+
+/// storage key of a crate file, unique per crate name and version
+pub fn get_crate_storage_key(name: &str, version: &str) -> String {
+    format!("{}/{}.crate", get_crate_file_path(name), version)
+}
+
+/// storage key of a crate file fetched from the upstream registry
+pub fn get_mirror_storage_key(name: &str, version: &str) -> String {
+    format!("mirror/{}", get_crate_storage_key(name, version))
+}
+
+/// storage key of a crate file uploaded for a version that is not in the index yet,
+/// the staging id keeps concurrent uploads of the same version apart
+pub fn get_staging_storage_key(name: &str, version: &str, staging_id: &str) -> String {
+    format!(
+        "staging/{}/{}",
+        staging_id,
+        get_crate_storage_key(name, version)
+    )
+}
+
+pub fn get_owners_file_path(name: &str) -> String {
+    format!("owners/{}", get_crate_file_path(name))
 }
 
 pub struct BackendService<T: Backend + Sync + Send> {
-    backend: T,
+    worker: Worker<T>,
+    config: config::ServiceConfig,
 }
 
 impl<T: Backend + Sync + Send + 'static> BackendService<T> {
-    pub fn new(backend: T) -> Self {
-        Self { backend }
+    pub fn new(
+        backend: T,
+        catalog: Catalog,
+        pending: PendingVersions,
+        config: config::ServiceConfig,
+        journal: Arc<Journal>,
+    ) -> Self {
+        Self {
+            worker: Worker {
+                backend: Arc::new(backend),
+                catalog: Arc::new(Mutex::new(catalog)),
+                pending: Arc::new(Mutex::new(pending)),
+                metrics: Arc::new(metrics::BackendMetrics::new(config.workers())),
+                journal,
+            },
+            config,
+        }
     }
 
+    /// Starts the configured number of workers and a dispatcher feeding them.
+    ///
+    /// Operations the journal reports as unfinished are rolled back and the
+    /// catalog is rebuilt from the index before the first command is handled.
+    ///
+    /// Commands of a crate always go to the same worker, so they are handled in
+    /// the order they were sent while other crates are handled in parallel. A
+    /// command finding the queue of its worker full is rejected right away.
     pub fn run(
         self,
     ) -> (
         tokio::task::JoinHandle<()>,
         tokio::sync::mpsc::Sender<BackendCommand>,
     ) {
-        let (sender, mut receiver) = tokio::sync::mpsc::channel::<BackendCommand>(16);
+        let workers = self.config.workers();
+        let queue_size = self.config.queue_size();
+
+        let queues = (0..workers)
+            .map(|index| {
+                let (sender, mut receiver) =
+                    tokio::sync::mpsc::channel::<(BackendCommand, Instant)>(queue_size);
+                let worker = self.worker.clone();
+                tokio::spawn(async move {
+                    while let Some((command, queued_at)) = receiver.recv().await {
+                        worker.metrics.dequeued(index, queued_at.elapsed());
+                        let started_at = Instant::now();
+                        // a panicking command must not take the queue of its worker down
+                        let handler = worker.clone();
+                        let result = tokio::spawn(async move { handler.handle(command).await });
+                        if let Err(e) = result.await {
+                            tracing::error!("Worker {} failed to handle a command: {}", index, e);
+                        }
+                        worker.metrics.handled(started_at.elapsed());
+                    }
+                });
+                sender
+            })
+            .collect::<Vec<_>>();
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<BackendCommand>(queue_size);
         let handle = tokio::spawn(async move {
-            loop {
-                match receiver.recv().await {
-                    Some(command) => match command {
-                        BackendCommand::Publish(token, req, sender) => {
-                            match publish::execute(&self.backend, &token, &req).await {
-                                Ok(_) => {
-                                    if sender.send(true).is_err() {
-                                        tracing::error!("Failed to send publish result!");
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::error!("Publish failed: {}", e);
-                                    if sender.send(false).is_err() {
-                                        tracing::error!("Failed to send publish result!");
-                                    }
-                                }
-                            }
+            // a panicking backend call must not keep the dispatcher from starting
+            let worker = self.worker.clone();
+            let rollback = tokio::spawn(async move {
+                let pending = worker.pending.clone();
+                journal::rollback(&worker.journal, worker.backend.as_ref(), |branch| {
+                    pending.lock().unwrap().get(branch).is_some()
+                })
+                .await
+            });
+            if let Err(e) = rollback.await {
+                tracing::error!("Rollback of unfinished operations failed: {}", e);
+            }
+            // reading every crate of a large index takes a while, commands are served meanwhile
+            let worker = self.worker.clone();
+            tokio::spawn(async move { worker.refresh_catalog().await });
+
+            while let Some(command) = receiver.recv().await {
+                let key = match &command {
+                    BackendCommand::Publish(_, req, _) => req.meta.name.clone(),
+                    BackendCommand::Yank(_, req, _) => req.name.clone(),
+                    BackendCommand::ListOwners(_, name, _)
+                    | BackendCommand::AddOwners(_, name, _, _)
+                    | BackendCommand::RemoveOwners(_, name, _, _) => name.clone(),
+                    BackendCommand::Review(event, _) => {
+                        let pending = self.worker.pending.lock().unwrap();
+                        match pending.find_pull_request(event.pull_request) {
+                            Some(pending) => pending.meta.name.clone(),
+                            None => event.pull_request.to_string(),
                         }
-                        BackendCommand::Yank(token, req, sender) => {
-                            match yank::execute(&self.backend, &token, &req).await {
-                                Ok(_) => {
-                                    if sender.send(true).is_err() {
-                                        tracing::error!("Failed to send yank result!");
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::error!("Yank/Unyank failed: {}", e);
-                                    if sender.send(false).is_err() {
-                                        tracing::error!("Failed to send yank result!");
-                                    }
-                                }
-                            }
+                    }
+                    // reading the index doesn't change anything, so it is not ordered
+                    BackendCommand::Authenticate(_, _) | BackendCommand::GetIndexFile(_, _, _) => {
+                        let worker = self.worker.clone();
+                        tokio::spawn(async move { worker.handle(command).await });
+                        continue;
+                    }
+                    BackendCommand::Search(_, _) | BackendCommand::Metrics(_) => {
+                        self.worker.handle(command).await;
+                        continue;
+                    }
+                };
+
+                // waiting for a full queue would hold up the commands of all other crates
+                let index = worker_index(&key, workers);
+                self.worker.metrics.enqueued(index);
+                match queues[index].try_send((command, Instant::now())) {
+                    Ok(()) => {}
+                    Err(tokio::sync::mpsc::error::TrySendError::Full((command, _))) => {
+                        tracing::warn!("Queue of worker {} is full!", index);
+                        self.worker.metrics.dequeued(index, Duration::ZERO);
+                        command.reject(models::RegistryError::QueueFull(key));
+                    }
+                    Err(tokio::sync::mpsc::error::TrySendError::Closed((command, _))) => {
+                        tracing::error!("Worker {} stopped!", index);
+                        self.worker.metrics.dequeued(index, Duration::ZERO);
+                        command.reject(models::RegistryError::Internal(
+                            "backend worker stopped".into(),
+                        ));
+                    }
+                }
+            }
+            tracing::warn!("Backend service stopped!");
+        });
+
+        (handle, sender)
+    }
+}
+
+/// worker handling a crate, names only differing in case or `-` and `_` share one
+fn worker_index(crate_name: &str, workers: usize) -> usize {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    validation::canonical_name(crate_name).hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+/// state shared by all workers of a backend service
+struct Worker<T: Backend + Sync + Send> {
+    backend: Arc<T>,
+    catalog: Arc<Mutex<Catalog>>,
+    pending: Arc<Mutex<PendingVersions>>,
+    metrics: Arc<metrics::BackendMetrics>,
+    journal: Arc<Journal>,
+}
+
+impl<T: Backend + Sync + Send> Clone for Worker<T> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            catalog: self.catalog.clone(),
+            pending: self.pending.clone(),
+            metrics: self.metrics.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+}
+
+impl<T: Backend + Sync + Send + 'static> Worker<T> {
+    async fn handle(&self, command: BackendCommand) {
+        match command {
+            BackendCommand::Publish(token, req, sender) => {
+                let operation = self
+                    .journal
+                    .begin(&format!("publish {} {}", req.meta.name, req.meta.vers))
+                    .await;
+                let similar = self
+                    .catalog
+                    .lock()
+                    .unwrap()
+                    .find_similar(&req.meta.name)
+                    .map(String::from);
+                let pending = self
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .find(&req.meta.name, &req.meta.vers)
+                    .is_some();
+                let result = match similar {
+                    Some(existing) => Err(models::RegistryError::NameTaken(
+                        req.meta.name.clone(),
+                        existing,
+                    )),
+                    None if pending => Err(models::RegistryError::PendingReview(
+                        req.meta.name.clone(),
+                        req.meta.vers.clone(),
+                    )),
+                    None => {
+                        let backend = self.journal.backend(self.backend.as_ref(), operation);
+                        publish::execute(&backend, &token, &req).await
+                    }
+                };
+                let result = match result {
+                    Ok(models::PublishStatus::Published(versions)) => {
+                        self.catalog
+                            .lock()
+                            .unwrap()
+                            .update(&versions, Some(&req.meta));
+                        Ok(models::PublishStatus::Published(versions))
+                    }
+                    Ok(models::PublishStatus::Pending(pending)) => {
+                        tracing::info!(
+                            "'{}' version '{}' waits for review of pull request {}",
+                            pending.meta.name,
+                            pending.meta.vers,
+                            pending.pull_request
+                        );
+                        self.pending.lock().unwrap().insert(*pending.clone());
+                        Ok(models::PublishStatus::Pending(pending))
+                    }
+                    Err(e) => {
+                        tracing::error!("Publish failed: {}", e);
+                        Err(e)
+                    }
+                };
+                // only now the branch of a pending version is known to be kept
+                self.journal.end(operation).await;
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send publish result!");
+                }
+            }
+            BackendCommand::Yank(token, req, sender) => {
+                let operation = self
+                    .journal
+                    .begin(&format!("yank {} {}", req.name, req.vers))
+                    .await;
+                let backend = self.journal.backend(self.backend.as_ref(), operation);
+                let result = match yank::execute(&backend, &token, &req).await {
+                    Ok(versions) => {
+                        self.catalog.lock().unwrap().update(&versions, None);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        tracing::error!("Yank/Unyank failed: {}", e);
+                        Err(e)
+                    }
+                };
+                self.journal.end(operation).await;
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send yank result!");
+                }
+            }
+            BackendCommand::Authenticate(token, sender) => {
+                let result = self.backend.get_user(&token).await.map_err(|e| {
+                    tracing::warn!("Failed to authenticate: {}", e);
+                    e.into()
+                });
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send authentication result!");
+                }
+            }
+            BackendCommand::GetIndexFile(token, path, sender) => {
+                let result = match token.or_else(|| self.backend.cargolifter_token()) {
+                    Some(token) => match self.backend.get_file(&token, &path).await {
+                        Ok((content, encoding, sha)) => {
+                            commands::utils::decode_content(&content, &encoding)
+                                .map(|content| models::IndexFile { content, sha })
+                                .map_err(|e| {
+                                    tracing::error!("index file '{}' is invalid: {}", path, e);
+                                    e.into()
+                                })
                         }
-                        BackendCommand::IsVersionPublished(token, name, version, sender) => {
-                            match is_published::execute(&self.backend, &token, &name, &version)
-                                .await
-                            {
-                                Ok(result) => {
-                                    if sender.send(result).is_err() {
-                                        tracing::error!("Failed to send isPublished result!");
-                                    }
-                                }
-                                Err(_) => {
-                                    if sender.send(false).is_err() {
-                                        tracing::error!("Failed to send isPublished result!");
-                                    }
-                                }
-                            }
+                        Err(models::BackendError::NotFound(_)) => {
+                            tracing::info!("index file '{}' not found", path);
+                            Err(models::RegistryError::NotFound(format!(
+                                "index file '{}' not found",
+                                path
+                            )))
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to read index file '{}': {}", path, e);
+                            Err(e.into())
                         }
                     },
                     None => {
-                        tracing::warn!("Did not receive a BackendCommand!")
+                        tracing::warn!("no token to read index file '{}'", path);
+                        Err(models::RegistryError::Unauthorized(
+                            "no authorization token given, please run `cargo login`".into(),
+                        ))
                     }
+                };
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send index file result!");
                 }
             }
-        });
+            BackendCommand::Search(req, sender) => {
+                if sender
+                    .send(self.catalog.lock().unwrap().search(&req))
+                    .is_err()
+                {
+                    tracing::error!("Failed to send search result!");
+                }
+            }
+            BackendCommand::ListOwners(token, name, sender) => {
+                let result = owners::list(self.backend.as_ref(), &token, &name).await;
+                if let Err(e) = &result {
+                    tracing::error!("Listing owners failed: {}", e);
+                }
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send owners result!");
+                }
+            }
+            BackendCommand::AddOwners(token, name, users, sender) => {
+                let operation = self.journal.begin(&format!("add owners of {}", name)).await;
+                let backend = self.journal.backend(self.backend.as_ref(), operation);
+                let result = owners::add(&backend, &token, &name, &users).await;
+                self.journal.end(operation).await;
+                if let Err(e) = &result {
+                    tracing::error!("Adding owners failed: {}", e);
+                }
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send owners result!");
+                }
+            }
+            BackendCommand::RemoveOwners(token, name, users, sender) => {
+                let operation = self
+                    .journal
+                    .begin(&format!("remove owners of {}", name))
+                    .await;
+                let backend = self.journal.backend(self.backend.as_ref(), operation);
+                let result = owners::remove(&backend, &token, &name, &users).await;
+                self.journal.end(operation).await;
+                if let Err(e) = &result {
+                    tracing::error!("Removing owners failed: {}", e);
+                }
+                if sender.send(result).is_err() {
+                    tracing::error!("Failed to send owners result!");
+                }
+            }
+            BackendCommand::Metrics(sender) => {
+                if sender.send(self.metrics.snapshot()).is_err() {
+                    tracing::error!("Failed to send metrics!");
+                }
+            }
+            BackendCommand::Review(event, sender) => {
+                let pending = if self
+                    .backend
+                    .webhook_repository()
+                    .map(|repository| repository.eq_ignore_ascii_case(&event.repository))
+                    .unwrap_or(false)
+                {
+                    let mut pending = self.pending.lock().unwrap();
+                    let branch = pending
+                        .find_pull_request(event.pull_request)
+                        .map(|pending| pending.branch.clone());
+                    branch.and_then(|branch| pending.remove(&branch))
+                } else {
+                    // pull requests from forks or other repositories are never pending
+                    tracing::warn!(
+                        "ignoring pull request {} from repository '{}'",
+                        event.pull_request,
+                        event.repository
+                    );
+                    None
+                };
+                if let Some(pending) = &pending {
+                    self.finalize_review(pending, event.merged).await;
+                }
+                if sender.send(Ok(pending)).is_err() {
+                    tracing::error!("Failed to send review result!");
+                }
+            }
+        }
+    }
 
-        (handle, sender)
+    /// replaces the catalog with the crates in the index, which is read with the cargolifter token
+    async fn refresh_catalog(&self) {
+        let token = match self.backend.cargolifter_token() {
+            Some(token) => token,
+            None => {
+                tracing::warn!("no token to read the index, the catalog is not refreshed");
+                return;
+            }
+        };
+
+        self.catalog.lock().unwrap().begin_rebuild();
+        match commands::list::execute(self.backend.as_ref(), &token).await {
+            Ok(crates) => {
+                tracing::info!("found {} crates in the index", crates.len());
+                self.catalog.lock().unwrap().rebuild(&crates);
+            }
+            Err(e) => {
+                tracing::error!("failed to read the index into the catalog: {}", e);
+                self.catalog.lock().unwrap().cancel_rebuild();
+            }
+        }
+    }
+
+    /// adds a merged version to the catalog and removes the branch of the pull request
+    async fn finalize_review(&self, pending: &models::PendingVersion, merged: bool) {
+        let token = match self.backend.cargolifter_token() {
+            Some(token) => token,
+            None => {
+                tracing::warn!("no token to finalize review of '{}'", pending.branch);
+                return;
+            }
+        };
+
+        if merged {
+            tracing::info!(
+                "'{}' version '{}' got merged",
+                pending.meta.name,
+                pending.meta.vers
+            );
+            let crate_path = get_crate_file_path(&pending.meta.name);
+            match self.backend.get_file(&token, &crate_path).await {
+                Ok((content, encoding, _)) => {
+                    match commands::utils::read_versions(&content, &encoding) {
+                        Ok(versions) => self
+                            .catalog
+                            .lock()
+                            .unwrap()
+                            .update(&versions, Some(&pending.meta)),
+                        Err(e) => tracing::error!("index file '{}' is invalid: {}", crate_path, e),
+                    }
+                }
+                Err(e) => tracing::error!("failed to read '{}': {}", crate_path, e),
+            }
+        } else {
+            tracing::info!(
+                "review of '{}' version '{}' got closed",
+                pending.meta.name,
+                pending.meta.vers
+            );
+        }
+
+        if let Err(e) = self.backend.delete_branch(&token, &pending.branch).await {
+            tracing::info!("failed to delete branch '{}': {}", pending.branch, e);
+        }
     }
 }
 
@@ -193,37 +743,118 @@ impl<T: Storage + Sync + Send + 'static> StorageService<T> {
                 match receiver.recv().await {
                     Some(command) => match command {
                         StorageCommand::Get(req) => {
-                            match self.storage.get(&req.crate_name, &req.crate_version).await {
-                                Ok(data) => {
-                                    if req.result_sender.send(Some(data)).is_err() {
-                                        tracing::error!("Failed to send storage result!");
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::error!("Storage get failed: {}", e);
-                                    if req.result_sender.send(None).is_err() {
-                                        tracing::error!("Failed to send storage result!");
-                                    }
-                                }
+                            let result =
+                                self.storage.get(&req.crate_name, &req.crate_version).await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage get failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
                             }
                         }
                         StorageCommand::Put(req) => {
-                            match self
+                            let result = self
                                 .storage
                                 .put(&req.crate_name, &req.crate_version, &req.data)
-                                .await
-                            {
-                                Ok(_) => {
-                                    if req.result_sender.send(true).is_err() {
-                                        tracing::error!("Failed to send storage result!");
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::error!("Storage put failed: {}", e);
-                                    if req.result_sender.send(false).is_err() {
-                                        tracing::error!("Failed to send storage result!");
-                                    }
-                                }
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage put failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::Delete(req) => {
+                            let result = self
+                                .storage
+                                .delete(&req.crate_name, &req.crate_version)
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage delete failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::GetMirror(req) => {
+                            let result = self
+                                .storage
+                                .get_mirror(&req.crate_name, &req.crate_version)
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::info!("Storage get of mirrored crate failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::PutMirror(req) => {
+                            let result = self
+                                .storage
+                                .put_mirror(&req.crate_name, &req.crate_version, &req.data)
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage put of mirrored crate failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::AppendStaged(req) => {
+                            let result = self
+                                .storage
+                                .append_staged(
+                                    &req.crate_name,
+                                    &req.crate_version,
+                                    &req.staging_id,
+                                    &req.data,
+                                )
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage append to staged crate failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::CompleteStaged(req) => {
+                            let result = self
+                                .storage
+                                .complete_staged(
+                                    &req.crate_name,
+                                    &req.crate_version,
+                                    &req.staging_id,
+                                )
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage completion of staged crate failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::Promote(req) => {
+                            let result = self
+                                .storage
+                                .promote(&req.crate_name, &req.crate_version, &req.staging_id)
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage promote failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
+                            }
+                        }
+                        StorageCommand::DeleteStaged(req) => {
+                            let result = self
+                                .storage
+                                .delete_staged(&req.crate_name, &req.crate_version, &req.staging_id)
+                                .await;
+                            if let Err(e) = &result {
+                                tracing::error!("Storage delete of staged crate failed: {}", e);
+                            }
+                            if req.result_sender.send(result).is_err() {
+                                tracing::error!("Failed to send storage result!");
                             }
                         }
                     },
@@ -237,3 +868,238 @@ impl<T: Storage + Sync + Send + 'static> StorageService<T> {
         (handle, sender)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::{BackendError, RegistryError};
+
+    /// index without crates, reads of the owners of `slow` wait for a permit
+    #[derive(Clone)]
+    struct SlowIndex {
+        permits: Arc<tokio::sync::Semaphore>,
+        /// tokens of all reads in the order they started
+        reads: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Backend for SlowIndex {
+        async fn get_file(
+            &self,
+            token: &str,
+            path: &str,
+        ) -> Result<(String, String, String), BackendError> {
+            self.reads.lock().unwrap().push(token.into());
+            if path == get_owners_file_path("slow") {
+                self.permits.acquire().await.unwrap().forget();
+            }
+            Err(BackendError::NotFound(path.into()))
+        }
+
+        async fn create_file(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+        ) -> Result<(), BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        async fn update_file(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+        ) -> Result<(), BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        async fn delete_branch(&self, _: &str, _: &str) -> Result<(), BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        async fn create_pull_request(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+        ) -> Result<u64, BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        async fn merge_pull_request(&self, _: &str, _: u64) -> Result<(), BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        #[allow(clippy::diverging_sub_expression)]
+        async fn delete_pull_request(&self, _: &str, _: u64) -> Result<(), BackendError> {
+            panic!("simulated panic of a backend call")
+        }
+
+        async fn commit_file(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: &str,
+            _: Option<&str>,
+        ) -> Result<(), BackendError> {
+            Err(BackendError::Forbidden("the index is read only".into()))
+        }
+
+        fn commit_mode(&self) -> config::CommitMode {
+            config::CommitMode::Direct
+        }
+
+        async fn list_files(&self, _: &str) -> Result<Vec<String>, BackendError> {
+            Ok(Vec::new())
+        }
+
+        #[allow(clippy::diverging_sub_expression)]
+        async fn get_user(&self, _: &str) -> Result<String, BackendError> {
+            panic!("simulated panic of a backend call")
+        }
+
+        fn cargolifter_token(&self) -> Option<String> {
+            Some("cargolifter".into())
+        }
+
+        fn webhook_repository(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn list_owners(
+        token: &str,
+        name: &str,
+    ) -> (
+        BackendCommand,
+        tokio::sync::oneshot::Receiver<Result<models::Owners, RegistryError>>,
+    ) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let command = BackendCommand::ListOwners(token.into(), name.into(), tx);
+        (command, rx)
+    }
+
+    #[tokio::test]
+    async fn test_crate_order() {
+        let index = SlowIndex {
+            permits: Arc::new(tokio::sync::Semaphore::new(0)),
+            reads: Default::default(),
+        };
+        let config = config::ServiceConfig {
+            workers: Some(2),
+            queue_size: Some(1),
+        };
+        let service = BackendService::new(
+            index.clone(),
+            Catalog::new(),
+            PendingVersions::new(),
+            config,
+            Arc::new(Journal::new()),
+        );
+        let (_, sender) = service.run();
+        let fast = (0..)
+            .map(|i| format!("fast{}", i))
+            .find(|name| worker_index(name, 2) != worker_index("slow", 2))
+            .unwrap();
+
+        let (command, first) = list_owners("1", "slow");
+        assert!(sender.send(command).await.is_ok());
+        while index.reads.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let (command, second) = list_owners("2", "slow");
+        assert!(sender.send(command).await.is_ok());
+
+        // other crates are handled while `slow` is stuck
+        let (command, other) = list_owners("3", &fast);
+        assert!(sender.send(command).await.is_ok());
+        assert!(matches!(other.await.unwrap(), Ok(owners) if owners.users.is_empty()));
+
+        // the queue of `slow` holds the second command, a third one is rejected
+        let (command, third) = list_owners("4", "slow");
+        assert!(sender.send(command).await.is_ok());
+        assert!(matches!(
+            third.await.unwrap(),
+            Err(RegistryError::QueueFull(_))
+        ));
+
+        index.permits.add_permits(2);
+        assert!(matches!(first.await.unwrap(), Ok(owners) if owners.users.is_empty()));
+        assert!(matches!(second.await.unwrap(), Ok(owners) if owners.users.is_empty()));
+        assert_eq!(*index.reads.lock().unwrap(), vec!["1", "3", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_worker_survives_panic() {
+        let index = SlowIndex {
+            permits: Arc::new(tokio::sync::Semaphore::new(0)),
+            reads: Default::default(),
+        };
+        let config = config::ServiceConfig {
+            workers: Some(1),
+            queue_size: None,
+        };
+        let service = BackendService::new(
+            index,
+            Catalog::new(),
+            PendingVersions::new(),
+            config,
+            Arc::new(Journal::new()),
+        );
+        let (_, sender) = service.run();
+
+        // reading the user panics
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let command = BackendCommand::AddOwners("1".into(), "foo".into(), Vec::new(), tx);
+        assert!(sender.send(command).await.is_ok());
+        assert!(rx.await.is_err());
+
+        let (command, rx) = list_owners("2", "foo");
+        assert!(sender.send(command).await.is_ok());
+        assert!(matches!(rx.await.unwrap(), Ok(owners) if owners.users.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let journal = Journal::open(path);
+        let operation = journal.begin("publish").await;
+        journal
+            .record(operation, journal::Step::PullRequest(1))
+            .await;
+        drop(journal);
+
+        // deleting the pull request panics
+        let service = BackendService::new(
+            SlowIndex {
+                permits: Arc::new(tokio::sync::Semaphore::new(0)),
+                reads: Default::default(),
+            },
+            Catalog::new(),
+            PendingVersions::new(),
+            config::ServiceConfig::default(),
+            Arc::new(Journal::open(path)),
+        );
+        let (_, sender) = service.run();
+
+        let (command, rx) = list_owners("1", "foo");
+        assert!(sender.send(command).await.is_ok());
+        assert!(matches!(rx.await.unwrap(), Ok(owners) if owners.users.is_empty()));
+    }
+
+    #[test]
+    fn test_worker_index() {
+        assert_eq!(worker_index("Foo-Bar", 4), worker_index("foo_bar", 4));
+        assert!((0..100).all(|i| worker_index(&format!("crate{}", i), 3) < 3));
+        assert_eq!(0, worker_index("foo", 1));
+    }
+}
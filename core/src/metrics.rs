@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::models::ServiceMetrics;
+
+/// Counters of the backend service workers.
+///
+/// Latencies are summed up so averages can be derived from the number of
+/// handled commands.
+#[derive(Debug)]
+pub struct BackendMetrics {
+    queue_depth: Vec<AtomicUsize>,
+    handled: AtomicU64,
+    queue_micros: AtomicU64,
+    handle_micros: AtomicU64,
+}
+
+impl BackendMetrics {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            queue_depth: (0..workers).map(|_| AtomicUsize::new(0)).collect(),
+            handled: AtomicU64::new(0),
+            queue_micros: AtomicU64::new(0),
+            handle_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enqueued(&self, worker: usize) {
+        self.queue_depth[worker].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dequeued(&self, worker: usize, waited: Duration) {
+        self.queue_depth[worker].fetch_sub(1, Ordering::Relaxed);
+        self.queue_micros
+            .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn handled(&self, took: Duration) {
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        self.handle_micros
+            .fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ServiceMetrics {
+        ServiceMetrics {
+            queue_depth: self
+                .queue_depth
+                .iter()
+                .map(|depth| depth.load(Ordering::Relaxed))
+                .collect(),
+            handled: self.handled.load(Ordering::Relaxed),
+            queue_seconds: self.queue_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            handle_seconds: self.handle_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = BackendMetrics::new(2);
+        metrics.enqueued(1);
+        metrics.enqueued(1);
+        metrics.dequeued(1, Duration::from_millis(500));
+        metrics.handled(Duration::from_secs(2));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(vec![0, 1], snapshot.queue_depth);
+        assert_eq!(1, snapshot.handled);
+        assert_eq!(0.5, snapshot.queue_seconds);
+        assert_eq!(2.0, snapshot.handle_seconds);
+    }
+}
@@ -0,0 +1,84 @@
+//! Moving crate files from the old `{crate_path}/{version}` storage layout,
+//! which left out the crate name, to `get_crate_storage_key`.
+
+use crate::models::StorageError;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MigrationReport {
+    pub migrated: usize,
+    pub skipped: usize,
+}
+
+/// keys of the current layout always end with `.crate`
+pub fn is_legacy_storage_key(key: &str) -> bool {
+    !key.ends_with(".crate")
+}
+
+/// new key of a crate file stored at `legacy_key`, the crate name is read from
+/// the crate file itself as the legacy key does not contain it
+pub fn migrated_storage_key(legacy_key: &str, data: &[u8]) -> Result<String, StorageError> {
+    let (crate_path, version) = legacy_key.rsplit_once('/').ok_or_else(|| {
+        StorageError::Corrupt(format!("'{}' is not a legacy storage key", legacy_key))
+    })?;
+    let crate_name = crate::tarball::read_crate_name(data, version)?;
+
+    // legacy keys were built from the crate name without lowercasing it
+    if crate::get_crate_path(&crate_name) != crate_path.to_ascii_lowercase() {
+        return Err(StorageError::Corrupt(format!(
+            "'{}' contains crate '{}' which does not belong there",
+            legacy_key, crate_name
+        )));
+    }
+
+    Ok(crate::get_crate_storage_key(&crate_name, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crate_file(root: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let content = b"[package]\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{}/Cargo.toml", root), &content[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_migrated_storage_key() {
+        assert_eq!(
+            "se/rd/serde/1.0.0.crate",
+            migrated_storage_key("se/rd/1.0.0", &crate_file("serde-1.0.0")).unwrap()
+        );
+        assert_eq!(
+            "se/rd/serdx/1.0.0.crate",
+            migrated_storage_key("se/rd/1.0.0", &crate_file("serdx-1.0.0")).unwrap()
+        );
+        assert_eq!(
+            "se/rd/serde_json/1.0.0.crate",
+            migrated_storage_key("Se/rd/1.0.0", &crate_file("Serde_Json-1.0.0")).unwrap()
+        );
+        assert_eq!(
+            "1/a/0.1.0-alpha.1.crate",
+            migrated_storage_key("1/0.1.0-alpha.1", &crate_file("a-0.1.0-alpha.1")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_migrated_storage_key_rejects_mismatch() {
+        assert!(migrated_storage_key("se/rd/1.0.0", &crate_file("toml-1.0.0")).is_err());
+        assert!(migrated_storage_key("se/rd/1.0.0", &crate_file("serde-1.0.1")).is_err());
+        assert!(migrated_storage_key("se/rd/1.0.0", b"not a crate").is_err());
+        assert!(is_legacy_storage_key("se/rd/1.0.0"));
+        assert!(!is_legacy_storage_key("se/rd/serde/1.0.0.crate"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Client of the upstream registry cargolifter acts as a caching proxy for.
+
+use sha2::{Digest, Sha256};
+
+use crate::models::BackendError;
+
+const DEFAULT_UPSTREAM_INDEX: &str = "https://index.crates.io";
+
+#[derive(Clone)]
+pub struct Upstream {
+    client: reqwest::Client,
+    index_url: String,
+}
+
+impl Upstream {
+    pub fn new(config: &crate::config::MirrorConfig) -> Self {
+        let index_url = config
+            .upstream_index
+            .as_deref()
+            .unwrap_or(DEFAULT_UPSTREAM_INDEX)
+            .trim_end_matches('/')
+            .to_string();
+
+        Self {
+            client: reqwest::Client::new(),
+            index_url,
+        }
+    }
+
+    /// index file at `path` of the upstream sparse index, `None` if upstream does not know the crate
+    pub async fn get_index_file(&self, path: &str) -> Result<Option<String>, BackendError> {
+        let url = format!("{}/{}", self.index_url, path);
+        tracing::info!("fetching '{}' from upstream", url);
+
+        match self.get(&url).await {
+            Ok(response) => Ok(Some(response.text().await?)),
+            // sparse indexes may answer missing crates with 403 or 410 as well
+            Err(BackendError::NotFound(_)) | Err(BackendError::Forbidden(_)) => Ok(None),
+            Err(BackendError::UnexpectedStatus(410, _)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// crate file downloaded from the `dl` location announced by the upstream
+    /// index, verified against the checksum of its index entry
+    pub async fn download(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+    ) -> Result<Vec<u8>, BackendError> {
+        let not_found = || {
+            BackendError::NotFound(format!(
+                "crate '{}' version '{}' not found upstream",
+                crate_name, crate_version
+            ))
+        };
+        let index_file = self
+            .get_index_file(&crate::get_crate_file_path(crate_name))
+            .await?
+            .ok_or_else(not_found)?;
+        let versions = crate::commands::utils::read_versions(&index_file, "utf-8")?;
+        let version = versions
+            .iter()
+            .find(|v| v.vers == crate_version)
+            .ok_or_else(not_found)?;
+
+        let config = self
+            .get(&format!("{}/config.json", self.index_url))
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        let dl = config.get("dl").and_then(|dl| dl.as_str()).ok_or_else(|| {
+            BackendError::InvalidIndex("upstream config.json has no 'dl' entry".into())
+        })?;
+
+        // the upstream expects the name as written in its index
+        let url = download_url(dl, &version.name, &version.vers);
+        tracing::info!("downloading '{}' from upstream", url);
+        let data = self.get(&url).await?.bytes().await?.to_vec();
+
+        let cksum = hex::encode(Sha256::digest(&data));
+        if cksum != version.cksum {
+            return Err(BackendError::ChecksumMismatch(format!(
+                "'{}' has checksum {} but the upstream index expects {}",
+                url, cksum, version.cksum
+            )));
+        }
+        Ok(data)
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response, BackendError> {
+        let response = self
+            .client
+            .get(url)
+            .header("user-agent", "cargolifter")
+            .send()
+            .await?;
+        BackendError::check(response).await
+    }
+}
+
+/// expands the `dl` template of a registry config as described in the cargo book
+fn download_url(dl: &str, crate_name: &str, crate_version: &str) -> String {
+    let markers = ["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+    if !markers.iter().any(|marker| dl.contains(marker)) {
+        return format!(
+            "{}/{}/{}/download",
+            dl.trim_end_matches('/'),
+            crate_name,
+            crate_version
+        );
+    }
+
+    dl.replace("{crate}", crate_name)
+        .replace("{version}", crate_version)
+        .replace("{prefix}", &crate::get_crate_prefix(crate_name))
+        .replace("{lowerprefix}", &crate::get_crate_path(crate_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn upstream() -> (MockServer, Upstream) {
+        let server = MockServer::start().await;
+        let upstream = Upstream::new(&crate::config::MirrorConfig {
+            public_url: "http://localhost".into(),
+            upstream_index: Some(format!("{}/index/", server.uri())),
+        });
+        (server, upstream)
+    }
+
+    #[test]
+    fn test_download_url() {
+        assert_eq!(
+            "https://crates.io/api/v1/crates/serde/1.0.0/download",
+            download_url("https://crates.io/api/v1/crates", "serde", "1.0.0")
+        );
+        assert_eq!(
+            "https://static.crates.io/crates/serde/serde-1.0.0.crate",
+            download_url(
+                "https://static.crates.io/crates/{crate}/{crate}-{version}.crate",
+                "serde",
+                "1.0.0"
+            )
+        );
+        assert_eq!(
+            "https://example.com/se/rd/serde/1.0.0",
+            download_url(
+                "https://example.com/{lowerprefix}/{crate}/{version}",
+                "serde",
+                "1.0.0"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_index_file() {
+        let (server, upstream) = upstream().await;
+        Mock::given(method("GET"))
+            .and(path("/index/se/rd/serde"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{\"name\":\"serde\"}\n"))
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            Some("{\"name\":\"serde\"}\n".to_string()),
+            upstream.get_index_file("se/rd/serde").await.unwrap()
+        );
+        assert_eq!(None, upstream.get_index_file("se/rd/serdx").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let (server, upstream) = upstream().await;
+        let index_line = |cksum: &str| {
+            format!(
+                r#"{{"name":"Serde","vers":"1.0.0","deps":[],"cksum":"{}","features":{{}},"yanked":false,"links":null}}"#,
+                cksum
+            )
+        };
+        Mock::given(method("GET"))
+            .and(path("/index/config.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                "{{\"dl\":\"{}/crates/{{crate}}/{{crate}}-{{version}}.crate\"}}",
+                server.uri()
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/index/se/rd/serde"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(index_line(&hex::encode(Sha256::digest(b"crate")))),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/index/se/rd/serdx"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(index_line("0000").replace("Serde", "serdx")),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crates/Serde/Serde-1.0.0.crate"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"crate".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crates/serdx/serdx-1.0.0.crate"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"crate".to_vec()))
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            b"crate".to_vec(),
+            upstream.download("serde", "1.0.0").await.unwrap()
+        );
+        assert!(matches!(
+            upstream.download("serde", "2.0.0").await,
+            Err(BackendError::NotFound(_))
+        ));
+        assert!(matches!(
+            upstream.download("serdx", "1.0.0").await,
+            Err(BackendError::ChecksumMismatch(_))
+        ));
+    }
+}
@@ -1,7 +1,19 @@
+pub mod error;
+pub mod index;
+pub mod metrics;
+pub mod owners;
 pub mod publish;
+pub mod review;
+pub mod search;
 pub mod storage;
 pub mod yank;
 
+pub use error::*;
+pub use index::*;
+pub use metrics::*;
+pub use owners::*;
 pub use publish::*;
+pub use review::*;
+pub use search::*;
 pub use storage::*;
 pub use yank::*;
@@ -0,0 +1,359 @@
+use serde::{Deserialize, Serialize};
+
+/// messages of 422 responses that reject a change because of the current state
+/// of the repository, other 422 responses are invalid requests that fail again
+const CONFLICT_MESSAGES: &[&str] = &["already exists", "does not match", "wasn't supplied"];
+
+/// Failure of a request against the index repository backend.
+///
+/// HTTP responses are mapped by status so that callers can tell a missing
+/// file from a conflicting change or a denied permission.
+#[derive(Debug)]
+pub enum BackendError {
+    NotFound(String),
+    Conflict(String),
+    Unauthorized(String),
+    Forbidden(String),
+    RateLimited(Option<std::time::Duration>),
+    UnexpectedStatus(u16, String),
+    Transport(reqwest::Error),
+    InvalidIndex(String),
+    ChecksumMismatch(String),
+    Git(String),
+    /// response that can't be used, e.g. pointing to another host
+    InvalidResponse(String),
+}
+
+impl BackendError {
+    /// passes successful responses through and maps all others to an error
+    pub async fn check(response: reqwest::Response) -> Result<reqwest::Response, BackendError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(std::time::Duration::from_secs);
+        let rate_limit_exhausted = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .map(|value| value == "0")
+            .unwrap_or(false);
+        let url = response.url().to_string();
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(String::from))
+            .unwrap_or(body);
+        let detail = if message.is_empty() {
+            url
+        } else {
+            format!("{}: {}", url, message)
+        };
+
+        Err(match status {
+            reqwest::StatusCode::NOT_FOUND => BackendError::NotFound(detail),
+            reqwest::StatusCode::CONFLICT => BackendError::Conflict(detail),
+            reqwest::StatusCode::UNPROCESSABLE_ENTITY
+                if CONFLICT_MESSAGES
+                    .iter()
+                    .any(|conflict| detail.to_lowercase().contains(conflict)) =>
+            {
+                BackendError::Conflict(detail)
+            }
+            reqwest::StatusCode::UNAUTHORIZED => BackendError::Unauthorized(detail),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => BackendError::RateLimited(retry_after),
+            reqwest::StatusCode::FORBIDDEN if rate_limit_exhausted || retry_after.is_some() => {
+                BackendError::RateLimited(retry_after)
+            }
+            reqwest::StatusCode::FORBIDDEN => BackendError::Forbidden(detail),
+            _ => BackendError::UnexpectedStatus(status.as_u16(), detail),
+        })
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackendError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::NotFound(detail) => write!(f, "not found: {}", detail),
+            BackendError::Conflict(detail) => write!(f, "conflict: {}", detail),
+            BackendError::Unauthorized(detail) => write!(f, "unauthorized: {}", detail),
+            BackendError::Forbidden(detail) => write!(f, "forbidden: {}", detail),
+            BackendError::RateLimited(Some(retry_after)) => write!(
+                f,
+                "rate limited, retry after {} seconds",
+                retry_after.as_secs()
+            ),
+            BackendError::RateLimited(None) => write!(f, "rate limited"),
+            BackendError::UnexpectedStatus(status, detail) => {
+                write!(f, "unexpected status {}: {}", status, detail)
+            }
+            BackendError::Transport(e) => write!(f, "request failed: {}", e),
+            BackendError::InvalidIndex(detail) => write!(f, "invalid index file: {}", detail),
+            BackendError::ChecksumMismatch(detail) => write!(f, "checksum mismatch: {}", detail),
+            BackendError::Git(detail) => write!(f, "git failed: {}", detail),
+            BackendError::InvalidResponse(detail) => write!(f, "invalid response: {}", detail),
+        }
+    }
+}
+
+impl std::convert::From<reqwest::Error> for BackendError {
+    fn from(e: reqwest::Error) -> Self {
+        BackendError::Transport(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Backend(BackendError),
+    Conflict(String),
+    Internal(String),
+    InvalidCrate(String),
+    InvalidName(String, String),
+    InvalidOwners(serde_json::Error),
+    InvalidVersion(String, String),
+    LastOwner(String),
+    MalformedRequest(String),
+    NameTaken(String, String),
+    NotAnOwner(String, String),
+    NotFound(String),
+    PendingReview(String, String),
+    QueueFull(String),
+    Storage(crate::models::StorageError),
+    TooLarge(String),
+    Unauthorized(String),
+    VersionExists(String, String),
+}
+
+impl std::error::Error for RegistryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RegistryError::Backend(e) => Some(e),
+            RegistryError::InvalidOwners(e) => Some(e),
+            RegistryError::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::Backend(e) => write!(f, "index backend failed: {}", e),
+            RegistryError::Conflict(detail) => {
+                write!(f, "conflicting change in the index repository: {}", detail)
+            }
+            RegistryError::Internal(detail) => write!(f, "internal error: {}", detail),
+            RegistryError::InvalidCrate(detail) => write!(f, "invalid crate file: {}", detail),
+            RegistryError::InvalidName(name, reason) => {
+                write!(f, "invalid crate name '{}': {}", name, reason)
+            }
+            RegistryError::InvalidOwners(e) => write!(f, "invalid owners file: {}", e),
+            RegistryError::InvalidVersion(vers, reason) => {
+                write!(f, "invalid version '{}': {}", vers, reason)
+            }
+            RegistryError::LastOwner(crate_name) => {
+                write!(f, "cannot remove all owners of crate '{}'", crate_name)
+            }
+            RegistryError::MalformedRequest(detail) => write!(f, "malformed request: {}", detail),
+            RegistryError::NameTaken(name, existing) => write!(
+                f,
+                "crate name '{}' is too similar to existing crate '{}'",
+                name, existing
+            ),
+            RegistryError::NotAnOwner(user, crate_name) => {
+                write!(
+                    f,
+                    "user '{}' is not an owner of crate '{}'",
+                    user, crate_name
+                )
+            }
+            RegistryError::NotFound(detail) => write!(f, "{}", detail),
+            RegistryError::PendingReview(crate_name, version) => write!(
+                f,
+                "crate '{}' version '{}' is already waiting for review",
+                crate_name, version
+            ),
+            RegistryError::QueueFull(crate_name) => write!(
+                f,
+                "too many requests for crate '{}' are waiting, please retry later",
+                crate_name
+            ),
+            RegistryError::Storage(e) => write!(f, "failed to access crate storage: {}", e),
+            RegistryError::TooLarge(detail) => write!(f, "request too large: {}", detail),
+            RegistryError::Unauthorized(detail) => write!(f, "authentication failed: {}", detail),
+            RegistryError::VersionExists(crate_name, version) => write!(
+                f,
+                "crate '{}' version '{}' already exists with different contents",
+                crate_name, version
+            ),
+        }
+    }
+}
+
+impl std::convert::From<BackendError> for RegistryError {
+    fn from(e: BackendError) -> Self {
+        match e {
+            BackendError::Unauthorized(detail) => RegistryError::Unauthorized(detail),
+            BackendError::Conflict(detail) => RegistryError::Conflict(detail),
+            e => RegistryError::Backend(e),
+        }
+    }
+}
+
+impl std::convert::From<crate::models::StorageError> for RegistryError {
+    fn from(e: crate::models::StorageError) -> Self {
+        RegistryError::Storage(e)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub detail: String,
+}
+
+/// error body understood by cargo
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub errors: Vec<ErrorDetail>,
+}
+
+impl std::convert::From<&RegistryError> for ErrorResponse {
+    fn from(e: &RegistryError) -> Self {
+        Self {
+            errors: vec![ErrorDetail {
+                detail: e.to_string(),
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::any;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn check(response: ResponseTemplate) -> Result<reqwest::Response, BackendError> {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        let response = reqwest::get(server.uri()).await.unwrap();
+        BackendError::check(response).await
+    }
+
+    fn json_message(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_string(format!(r#"{{"message":"{}"}}"#, message))
+    }
+
+    #[tokio::test]
+    async fn test_check_status() {
+        assert!(check(ResponseTemplate::new(201)).await.is_ok());
+        assert!(matches!(
+            check(ResponseTemplate::new(404)).await,
+            Err(BackendError::NotFound(_))
+        ));
+        assert!(matches!(
+            check(ResponseTemplate::new(409)).await,
+            Err(BackendError::Conflict(_))
+        ));
+        assert!(matches!(
+            check(ResponseTemplate::new(401)).await,
+            Err(BackendError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            check(ResponseTemplate::new(403)).await,
+            Err(BackendError::Forbidden(_))
+        ));
+        assert!(matches!(
+            check(ResponseTemplate::new(500)).await,
+            Err(BackendError::UnexpectedStatus(500, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_unprocessable() {
+        assert!(matches!(
+            check(json_message(422, "Reference already exists")).await,
+            Err(BackendError::Conflict(_))
+        ));
+        assert!(matches!(
+            check(json_message(
+                422,
+                "Invalid request. \\\"sha\\\" wasn't supplied."
+            ))
+            .await,
+            Err(BackendError::Conflict(_))
+        ));
+        assert!(matches!(
+            check(json_message(
+                422,
+                "sha does not match [given: a, expected: b]"
+            ))
+            .await,
+            Err(BackendError::Conflict(_))
+        ));
+        // invalid requests are not retried
+        assert!(matches!(
+            check(json_message(422, "Validation Failed")).await,
+            Err(BackendError::UnexpectedStatus(422, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_rate_limit() {
+        let response = ResponseTemplate::new(403).insert_header("x-ratelimit-remaining", "0");
+        assert!(matches!(
+            check(response).await,
+            Err(BackendError::RateLimited(None))
+        ));
+        let response = ResponseTemplate::new(403).insert_header("retry-after", "30");
+        assert!(matches!(
+            check(response).await,
+            Err(BackendError::RateLimited(Some(d))) if d.as_secs() == 30
+        ));
+        let response = ResponseTemplate::new(429).insert_header("retry-after", "60");
+        assert!(matches!(
+            check(response).await,
+            Err(BackendError::RateLimited(Some(d))) if d.as_secs() == 60
+        ));
+        // only delays in seconds are understood
+        let response = ResponseTemplate::new(429)
+            .insert_header("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert!(matches!(
+            check(response).await,
+            Err(BackendError::RateLimited(None))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_message() {
+        match check(json_message(404, "Not Found")).await {
+            Err(BackendError::NotFound(detail)) => assert!(detail.ends_with("/: Not Found")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match check(ResponseTemplate::new(404).set_body_string("no such file")).await {
+            Err(BackendError::NotFound(detail)) => assert!(detail.ends_with(": no such file")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match check(ResponseTemplate::new(404)).await {
+            Err(BackendError::NotFound(detail)) => assert!(detail.starts_with("http://")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
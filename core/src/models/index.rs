@@ -0,0 +1,6 @@
+/// decoded content of a file in the index repository
+#[derive(Debug, Clone)]
+pub struct IndexFile {
+    pub content: String,
+    pub sha: String,
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+/// state of the backend service at the time it was requested
+#[derive(Debug, Default, Serialize)]
+pub struct ServiceMetrics {
+    /// commands waiting for each worker
+    pub queue_depth: Vec<usize>,
+    /// commands handled by all workers
+    pub handled: u64,
+    /// total time handled commands waited in a queue
+    pub queue_seconds: f64,
+    /// total time it took to handle the commands
+    pub handle_seconds: f64,
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// content of the owners file kept next to the index entries of a crate
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Owners {
+    pub users: Vec<String>,
+}
+
+impl Owners {
+    pub fn contains(&self, login: &str) -> bool {
+        self.users.iter().any(|u| u.eq_ignore_ascii_case(login))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OwnersRequest {
+    pub users: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Owner {
+    pub id: u32,
+    pub login: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnersResponse {
+    pub users: Vec<Owner>,
+}
+
+impl std::convert::From<Owners> for OwnersResponse {
+    fn from(owners: Owners) -> Self {
+        Self {
+            users: owners
+                .users
+                .into_iter()
+                .enumerate()
+                .map(|(i, login)| Owner {
+                    id: i as u32 + 1,
+                    login,
+                    name: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnersChangeResponse {
+    pub ok: bool,
+    pub msg: String,
+}
@@ -1,9 +1,8 @@
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::hash::Hash;
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Dependency {
     pub name: String,
     pub version_req: String,
@@ -16,7 +15,7 @@ pub struct Dependency {
     pub package: Option<String>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct MetaData {
     pub name: String,
     pub vers: String,
@@ -35,12 +34,29 @@ pub struct MetaData {
     pub repository: Option<String>,
     pub badges: HashMap<String, HashMap<String, String>>,
     pub links: Option<String>,
+    #[serde(default)]
+    pub rust_version: Option<String>,
 }
 
+/// metadata of an uploaded crate file, which is streamed into the staging area
 #[derive(Debug, Default)]
 pub struct PublishRequest {
     pub meta: MetaData,
-    pub data: Vec<u8>,
+    /// hex encoded SHA-256 of the crate file, as stored in the index
+    pub cksum: String,
+}
+
+/// response to `cargo publish`, which prints the warnings
+#[derive(Debug, Default, Serialize)]
+pub struct PublishResponse {
+    pub warnings: PublishWarnings,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PublishWarnings {
+    pub invalid_categories: Vec<String>,
+    pub invalid_badges: Vec<String>,
+    pub other: Vec<String>,
 }
 
 impl MetaData {
@@ -49,7 +65,7 @@ impl MetaData {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
 pub struct PublishedDependency {
     pub name: String,
     pub req: String,
@@ -60,6 +76,9 @@ pub struct PublishedDependency {
     pub kind: String,
     pub registry: Option<String>,
     pub package: Option<String>,
+    /// fields of newer index versions, kept so that rewriting a line does not drop them
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 impl std::convert::From<Dependency> for PublishedDependency {
@@ -74,11 +93,16 @@ impl std::convert::From<Dependency> for PublishedDependency {
             kind: dep.kind,
             registry: dep.registry,
             package: dep.package,
+            extra: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq)]
+/// Line of an index file.
+///
+/// `v`, `features2` and `rust_version` are only written if present. Any other
+/// field is kept in `extra` so that rewriting a line does not drop it.
+#[derive(Debug, Default, Serialize, Deserialize, Eq)]
 pub struct PublishedVersion {
     pub name: String,
     pub vers: String,
@@ -87,11 +111,38 @@ pub struct PublishedVersion {
     pub features: HashMap<String, Vec<String>>,
     pub yanked: bool,
     pub links: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features2: Option<HashMap<String, Vec<String>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+type Features = HashMap<String, Vec<String>>;
+
+/// Splits features like crates.io does: features using `dep:` or `?/` syntax go
+/// into `features2`, which cargo versions before 1.60 don't understand.
+fn split_features(features: &Features) -> (Features, Option<Features>) {
+    let (features2, features): (HashMap<_, _>, HashMap<_, _>) =
+        features.clone().into_iter().partition(|(_, values)| {
+            values
+                .iter()
+                .any(|value| value.starts_with("dep:") || value.contains("?/"))
+        });
+
+    if features2.is_empty() {
+        (features, None)
+    } else {
+        (features, Some(features2))
+    }
 }
 
 impl std::convert::From<&PublishRequest> for PublishedVersion {
     fn from(req: &PublishRequest) -> Self {
-        let cksum = Sha256::digest(&req.data);
+        let (features, features2) = split_features(&req.meta.features);
 
         Self {
             name: req.meta.name.clone(),
@@ -103,10 +154,14 @@ impl std::convert::From<&PublishRequest> for PublishedVersion {
                 .into_iter()
                 .map(|dep| dep.into())
                 .collect(),
-            cksum: hex::encode(cksum),
-            features: req.meta.features.clone(),
+            cksum: req.cksum.clone(),
+            features,
             yanked: false,
             links: req.meta.links.clone(),
+            v: features2.as_ref().map(|_| 2),
+            features2,
+            rust_version: req.meta.rust_version.clone(),
+            ..Default::default()
         }
     }
 }
@@ -132,7 +187,7 @@ mod tests {
             name: "A".into(),
             ..Default::default()
         };
-        assert_eq!("1/A", meta.crate_file_path());
+        assert_eq!("1/a", meta.crate_file_path());
     }
 
     #[test]
@@ -141,7 +196,7 @@ mod tests {
             name: "AB".into(),
             ..Default::default()
         };
-        assert_eq!("2/AB", meta.crate_file_path());
+        assert_eq!("2/ab", meta.crate_file_path());
     }
 
     #[test]
@@ -150,7 +205,7 @@ mod tests {
             name: "ABC".into(),
             ..Default::default()
         };
-        assert_eq!("3/A/ABC", meta.crate_file_path());
+        assert_eq!("3/a/abc", meta.crate_file_path());
     }
 
     #[test]
@@ -159,6 +214,50 @@ mod tests {
             name: "ABCDE".into(),
             ..Default::default()
         };
-        assert_eq!("AB/CD/ABCDE", meta.crate_file_path());
+        assert_eq!("ab/cd/abcde", meta.crate_file_path());
+    }
+
+    #[test]
+    fn test_features_v1() {
+        let request = super::PublishRequest {
+            meta: super::MetaData {
+                features: [("std".to_string(), vec!["serde/std".to_string()])].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let version = super::PublishedVersion::from(&request);
+        assert_eq!(request.meta.features, version.features);
+        assert_eq!(None, version.features2);
+        assert_eq!(None, version.v);
+    }
+
+    #[test]
+    fn test_features_v2() {
+        let request = super::PublishRequest {
+            meta: super::MetaData {
+                features: [
+                    ("std".to_string(), vec!["serde/std".to_string()]),
+                    ("json".to_string(), vec!["dep:serde_json".to_string()]),
+                    ("derive".to_string(), vec!["serde?/derive".to_string()]),
+                ]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let version = super::PublishedVersion::from(&request);
+        assert_eq!(
+            vec!["std"],
+            version
+                .features
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+        );
+        let mut features2 = version.features2.unwrap().into_keys().collect::<Vec<_>>();
+        features2.sort();
+        assert_eq!(vec!["derive", "json"], features2);
+        assert_eq!(Some(2), version.v);
     }
 }
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// published version whose pull request waits for review
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingVersion {
+    pub branch: String,
+    pub pull_request: u64,
+    pub meta: crate::models::MetaData,
+}
+
+#[derive(Debug)]
+pub enum PublishStatus {
+    Published(Vec<crate::models::PublishedVersion>),
+    Pending(Box<PendingVersion>),
+}
+
+/// pull request that got merged or closed, as reported by a forge webhook
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReviewEvent {
+    /// number of the pull request, the iid of GitLab merge requests
+    pub pull_request: u64,
+    /// repository of the head branch, as identified by the forge
+    pub repository: String,
+    pub merged: bool,
+}
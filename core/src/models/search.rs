@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchRequest {
+    #[serde(default)]
+    pub q: String,
+    pub per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SearchResultCrate {
+    pub name: String,
+    pub max_version: String,
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SearchResultMeta {
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SearchResponse {
+    pub crates: Vec<SearchResultCrate>,
+    pub meta: SearchResultMeta,
+}
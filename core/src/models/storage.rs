@@ -1,31 +1,75 @@
 #[derive(Debug)]
 pub enum StorageError {
-    DetailMeLater,
+    NotFound(String),
+    AccessDenied(String),
+    Io(std::io::Error),
+    Backend(String),
+    Corrupt(String),
 }
 
-impl std::error::Error for StorageError {}
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl std::fmt::Display for StorageError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Storage error occured")
+        match self {
+            StorageError::NotFound(detail) => write!(f, "not found in storage: {}", detail),
+            StorageError::AccessDenied(detail) => write!(f, "access denied: {}", detail),
+            StorageError::Io(e) => write!(f, "io error: {}", e),
+            StorageError::Backend(detail) => write!(f, "storage backend failed: {}", detail),
+            StorageError::Corrupt(detail) => write!(f, "corrupt crate file: {}", detail),
+        }
     }
 }
 
 impl std::convert::From<std::io::Error> for StorageError {
-    fn from(_: std::io::Error) -> Self {
-        StorageError::DetailMeLater
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => StorageError::NotFound(e.to_string()),
+            std::io::ErrorKind::PermissionDenied => StorageError::AccessDenied(e.to_string()),
+            _ => StorageError::Io(e),
+        }
     }
 }
 
 pub struct StorageGetRequest {
     pub crate_name: String,
     pub crate_version: String,
-    pub result_sender: tokio::sync::oneshot::Sender<Option<Vec<u8>>>,
+    pub result_sender: tokio::sync::oneshot::Sender<Result<Vec<u8>, StorageError>>,
+}
+
+/// request on a single crate file that only reports success
+pub struct StorageFileRequest {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub result_sender: tokio::sync::oneshot::Sender<Result<(), StorageError>>,
 }
 
 pub struct StoragePutRequest {
     pub crate_name: String,
     pub crate_version: String,
-    pub data: Vec<u8>,
-    pub result_sender: tokio::sync::oneshot::Sender<bool>,
+    pub data: bytes::Bytes,
+    pub result_sender: tokio::sync::oneshot::Sender<Result<(), StorageError>>,
+}
+
+/// request on a crate file in the staging area of a single transaction
+pub struct StagedFileRequest {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub staging_id: String,
+    pub result_sender: tokio::sync::oneshot::Sender<Result<(), StorageError>>,
+}
+
+pub struct StagedPutRequest {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub staging_id: String,
+    pub data: bytes::Bytes,
+    pub result_sender: tokio::sync::oneshot::Sender<Result<(), StorageError>>,
 }
@@ -0,0 +1,95 @@
+//! JSON files that are replaced as a whole whenever their content changes.
+
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// File the catalog and the pending versions are persisted to.
+///
+/// Snapshots are written to a temporary file that replaces the file once it is
+/// synced, so a crash never leaves a truncated file behind. Within a runtime the
+/// write happens on a blocking thread; a snapshot that is taken later but written
+/// first is not overwritten by the older one.
+#[derive(Debug)]
+pub struct JsonFile {
+    path: PathBuf,
+    /// number of the last snapshot taken
+    taken: u64,
+    /// number of the last snapshot written, locked while writing
+    written: Arc<Mutex<u64>>,
+}
+
+impl JsonFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            taken: 0,
+            written: Default::default(),
+        }
+    }
+
+    pub fn save(&mut self, value: &impl Serialize) {
+        let data = match serde_json::to_vec(value) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("failed to serialize '{}': {}", self.path.display(), e);
+                return;
+            }
+        };
+        self.taken += 1;
+        let snapshot = self.taken;
+        let path = self.path.clone();
+        let written = self.written.clone();
+        let write = move || {
+            let mut written = written.lock().unwrap();
+            if *written > snapshot {
+                return;
+            }
+            if let Err(e) = write_file(&path, &data) {
+                tracing::error!("failed to write '{}': {}", path.display(), e);
+            }
+            *written = snapshot;
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(write);
+            }
+            Err(_) => write(),
+        }
+    }
+}
+
+fn write_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("entries.json");
+        let mut file = JsonFile::new(path.clone());
+        for i in 0..10 {
+            file.save(&vec![i]);
+        }
+
+        // the last snapshot wins, no matter in which order they were written
+        for _ in 0..100 {
+            if *file.written.lock().unwrap() == 10 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!("[9]", std::fs::read_to_string(&path).unwrap());
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -0,0 +1,350 @@
+use hmac::{Hmac, Mac, NewMac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::models::{PendingVersion, RegistryError, ReviewEvent};
+use crate::persist::JsonFile;
+
+/// Published versions whose pull request is not merged yet.
+///
+/// Entries are keyed by branch name, pull request events are matched by the id
+/// of their pull request. If a path is given they are persisted as JSON so
+/// pending reviews survive a restart.
+#[derive(Debug, Default)]
+pub struct PendingVersions {
+    entries: BTreeMap<String, PendingVersion>,
+    file: Option<JsonFile>,
+}
+
+impl PendingVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let entries = match std::fs::File::open(&path) {
+            Ok(file) => match serde_json::from_reader(std::io::BufReader::new(file)) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::error!(
+                        "failed to read pending versions '{}': {}",
+                        path.display(),
+                        e
+                    );
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            entries,
+            file: Some(JsonFile::new(path)),
+        }
+    }
+
+    pub fn get(&self, branch: &str) -> Option<&PendingVersion> {
+        self.entries.get(branch)
+    }
+
+    pub fn find_pull_request(&self, pull_request: u64) -> Option<&PendingVersion> {
+        self.entries
+            .values()
+            .find(|p| p.pull_request == pull_request)
+    }
+
+    pub fn find(&self, name: &str, vers: &str) -> Option<&PendingVersion> {
+        self.entries
+            .values()
+            .find(|p| p.meta.name.eq_ignore_ascii_case(name) && p.meta.vers == vers)
+    }
+
+    pub fn insert(&mut self, pending: PendingVersion) {
+        self.entries.insert(pending.branch.clone(), pending);
+        self.save();
+    }
+
+    pub fn remove(&mut self, branch: &str) -> Option<PendingVersion> {
+        let pending = self.entries.remove(branch);
+        if pending.is_some() {
+            self.save();
+        }
+        pending
+    }
+
+    fn save(&mut self) {
+        if let Some(file) = &mut self.file {
+            file.save(&self.entries);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct Head {
+    /// `null` if the repository of a fork got deleted
+    repo: Option<Repository>,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    number: u64,
+    #[serde(default)]
+    merged: bool,
+    head: Head,
+}
+
+/// pull request event of GitHub and Gitea
+#[derive(Deserialize)]
+struct PullRequestEvent {
+    action: String,
+    pull_request: PullRequest,
+}
+
+#[derive(Deserialize)]
+struct MergeRequestAttributes {
+    action: Option<String>,
+    iid: u64,
+    source_project_id: u64,
+}
+
+#[derive(Deserialize)]
+struct MergeRequestEvent {
+    object_attributes: MergeRequestAttributes,
+}
+
+/// Checks the signature of a forge webhook and extracts merged or closed pull requests.
+///
+/// Gitea also sends the GitHub headers, so it has to be recognized first. Other
+/// events are accepted but ignored. An empty secret verifies nothing and is refused.
+pub fn review_event(
+    headers: &reqwest::header::HeaderMap,
+    body: &[u8],
+    secret: &str,
+) -> Result<Option<ReviewEvent>, RegistryError> {
+    if secret.is_empty() {
+        return Err(RegistryError::Unauthorized(
+            "webhooks are refused as the configured secret is empty".into(),
+        ));
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let (event, verified) = if let Some(event) = header("x-gitea-event") {
+        let verified = header("x-gitea-signature")
+            .map(|signature| verify_signature(secret, body, signature))
+            .unwrap_or(false);
+        (event == "pull_request", verified)
+    } else if let Some(event) = header("x-github-event") {
+        let verified = header("x-hub-signature-256")
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .map(|signature| verify_signature(secret, body, signature))
+            .unwrap_or(false);
+        (event == "pull_request", verified)
+    } else if let Some(event) = header("x-gitlab-event") {
+        let verified = header("x-gitlab-token")
+            .map(|token| constant_time_eq(token.as_bytes(), secret.as_bytes()))
+            .unwrap_or(false);
+        (event == "Merge Request Hook", verified)
+    } else {
+        return Err(RegistryError::MalformedRequest(
+            "webhook was not sent by GitHub, GitLab or Gitea".into(),
+        ));
+    };
+
+    if !verified {
+        return Err(RegistryError::Unauthorized(
+            "webhook signature does not match the configured secret".into(),
+        ));
+    }
+    if !event {
+        return Ok(None);
+    }
+
+    let malformed = |e: serde_json::Error| {
+        RegistryError::MalformedRequest(format!("invalid webhook payload: {}", e))
+    };
+    if headers.contains_key("x-gitlab-event") {
+        let event: MergeRequestEvent = serde_json::from_slice(body).map_err(malformed)?;
+        let attributes = event.object_attributes;
+        let merged = match attributes.action.as_deref() {
+            Some("merge") => true,
+            Some("close") => false,
+            _ => return Ok(None),
+        };
+        Ok(Some(ReviewEvent {
+            pull_request: attributes.iid,
+            repository: attributes.source_project_id.to_string(),
+            merged,
+        }))
+    } else {
+        let event: PullRequestEvent = serde_json::from_slice(body).map_err(malformed)?;
+        let pull_request = event.pull_request;
+        Ok((event.action == "closed").then(|| ReviewEvent {
+            pull_request: pull_request.number,
+            repository: pull_request
+                .head
+                .repo
+                .map(|repo| repo.full_name)
+                .unwrap_or_default(),
+            merged: pull_request.merged,
+        }))
+    }
+}
+
+/// hex encoded HMAC-SHA256 of the body
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(body);
+    mac.verify(&signature).is_ok()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_github() {
+        let body = br#"{"action":"closed","number":3,"pull_request":{"number":3,"merged":true,"head":{"ref":"foo-0.1.0","repo":{"full_name":"owner/index"}}}}"#;
+        let signed = headers(&[
+            ("x-github-event", "pull_request".into()),
+            ("x-hub-signature-256", format!("sha256={}", sign(body))),
+        ]);
+        assert_eq!(
+            Some(ReviewEvent {
+                pull_request: 3,
+                repository: "owner/index".into(),
+                merged: true
+            }),
+            review_event(&signed, body, "secret").unwrap()
+        );
+        assert!(matches!(
+            review_event(&signed, body, "other"),
+            Err(RegistryError::Unauthorized(_))
+        ));
+
+        let body = br#"{"action":"opened","number":3,"pull_request":{"number":3,"merged":false,"head":{"ref":"foo-0.1.0","repo":{"full_name":"owner/index"}}}}"#;
+        let signed = headers(&[
+            ("x-github-event", "pull_request".into()),
+            ("x-hub-signature-256", format!("sha256={}", sign(body))),
+        ]);
+        assert_eq!(None, review_event(&signed, body, "secret").unwrap());
+
+        // pull requests of deleted forks have no head repository
+        let body = br#"{"action":"closed","number":4,"pull_request":{"number":4,"merged":true,"head":{"ref":"foo-0.1.0","repo":null}}}"#;
+        let signed = headers(&[
+            ("x-github-event", "pull_request".into()),
+            ("x-hub-signature-256", format!("sha256={}", sign(body))),
+        ]);
+        assert_eq!(
+            Some(String::new()),
+            review_event(&signed, body, "secret")
+                .unwrap()
+                .map(|event| event.repository)
+        );
+    }
+
+    #[test]
+    fn test_empty_secret() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"").unwrap();
+        let body = br#"{"action":"closed","number":3,"pull_request":{"number":3,"merged":true,"head":{"ref":"foo-0.1.0","repo":{"full_name":"owner/index"}}}}"#;
+        mac.update(body);
+        let signed = headers(&[
+            ("x-github-event", "pull_request".into()),
+            (
+                "x-hub-signature-256",
+                format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+            ),
+        ]);
+        assert!(matches!(
+            review_event(&signed, body, ""),
+            Err(RegistryError::Unauthorized(_))
+        ));
+        let signed = headers(&[
+            ("x-gitlab-event", "Merge Request Hook".into()),
+            ("x-gitlab-token", String::new()),
+        ]);
+        assert!(matches!(
+            review_event(&signed, b"{}", ""),
+            Err(RegistryError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_gitea() {
+        let body = br#"{"action":"closed","number":3,"pull_request":{"number":3,"merged":false,"head":{"ref":"foo-0.1.0","repo":{"full_name":"owner/index"}}}}"#;
+        let signed = headers(&[
+            ("x-github-event", "pull_request".into()),
+            ("x-gitea-event", "pull_request".into()),
+            ("x-gitea-signature", sign(body)),
+        ]);
+        assert_eq!(
+            Some(ReviewEvent {
+                pull_request: 3,
+                repository: "owner/index".into(),
+                merged: false
+            }),
+            review_event(&signed, body, "secret").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_gitlab() {
+        let body = br#"{"object_kind":"merge_request","object_attributes":{"id":41,"iid":3,"action":"merge","source_branch":"foo-0.1.0","source_project_id":7,"target_project_id":7}}"#;
+        let signed = headers(&[
+            ("x-gitlab-event", "Merge Request Hook".into()),
+            ("x-gitlab-token", "secret".into()),
+        ]);
+        assert_eq!(
+            Some(ReviewEvent {
+                pull_request: 3,
+                repository: "7".into(),
+                merged: true
+            }),
+            review_event(&signed, body, "secret").unwrap()
+        );
+
+        let unsigned = headers(&[("x-gitlab-event", "Merge Request Hook".into())]);
+        assert!(matches!(
+            review_event(&unsigned, body, "secret"),
+            Err(RegistryError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            review_event(&HeaderMap::new(), body, "secret"),
+            Err(RegistryError::MalformedRequest(_))
+        ));
+    }
+}
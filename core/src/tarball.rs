@@ -0,0 +1,435 @@
+use std::io::Read;
+use std::path::{Component, Path};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use crate::config::PublishConfig;
+use crate::models::{MetaData, RegistryError, StorageError};
+
+/// Name of the crate packed into a `.crate` file.
+///
+/// cargo packs all files below a `{name}-{version}` directory, so the name can
+/// be recovered from the first entry once the version is known.
+pub fn read_crate_name(data: &[u8], version: &str) -> Result<String, StorageError> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+    let mut entries = archive.entries().map_err(corrupt)?;
+    let entry = match entries.next() {
+        Some(entry) => entry.map_err(corrupt)?,
+        None => return Err(StorageError::Corrupt("crate file is empty".into())),
+    };
+    let path = entry.path().map_err(corrupt)?;
+
+    let root = match path.components().next() {
+        Some(std::path::Component::Normal(root)) => root.to_string_lossy().to_string(),
+        _ => {
+            return Err(StorageError::Corrupt(format!(
+                "unexpected entry '{}'",
+                path.display()
+            )))
+        }
+    };
+    match root.strip_suffix(&format!("-{}", version)) {
+        Some(name) if !name.is_empty() => Ok(name.into()),
+        _ => Err(StorageError::Corrupt(format!(
+            "directory '{}' does not match version '{}'",
+            root, version
+        ))),
+    }
+}
+
+fn corrupt(e: std::io::Error) -> StorageError {
+    StorageError::Corrupt(e.to_string())
+}
+
+/// Checks an uploaded `.crate` file before anything is stored.
+///
+/// The file has to be a gzip compressed tar whose entries all live below a
+/// `{name}-{version}` directory, including a `Cargo.toml` declaring the same
+/// name and version as the metadata. Entries escaping that directory, links
+/// to absolute paths or containing `..` and anything but files, directories and
+/// symlinks are rejected, as are crates unpacking to more than the configured size.
+pub fn verify(
+    data: impl Read,
+    meta: &MetaData,
+    config: &PublishConfig,
+) -> Result<(), RegistryError> {
+    let root = format!("{}-{}", meta.name, meta.vers);
+    let max_unpacked_size = config.max_unpacked_size();
+    // the limit also applies to tar headers, which aren't counted below
+    let decoder = flate2::read::GzDecoder::new(data).take(max_unpacked_size);
+    let mut archive = tar::Archive::new(decoder);
+    let mut unpacked_size = 0u64;
+    let mut manifest = None;
+
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let path = entry.path().map_err(invalid)?.into_owned();
+
+        let mut components = path.components();
+        match components.next() {
+            Some(Component::Normal(first)) if first == root.as_str() => {}
+            _ => {
+                return Err(invalid_entry(
+                    &path,
+                    &format!("must be inside directory '{}'", root),
+                ))
+            }
+        }
+        if !components.all(|c| matches!(c, Component::Normal(_))) {
+            return Err(invalid_entry(&path, "must not contain '.' or '..'"));
+        }
+
+        unpacked_size += entry.size();
+        if unpacked_size > max_unpacked_size {
+            return Err(RegistryError::InvalidCrate(format!(
+                "crate unpacks to more than {} bytes",
+                max_unpacked_size
+            )));
+        }
+
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                if path == Path::new(&root).join("Cargo.toml") {
+                    let mut content = String::new();
+                    entry
+                        .read_to_string(&mut content)
+                        .map_err(|e| invalid_entry(&path, &e.to_string()))?;
+                    manifest = Some(content);
+                }
+            }
+            tar::EntryType::Directory => {}
+            tar::EntryType::Symlink => {
+                let target = entry
+                    .link_name()
+                    .map_err(invalid)?
+                    .ok_or_else(|| invalid_entry(&path, "is a symlink without target"))?;
+                if escapes(&target) {
+                    return Err(invalid_entry(
+                        &path,
+                        &format!(
+                            "links to '{}', which may be outside of the crate",
+                            target.display()
+                        ),
+                    ));
+                }
+            }
+            entry_type => {
+                return Err(invalid_entry(
+                    &path,
+                    &format!("has unsupported type {:?}", entry_type),
+                ))
+            }
+        }
+    }
+
+    match manifest {
+        Some(manifest) => verify_manifest(&manifest, meta),
+        None => Err(RegistryError::InvalidCrate(format!(
+            "'{}/Cargo.toml' is missing",
+            root
+        ))),
+    }
+}
+
+/// whether a link target may leave the top level directory, any `..` counts as
+/// it may resolve through other links of the archive, e.g. `a -> b/b/..` with `b -> .`
+fn escapes(target: &Path) -> bool {
+    target
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Verifies a crate file while it is uploaded, see `verify`.
+///
+/// The chunks are unpacked on a blocking thread as they are fed. Verification
+/// stops reading at the first problem, which `finish` reports.
+pub struct Verifier {
+    chunks: Option<mpsc::Sender<Bytes>>,
+    result: tokio::task::JoinHandle<Result<(), RegistryError>>,
+}
+
+impl Verifier {
+    pub fn new(meta: MetaData, config: PublishConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(16);
+        let reader = ChunkReader {
+            receiver,
+            chunk: Bytes::new(),
+        };
+        Self {
+            chunks: Some(sender),
+            result: tokio::task::spawn_blocking(move || verify(reader, &meta, &config)),
+        }
+    }
+
+    pub async fn feed(&mut self, chunk: Bytes) {
+        if let Some(chunks) = &self.chunks {
+            if chunks.send(chunk).await.is_err() {
+                // verification is over, the rest of the file doesn't matter
+                self.chunks = None;
+            }
+        }
+    }
+
+    /// result of the verification once all chunks were fed
+    pub async fn finish(mut self) -> Result<(), RegistryError> {
+        self.chunks = None;
+        self.result
+            .await
+            .map_err(|e| RegistryError::Internal(format!("crate verification failed: {}", e)))?
+    }
+}
+
+/// reads the chunks fed to a `Verifier`, blocking until they arrive
+struct ChunkReader {
+    receiver: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let length = buf.len().min(self.chunk.len());
+        buf[..length].copy_from_slice(&self.chunk.split_to(length));
+        Ok(length)
+    }
+}
+
+fn verify_manifest(manifest: &str, meta: &MetaData) -> Result<(), RegistryError> {
+    let manifest: toml::Value = toml::from_str(manifest)
+        .map_err(|e| RegistryError::InvalidCrate(format!("invalid Cargo.toml: {}", e)))?;
+    let package = |key: &str| {
+        manifest
+            .get("package")
+            .and_then(|package| package.get(key))
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| {
+                RegistryError::InvalidCrate(format!("Cargo.toml has no package {}", key))
+            })
+    };
+
+    let name = package("name")?;
+    if name != meta.name {
+        return Err(RegistryError::InvalidCrate(format!(
+            "Cargo.toml declares name '{}' instead of '{}'",
+            name, meta.name
+        )));
+    }
+    let version = package("version")?;
+    if version != meta.vers {
+        return Err(RegistryError::InvalidCrate(format!(
+            "Cargo.toml declares version '{}' instead of '{}'",
+            version, meta.vers
+        )));
+    }
+    Ok(())
+}
+
+fn invalid(e: std::io::Error) -> RegistryError {
+    RegistryError::InvalidCrate(e.to_string())
+}
+
+fn invalid_entry(path: &Path, reason: &str) -> RegistryError {
+    RegistryError::InvalidCrate(format!("entry '{}' {}", path.display(), reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &[u8] = b"[package]\nname = \"foo\"\nversion = \"0.1.0\"\n";
+
+    fn meta() -> MetaData {
+        MetaData {
+            name: "foo".into(),
+            vers: "0.1.0".into(),
+            ..Default::default()
+        }
+    }
+
+    /// packs entries without the path checks of `tar::Builder`
+    fn pack(entries: &[(&str, tar::EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, entry_type, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            if *entry_type == tar::EntryType::Symlink {
+                header
+                    .set_link_name(std::str::from_utf8(data).unwrap())
+                    .unwrap();
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, std::io::empty()).unwrap();
+            } else {
+                header.set_size(data.len() as u64);
+                header.set_cksum();
+                builder.append(&header, *data).unwrap();
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn verify_default(data: &[u8]) -> Result<(), RegistryError> {
+        verify(data, &meta(), &PublishConfig::default())
+    }
+
+    fn assert_invalid(result: Result<(), RegistryError>, detail: &str) {
+        match result {
+            Err(RegistryError::InvalidCrate(e)) => assert!(e.contains(detail), "{}", e),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let data = pack(&[
+            ("foo-0.1.0/Cargo.toml", tar::EntryType::Regular, MANIFEST),
+            ("foo-0.1.0/src", tar::EntryType::Directory, b""),
+            ("foo-0.1.0/src/lib.rs", tar::EntryType::Regular, b""),
+            (
+                "foo-0.1.0/src/link.rs",
+                tar::EntryType::Symlink,
+                b"./lib.rs",
+            ),
+        ]);
+        verify_default(&data).unwrap();
+
+        assert_invalid(verify_default(b"not a crate"), "");
+        assert_invalid(
+            verify_default(&pack(&[(
+                "foo-0.1.0/src/lib.rs",
+                tar::EntryType::Regular,
+                b"",
+            )])),
+            "Cargo.toml' is missing",
+        );
+        assert_invalid(
+            verify_default(&pack(&[(
+                "foo-0.2.0/Cargo.toml",
+                tar::EntryType::Regular,
+                MANIFEST,
+            )])),
+            "must be inside directory 'foo-0.1.0'",
+        );
+        let other_version = b"[package]\nname = \"foo\"\nversion = \"0.2.0\"\n";
+        assert_invalid(
+            verify_default(&pack(&[(
+                "foo-0.1.0/Cargo.toml",
+                tar::EntryType::Regular,
+                other_version,
+            )])),
+            "declares version '0.2.0'",
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_escaping_entries() {
+        for (path, entry_type, data, detail) in [
+            (
+                "foo-0.1.0/../evil",
+                tar::EntryType::Regular,
+                &b""[..],
+                "'..'",
+            ),
+            ("/etc/evil", tar::EntryType::Regular, b"", "must be inside"),
+            (
+                "foo-0.1.0/link",
+                tar::EntryType::Symlink,
+                b"../../etc",
+                "outside",
+            ),
+            (
+                "foo-0.1.0/link",
+                tar::EntryType::Symlink,
+                b"/etc",
+                "outside",
+            ),
+            (
+                "foo-0.1.0/link",
+                tar::EntryType::Symlink,
+                b"../README.md",
+                "outside",
+            ),
+            (
+                "foo-0.1.0/dev",
+                tar::EntryType::Char,
+                b"",
+                "unsupported type",
+            ),
+            (
+                "foo-0.1.0/dev",
+                tar::EntryType::Block,
+                b"",
+                "unsupported type",
+            ),
+        ] {
+            let data = pack(&[
+                ("foo-0.1.0/Cargo.toml", tar::EntryType::Regular, MANIFEST),
+                (path, entry_type, data),
+            ]);
+            assert_invalid(verify_default(&data), detail);
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_chained_links() {
+        // `a` resolves to three levels above the top level directory through `b`
+        let data = pack(&[
+            ("foo-0.1.0/Cargo.toml", tar::EntryType::Regular, MANIFEST),
+            ("foo-0.1.0/b", tar::EntryType::Symlink, b"."),
+            ("foo-0.1.0/a", tar::EntryType::Symlink, b"b/b/b/../../.."),
+        ]);
+        assert_invalid(
+            verify_default(&data),
+            "'foo-0.1.0/a' links to 'b/b/b/../../..'",
+        );
+    }
+
+    #[test]
+    fn test_verify_limits() {
+        let data = pack(&[
+            ("foo-0.1.0/Cargo.toml", tar::EntryType::Regular, MANIFEST),
+            ("foo-0.1.0/zeros", tar::EntryType::Regular, &[0; 4096]),
+        ]);
+        let limit = |max_unpacked_size| PublishConfig {
+            max_unpacked_size: Some(max_unpacked_size),
+            ..Default::default()
+        };
+
+        verify(&data[..], &meta(), &limit(1 << 20)).unwrap();
+        assert_invalid(
+            verify(&data[..], &meta(), &limit(4096)),
+            "unpacks to more than 4096 bytes",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verifier() {
+        let data = pack(&[
+            ("foo-0.1.0/Cargo.toml", tar::EntryType::Regular, MANIFEST),
+            ("foo-0.1.0/src/lib.rs", tar::EntryType::Regular, b""),
+        ]);
+        let mut verifier = Verifier::new(meta(), PublishConfig::default());
+        for chunk in data.chunks(7) {
+            verifier.feed(Bytes::copy_from_slice(chunk)).await;
+        }
+        verifier.finish().await.unwrap();
+
+        // chunks fed after a failed verification are dropped
+        let mut verifier = Verifier::new(meta(), PublishConfig::default());
+        for _ in 0..100 {
+            verifier.feed(Bytes::from_static(b"not a crate")).await;
+        }
+        assert_invalid(verifier.finish().await, "");
+    }
+}
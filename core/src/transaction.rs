@@ -0,0 +1,1019 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::PublishConfig;
+use crate::journal::{Journal, StagedFile, Step};
+use crate::models::{MetaData, PublishRequest, PublishStatus, RegistryError, StorageError};
+use crate::tarball::Verifier;
+use crate::upload::PublishBodyParser;
+use crate::{BackendCommand, StorageCommand};
+
+/// attempts to move a staged crate file to its final key
+const PROMOTE_ATTEMPTS: u32 = 3;
+const PROMOTE_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Body of a publish request, streamed into the staging area as it arrives.
+///
+/// Each chunk of the crate file is hashed, verified and appended to the staged
+/// file right away, so the crate file is never held in memory as a whole. The
+/// staged file is journaled before its first chunk is stored and removed again
+/// if the upload fails or is dropped before it is finished.
+pub struct Upload {
+    storage: mpsc::Sender<StorageCommand>,
+    journal: Arc<Journal>,
+    config: PublishConfig,
+    parser: PublishBodyParser,
+    staging: Option<Staging>,
+}
+
+/// staged file of an upload, started once the metadata is known
+struct Staging {
+    operation: u64,
+    staged: StagedFile,
+    hasher: Sha256,
+    verifier: Verifier,
+}
+
+impl Staging {
+    async fn begin(journal: &Journal, meta: &MetaData, config: &PublishConfig) -> Self {
+        let operation = journal
+            .begin(&format!("publish transaction {} {}", meta.name, meta.vers))
+            .await;
+        let staged = StagedFile {
+            name: meta.name.clone(),
+            vers: meta.vers.clone(),
+            staging_id: staging_id(operation),
+        };
+        journal
+            .record(operation, Step::Staged(staged.clone()))
+            .await;
+        Self {
+            operation,
+            staged,
+            hasher: Sha256::new(),
+            verifier: Verifier::new(meta.clone(), config.clone()),
+        }
+    }
+}
+
+/// completely uploaded crate file waiting for its index entry
+pub struct StagedUpload {
+    operation: u64,
+    staged: StagedFile,
+    request: PublishRequest,
+}
+
+impl StagedUpload {
+    pub fn request(&self) -> &PublishRequest {
+        &self.request
+    }
+}
+
+impl Upload {
+    pub fn new(
+        storage: mpsc::Sender<StorageCommand>,
+        journal: Arc<Journal>,
+        config: PublishConfig,
+    ) -> Self {
+        Self {
+            storage,
+            journal,
+            parser: PublishBodyParser::new(&config),
+            config,
+            staging: None,
+        }
+    }
+
+    /// Handles the next chunk of the request body, a failure aborts the upload.
+    pub async fn push(&mut self, chunk: Bytes) -> Result<(), RegistryError> {
+        let result = self.append(chunk).await;
+        if result.is_err() {
+            self.abort().await;
+        }
+        result
+    }
+
+    /// Completes the staged crate file once the whole body was pushed.
+    pub async fn finish(mut self) -> Result<StagedUpload, RegistryError> {
+        let meta = match self.parser.finish() {
+            Ok(meta) => meta,
+            Err(e) => {
+                self.abort().await;
+                return Err(e);
+            }
+        };
+        // an empty crate file has no chunk that started the staging
+        let staging = match self.staging.take() {
+            Some(staging) => staging,
+            None => Staging::begin(&self.journal, &meta, &self.config).await,
+        };
+
+        let result = match staging.verifier.finish().await {
+            Ok(()) => {
+                send_storage(&self.storage, |result_sender| {
+                    StorageCommand::CompleteStaged(file_request(&staging.staged, result_sender))
+                })
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            remove_staged(
+                &self.storage,
+                &self.journal,
+                staging.operation,
+                &staging.staged,
+            )
+            .await;
+            return Err(e);
+        }
+
+        let cksum = hex::encode(staging.hasher.finalize());
+        self.journal
+            .record(staging.operation, Step::Uploaded(cksum.clone()))
+            .await;
+        Ok(StagedUpload {
+            operation: staging.operation,
+            staged: staging.staged,
+            request: PublishRequest { meta, cksum },
+        })
+    }
+
+    async fn append(&mut self, chunk: Bytes) -> Result<(), RegistryError> {
+        let data = match self.parser.push(chunk)? {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        let staging = match &mut self.staging {
+            Some(staging) => staging,
+            staging => {
+                let meta = self.parser.meta().ok_or_else(|| {
+                    RegistryError::Internal("crate file arrived before its metadata".into())
+                })?;
+                staging.insert(Staging::begin(&self.journal, meta, &self.config).await)
+            }
+        };
+
+        staging.hasher.update(&data);
+        staging.verifier.feed(data.clone()).await;
+        send_storage(&self.storage, |result_sender| {
+            StorageCommand::AppendStaged(crate::models::StagedPutRequest {
+                crate_name: staging.staged.name.clone(),
+                crate_version: staging.staged.vers.clone(),
+                staging_id: staging.staged.staging_id.clone(),
+                data,
+                result_sender,
+            })
+        })
+        .await
+    }
+
+    async fn abort(&mut self) {
+        if let Some(staging) = self.staging.take() {
+            remove_staged(
+                &self.storage,
+                &self.journal,
+                staging.operation,
+                &staging.staged,
+            )
+            .await;
+        }
+    }
+}
+
+impl Drop for Upload {
+    /// an upload dropped with the request, e.g. as the client disconnected
+    fn drop(&mut self) {
+        if let Some(staging) = self.staging.take() {
+            let storage = self.storage.clone();
+            let journal = self.journal.clone();
+            tokio::spawn(async move {
+                remove_staged(&storage, &journal, staging.operation, &staging.staged).await
+            });
+        }
+    }
+}
+
+/// Publishes an uploaded crate file together with its index entry.
+///
+/// The staged crate file is only promoted to its final key once the index
+/// entry is committed. A failed index update removes the staged file again
+/// unless the index can't be read to confirm the version is missing, then the
+/// file is left to `recover`. A re-publish with identical contents, which the
+/// backend accepts without changing the index, promotes its crate file again
+/// and so restores one that got lost. The transaction runs in its own task, so
+/// it is finished even if the client stops waiting for it. Its steps are
+/// journaled so `recover` can finish it after a crash.
+pub async fn publish(
+    backend: mpsc::Sender<BackendCommand>,
+    storage: mpsc::Sender<StorageCommand>,
+    journal: Arc<Journal>,
+    token: String,
+    upload: StagedUpload,
+) -> Result<PublishStatus, RegistryError> {
+    tokio::spawn(async move { execute(&backend, &storage, &journal, token, upload).await })
+        .await
+        .map_err(|e| RegistryError::Internal(format!("publish transaction failed: {}", e)))?
+}
+
+async fn execute(
+    backend: &mpsc::Sender<BackendCommand>,
+    storage: &mpsc::Sender<StorageCommand>,
+    journal: &Journal,
+    token: String,
+    upload: StagedUpload,
+) -> Result<PublishStatus, RegistryError> {
+    let StagedUpload {
+        operation,
+        staged,
+        request,
+    } = upload;
+    let (name, vers) = (&staged.name, &staged.vers);
+    let cksum = request.cksum.clone();
+
+    let result = match commit(backend, token, request).await {
+        Ok(status) => Ok(status),
+        // a failed response doesn't tell whether the index entry was written
+        Err(e) => match indexed_cksum(backend, name, vers).await {
+            Ok(Some(indexed)) if indexed == cksum => {
+                tracing::warn!(
+                    "'{}' version '{}' is in the index despite: {}",
+                    name,
+                    vers,
+                    e
+                );
+                Err(e)
+            }
+            Ok(_) => {
+                remove_staged(storage, journal, operation, &staged).await;
+                return Err(e);
+            }
+            Err(read_error) => {
+                // the operation stays unfinished, so `recover` handles the staged file
+                tracing::error!(
+                    "keeping staged '{}' version '{}' as the index can't be read: {}",
+                    name,
+                    vers,
+                    read_error
+                );
+                return Err(e);
+            }
+        },
+    };
+    journal.record(operation, Step::Committed).await;
+
+    let mut backoff = PROMOTE_BACKOFF;
+    for remaining in (0..PROMOTE_ATTEMPTS).rev() {
+        match send_storage(storage, |result_sender| {
+            StorageCommand::Promote(file_request(&staged, result_sender))
+        })
+        .await
+        {
+            Ok(()) => {
+                journal.end(operation).await;
+                return result;
+            }
+            Err(e) if remaining > 0 => {
+                tracing::warn!("failed to promote '{}' version '{}': {}", name, vers, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "'{}' version '{}' is in the index but its crate file is still staged: {}",
+                    name,
+                    vers,
+                    e
+                );
+                return Err(e);
+            }
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
+/// removes a staged crate file that won't be published, its operation stays
+/// unfinished for `recover` if that fails
+async fn remove_staged(
+    storage: &mpsc::Sender<StorageCommand>,
+    journal: &Journal,
+    operation: u64,
+    staged: &StagedFile,
+) {
+    tracing::info!(
+        "removing staged '{}' version '{}'",
+        staged.name,
+        staged.vers
+    );
+    match send_storage(storage, |result_sender| {
+        StorageCommand::DeleteStaged(file_request(staged, result_sender))
+    })
+    .await
+    {
+        // nothing was stored yet
+        Ok(()) | Err(RegistryError::Storage(StorageError::NotFound(_))) => {
+            journal.end(operation).await
+        }
+        Err(e) => tracing::error!(
+            "failed to remove staged '{}' version '{}': {}",
+            staged.name,
+            staged.vers,
+            e
+        ),
+    }
+}
+
+/// Finishes transactions a crash interrupted.
+///
+/// Staged crate files are promoted if their version made it into the index and
+/// removed otherwise. The index is read with the cargolifter token of the backend.
+pub async fn recover(
+    journal: &Journal,
+    backend: &mpsc::Sender<BackendCommand>,
+    storage: &mpsc::Sender<StorageCommand>,
+) {
+    for operation in journal.take_unfinished(|operation| operation.staged().is_some()) {
+        let staged = match operation.staged() {
+            Some(staged) => staged,
+            None => continue,
+        };
+        let (name, vers) = (&staged.name, &staged.vers);
+        // an upload that didn't finish can't have made it into the index
+        let promote = operation.committed()
+            || match operation.uploaded() {
+                Some(uploaded) => match indexed_cksum(backend, name, vers).await {
+                    Ok(cksum) => cksum.as_deref() == Some(uploaded),
+                    Err(e) => {
+                        tracing::error!("failed to recover '{}' version '{}': {}", name, vers, e);
+                        continue;
+                    }
+                },
+                None => false,
+            };
+
+        tracing::warn!(
+            "{} staged '{}' version '{}' of unfinished operation {}",
+            if promote { "promoting" } else { "removing" },
+            name,
+            vers,
+            operation.id
+        );
+        let result = send_storage(storage, |result_sender| {
+            let request = file_request(staged, result_sender);
+            if promote {
+                StorageCommand::Promote(request)
+            } else {
+                StorageCommand::DeleteStaged(request)
+            }
+        })
+        .await;
+        match result {
+            // the crash happened after the staged file was handled
+            Ok(()) | Err(RegistryError::Storage(StorageError::NotFound(_))) => {
+                journal.end(operation.id).await
+            }
+            Err(e) => tracing::error!("failed to recover '{}' version '{}': {}", name, vers, e),
+        }
+    }
+}
+
+/// staging ids keep the crate files of concurrent transactions apart, even
+/// across restarts that reuse operation ids
+fn staging_id(operation: u64) -> String {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!("{}-{}", millis, operation)
+}
+
+/// checksum of the version in the index, none if the version or its index file is missing
+async fn indexed_cksum(
+    backend: &mpsc::Sender<BackendCommand>,
+    name: &str,
+    vers: &str,
+) -> Result<Option<String>, RegistryError> {
+    let (tx, rx) = oneshot::channel();
+    backend
+        .send(BackendCommand::GetIndexFile(
+            None,
+            crate::get_crate_file_path(name),
+            tx,
+        ))
+        .await
+        .map_err(|_| RegistryError::Internal("failed to send backend command".into()))?;
+    let index_file = rx
+        .await
+        .map_err(|_| RegistryError::Internal("failed to receive backend response".into()))?;
+    Ok(match index_file {
+        Ok(index_file) => crate::commands::utils::read_versions(&index_file.content, "utf-8")?
+            .into_iter()
+            .find(|version| version.vers == vers)
+            .map(|version| version.cksum),
+        Err(RegistryError::NotFound(_)) => None,
+        Err(e) => return Err(e),
+    })
+}
+
+async fn commit(
+    backend: &mpsc::Sender<BackendCommand>,
+    token: String,
+    request: PublishRequest,
+) -> Result<PublishStatus, RegistryError> {
+    let (tx, rx) = oneshot::channel();
+    backend
+        .send(BackendCommand::Publish(token, Box::new(request), tx))
+        .await
+        .map_err(|_| RegistryError::Internal("failed to send backend command".into()))?;
+    rx.await
+        .map_err(|_| RegistryError::Internal("failed to receive backend response".into()))?
+}
+
+fn file_request(
+    staged: &StagedFile,
+    result_sender: oneshot::Sender<Result<(), StorageError>>,
+) -> crate::models::StagedFileRequest {
+    crate::models::StagedFileRequest {
+        crate_name: staged.name.clone(),
+        crate_version: staged.vers.clone(),
+        staging_id: staged.staging_id.clone(),
+        result_sender,
+    }
+}
+
+async fn send_storage(
+    storage: &mpsc::Sender<StorageCommand>,
+    command: impl FnOnce(oneshot::Sender<Result<(), StorageError>>) -> StorageCommand,
+) -> Result<(), RegistryError> {
+    let (tx, rx) = oneshot::channel();
+    storage
+        .send(command(tx))
+        .await
+        .map_err(|_| RegistryError::Internal("failed to send storage command".into()))?;
+    rx.await
+        .map_err(|_| RegistryError::Internal("failed to receive storage response".into()))?
+        .map_err(RegistryError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// in memory storage failing the operation named by `fail`
+    struct MockStorage {
+        files: Files,
+        fail: Option<&'static str>,
+    }
+
+    impl MockStorage {
+        fn check(&self, operation: &str) -> Result<(), StorageError> {
+            match self.fail {
+                Some(fail) if fail == operation => Err(StorageError::Backend(operation.into())),
+                _ => Ok(()),
+            }
+        }
+
+        fn insert(&self, key: String, data: &[u8]) -> Result<(), StorageError> {
+            self.files.lock().unwrap().insert(key, data.to_vec());
+            Ok(())
+        }
+
+        fn remove(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+            self.files
+                .lock()
+                .unwrap()
+                .remove(key)
+                .ok_or_else(|| StorageError::NotFound(key.into()))
+        }
+    }
+
+    #[async_trait]
+    impl crate::Storage for MockStorage {
+        async fn get(&self, name: &str, vers: &str) -> Result<Vec<u8>, StorageError> {
+            let key = crate::get_crate_storage_key(name, vers);
+            let files = self.files.lock().unwrap();
+            files.get(&key).cloned().ok_or(StorageError::NotFound(key))
+        }
+
+        async fn put(&mut self, name: &str, vers: &str, data: &[u8]) -> Result<(), StorageError> {
+            self.check("put")?;
+            self.insert(crate::get_crate_storage_key(name, vers), data)
+        }
+
+        async fn delete(&mut self, name: &str, vers: &str) -> Result<(), StorageError> {
+            self.check("delete")?;
+            self.remove(&crate::get_crate_storage_key(name, vers))
+                .map(|_| ())
+        }
+
+        async fn get_mirror(&self, name: &str, _: &str) -> Result<Vec<u8>, StorageError> {
+            Err(StorageError::NotFound(name.into()))
+        }
+
+        async fn put_mirror(&mut self, _: &str, _: &str, _: &[u8]) -> Result<(), StorageError> {
+            Ok(())
+        }
+
+        async fn append_staged(
+            &mut self,
+            name: &str,
+            vers: &str,
+            id: &str,
+            data: &[u8],
+        ) -> Result<(), StorageError> {
+            self.check("append_staged")?;
+            let key = crate::get_staging_storage_key(name, vers, id);
+            let mut files = self.files.lock().unwrap();
+            files.entry(key).or_default().extend_from_slice(data);
+            Ok(())
+        }
+
+        async fn complete_staged(&mut self, _: &str, _: &str, _: &str) -> Result<(), StorageError> {
+            self.check("complete_staged")
+        }
+
+        async fn promote(&mut self, name: &str, vers: &str, id: &str) -> Result<(), StorageError> {
+            self.check("promote")?;
+            let data = self.remove(&crate::get_staging_storage_key(name, vers, id))?;
+            self.insert(crate::get_crate_storage_key(name, vers), &data)
+        }
+
+        async fn delete_staged(
+            &mut self,
+            name: &str,
+            vers: &str,
+            id: &str,
+        ) -> Result<(), StorageError> {
+            self.check("delete_staged")?;
+            self.remove(&crate::get_staging_storage_key(name, vers, id))
+                .map(|_| ())
+        }
+    }
+
+    /// backend answering publishes with `result` once `release` is notified and
+    /// serving `index` as index file, returns the number of received publishes
+    fn backend_with_index(
+        result: fn() -> Result<PublishStatus, RegistryError>,
+        release: Option<Arc<tokio::sync::Notify>>,
+        index: fn() -> Result<String, RegistryError>,
+    ) -> (mpsc::Sender<BackendCommand>, Arc<Mutex<usize>>) {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let received = Arc::new(Mutex::new(0));
+        let counter = received.clone();
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                match command {
+                    BackendCommand::Publish(_, _, sender) => {
+                        *counter.lock().unwrap() += 1;
+                        if let Some(release) = &release {
+                            release.notified().await;
+                        }
+                        let _ = sender.send(result());
+                    }
+                    BackendCommand::GetIndexFile(_, _, sender) => {
+                        let _ = sender.send(index().map(|content| crate::models::IndexFile {
+                            content,
+                            sha: String::new(),
+                        }));
+                    }
+                    _ => {}
+                }
+            }
+        });
+        (sender, received)
+    }
+
+    fn backend(
+        result: fn() -> Result<PublishStatus, RegistryError>,
+        release: Option<Arc<tokio::sync::Notify>>,
+    ) -> (mpsc::Sender<BackendCommand>, Arc<Mutex<usize>>) {
+        backend_with_index(result, release, unindexed)
+    }
+
+    fn storage(fail: Option<&'static str>) -> (mpsc::Sender<StorageCommand>, Files) {
+        let files = Files::default();
+        let storage = MockStorage {
+            files: files.clone(),
+            fail,
+        };
+        let (_, sender) = crate::StorageService::new(storage).run();
+        (sender, files)
+    }
+
+    fn journal() -> Arc<Journal> {
+        Arc::new(Journal::new())
+    }
+
+    /// `foo` in version 0.1.0, packed like cargo does
+    fn crate_file() -> Vec<u8> {
+        let manifest = b"[package]\nname = \"foo\"\nversion = \"0.1.0\"\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        builder
+            .append_data(&mut header, "foo-0.1.0/Cargo.toml", &manifest[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// publish request body as sent by cargo
+    fn body(data: &[u8]) -> Vec<u8> {
+        let meta = serde_json::to_vec(&MetaData {
+            name: "foo".into(),
+            vers: "0.1.0".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut body = Vec::new();
+        body.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+        body.extend_from_slice(&meta);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        body
+    }
+
+    async fn upload_body(
+        storage: &mpsc::Sender<StorageCommand>,
+        journal: &Arc<Journal>,
+        body: &[u8],
+    ) -> Result<StagedUpload, RegistryError> {
+        let mut upload = Upload::new(storage.clone(), journal.clone(), PublishConfig::default());
+        for chunk in body.chunks(16) {
+            upload.push(Bytes::copy_from_slice(chunk)).await?;
+        }
+        upload.finish().await
+    }
+
+    async fn upload(
+        storage: &mpsc::Sender<StorageCommand>,
+        journal: &Arc<Journal>,
+    ) -> StagedUpload {
+        upload_body(storage, journal, &body(&crate_file()))
+            .await
+            .unwrap()
+    }
+
+    fn published() -> Result<PublishStatus, RegistryError> {
+        Ok(PublishStatus::Published(vec![]))
+    }
+
+    fn conflict() -> Result<PublishStatus, RegistryError> {
+        Err(RegistryError::Conflict("stale".into()))
+    }
+
+    fn unindexed() -> Result<String, RegistryError> {
+        Err(RegistryError::NotFound("index file".into()))
+    }
+
+    fn unreadable() -> Result<String, RegistryError> {
+        Err(RegistryError::Internal("index unavailable".into()))
+    }
+
+    /// index entry of `crate_file()`
+    fn indexed() -> Result<String, RegistryError> {
+        Ok(format!(
+            r#"{{"name":"foo","vers":"0.1.0","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+            hex::encode(Sha256::digest(&crate_file()))
+        ))
+    }
+
+    fn keys(files: &Files) -> Vec<String> {
+        let mut keys = files.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// whether the only file is the staged crate file of a transaction
+    fn staged_only(files: &Files) -> bool {
+        matches!(&keys(files)[..], [key] if key.starts_with("staging/") && key.ends_with("/3/f/foo/0.1.0.crate"))
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (backend, _) = backend(published, None);
+        let (storage, files) = storage(None);
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        assert_eq!(
+            hex::encode(Sha256::digest(&crate_file())),
+            upload.request().cksum
+        );
+        assert!(staged_only(&files));
+
+        let status = publish(backend, storage, journal, "token".into(), upload).await;
+        assert!(matches!(status, Ok(PublishStatus::Published(_))));
+        assert_eq!(vec!["3/f/foo/0.1.0.crate"], keys(&files));
+        let stored = files.lock().unwrap()["3/f/foo/0.1.0.crate"].clone();
+        assert_eq!(crate_file(), stored);
+    }
+
+    #[tokio::test]
+    async fn test_failed_stage() {
+        let (storage, files) = storage(Some("append_staged"));
+
+        let result = upload_body(&storage, &journal(), &body(&crate_file())).await;
+        assert!(matches!(result, Err(RegistryError::Storage(_))));
+        assert!(keys(&files).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let journal = Arc::new(Journal::open(path));
+        let (storage, files) = storage(None);
+
+        let result = upload_body(&storage, &journal, &body(b"not a crate")).await;
+        assert!(matches!(result, Err(RegistryError::InvalidCrate(_))));
+        let mut truncated = body(&crate_file());
+        truncated.pop();
+        let result = upload_body(&storage, &journal, &truncated).await;
+        assert!(matches!(result, Err(RegistryError::MalformedRequest(_))));
+
+        assert!(keys(&files).is_empty());
+        assert!(Journal::open(path).take_unfinished(|_| true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_upload() {
+        let (storage, files) = storage(None);
+
+        // the client disconnects in the middle of the crate file
+        let body = body(&crate_file());
+        let mut upload = Upload::new(storage, journal(), PublishConfig::default());
+        upload
+            .push(Bytes::copy_from_slice(&body[..body.len() - 10]))
+            .await
+            .unwrap();
+        assert!(staged_only(&files));
+        drop(upload);
+
+        for _ in 0..100 {
+            if keys(&files).is_empty() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("staged file was not removed: {:?}", keys(&files));
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit() {
+        let (backend, received) = backend(conflict, None);
+        let (storage, files) = storage(None);
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        let status = publish(backend, storage, journal, "token".into(), upload).await;
+        assert!(matches!(status, Err(RegistryError::Conflict(_))));
+        assert_eq!(1, *received.lock().unwrap());
+        assert!(keys(&files).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_cleanup() {
+        let (backend, _) = backend(conflict, None);
+        let (storage, files) = storage(Some("delete_staged"));
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        let status = publish(backend, storage, journal, "token".into(), upload).await;
+        assert!(matches!(status, Err(RegistryError::Conflict(_))));
+        assert!(staged_only(&files));
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_indexed() {
+        // the index was updated although the backend reported a failure
+        let (backend, _) = backend_with_index(conflict, None, indexed);
+        let (storage, files) = storage(None);
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        let status = publish(backend, storage, journal, "token".into(), upload).await;
+        assert!(matches!(status, Err(RegistryError::Conflict(_))));
+        assert_eq!(vec!["3/f/foo/0.1.0.crate"], keys(&files));
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_unreadable_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let (backend, _) = backend_with_index(conflict, None, unreadable);
+        let (storage, files) = storage(None);
+
+        let journal = Arc::new(Journal::open(path));
+        let upload = upload(&storage, &journal).await;
+        let status = publish(backend, storage.clone(), journal, "token".into(), upload).await;
+        assert!(matches!(status, Err(RegistryError::Conflict(_))));
+        assert!(staged_only(&files));
+
+        // the staged file is journaled, so it is handled once the index can be read
+        let journal = Journal::open(path);
+        let (backend, _) = backend_with_index(published, None, indexed);
+        recover(&journal, &backend, &storage).await;
+        assert_eq!(vec!["3/f/foo/0.1.0.crate"], keys(&files));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_staging() {
+        let (storage, files) = storage(None);
+        let journal = journal();
+
+        // a transaction waits for its index update
+        let release = Arc::new(tokio::sync::Notify::new());
+        let (waiting, received) = backend(published, Some(release));
+        let upload1 = upload(&storage, &journal).await;
+        tokio::spawn(publish(
+            waiting,
+            storage.clone(),
+            journal.clone(),
+            "token".into(),
+            upload1,
+        ));
+        while *received.lock().unwrap() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // a failing transaction of the same version only removes its own file
+        let (failing, _) = backend(conflict, None);
+        let upload2 = upload(&storage, &journal).await;
+        assert_eq!(2, keys(&files).len());
+        let status = publish(failing, storage, journal, "token".into(), upload2).await;
+        assert!(matches!(status, Err(RegistryError::Conflict(_))));
+        assert!(staged_only(&files));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_promote() {
+        let (backend, _) = backend(published, None);
+        let (storage, files) = storage(Some("promote"));
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        let status = publish(backend, storage, journal, "token".into(), upload).await;
+        assert!(matches!(status, Err(RegistryError::Storage(_))));
+        assert!(staged_only(&files));
+    }
+
+    #[tokio::test]
+    async fn test_finishes_without_client() {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let (backend, received) = backend(published, Some(release.clone()));
+        let (storage, files) = storage(None);
+        let journal = journal();
+
+        let upload = upload(&storage, &journal).await;
+        let client = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            publish(backend, storage, journal, "token".into(), upload),
+        );
+        assert!(client.await.is_err());
+        assert_eq!(1, *received.lock().unwrap());
+
+        release.notify_one();
+        for _ in 0..100 {
+            if keys(&files) == vec!["3/f/foo/0.1.0.crate"] {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("crate file was not promoted: {:?}", keys(&files));
+    }
+
+    #[tokio::test]
+    async fn test_recover_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let (storage, files) = storage(None);
+
+        // the process dies while the index is updated
+        let (backend, received) = backend(published, Some(Arc::new(tokio::sync::Notify::new())));
+        let journal = Arc::new(Journal::open(path));
+        let upload = upload(&storage, &journal).await;
+        let crashed = tokio::spawn(publish(
+            backend,
+            storage.clone(),
+            journal,
+            "token".into(),
+            upload,
+        ));
+        while *received.lock().unwrap() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        crashed.abort();
+        assert!(staged_only(&files));
+
+        let journal = Journal::open(path);
+        let (backend, _) = backend_with_index(published, None, unindexed);
+        recover(&journal, &backend, &storage).await;
+        assert!(keys(&files).is_empty());
+        assert!(Journal::open(path).take_unfinished(|_| true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recover_unreadable_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let (storage, files) = storage(None);
+
+        let journal = Journal::open(path);
+        let operation = journal.begin("publish").await;
+        let staged = StagedFile {
+            name: "foo".into(),
+            vers: "0.1.0".into(),
+            staging_id: "1".into(),
+        };
+        journal
+            .record(operation, Step::Staged(staged.clone()))
+            .await;
+        journal
+            .record(operation, Step::Uploaded("abc".into()))
+            .await;
+        let key = crate::get_staging_storage_key("foo", "0.1.0", "1");
+        files.lock().unwrap().insert(key.clone(), vec![1]);
+        drop(journal);
+
+        // the version may be published, so nothing is removed until the index is read
+        let journal = Journal::open(path);
+        let (backend, _) = backend_with_index(published, None, unreadable);
+        recover(&journal, &backend, &storage).await;
+        assert_eq!(vec![key], keys(&files));
+        let unfinished = Journal::open(path).take_unfinished(|_| true);
+        assert_eq!(Some(&staged), unfinished[0].staged());
+    }
+
+    #[tokio::test]
+    async fn test_recover_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let path = path.to_str().unwrap();
+        let (storage, files) = storage(None);
+
+        let journal = Journal::open(path);
+        // baz is in the index with the crate file of another transaction, the
+        // upload of qux didn't finish
+        for (name, uploaded, committed) in [
+            ("foo", Some("abc"), false),
+            ("bar", Some(""), true),
+            ("baz", Some(""), false),
+            ("qux", None, false),
+        ] {
+            let operation = journal.begin("publish").await;
+            journal
+                .record(
+                    operation,
+                    Step::Staged(StagedFile {
+                        name: name.into(),
+                        vers: "0.1.0".into(),
+                        staging_id: operation.to_string(),
+                    }),
+                )
+                .await;
+            if let Some(cksum) = uploaded {
+                journal
+                    .record(operation, Step::Uploaded(cksum.into()))
+                    .await;
+            }
+            if committed {
+                journal.record(operation, Step::Committed).await;
+            }
+            files.lock().unwrap().insert(
+                crate::get_staging_storage_key(name, "0.1.0", &operation.to_string()),
+                vec![1],
+            );
+        }
+        drop(journal);
+
+        let journal = Journal::open(path);
+        let (backend, _) = backend_with_index(published, None, || {
+            Ok(
+                r#"{"name":"foo","vers":"0.1.0","deps":[],"cksum":"abc","features":{},"yanked":false}"#
+                    .into(),
+            )
+        });
+        recover(&journal, &backend, &storage).await;
+        assert_eq!(
+            vec!["3/b/bar/0.1.0.crate", "3/f/foo/0.1.0.crate"],
+            keys(&files)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Incremental parser of the request body sent by `cargo publish`.
+//!
+//! The body consists of a little endian u32 length, the JSON metadata, another
+//! u32 length and the crate file. Chunks are parsed as they arrive, so an
+//! oversized upload is rejected as soon as its lengths are known. Only the
+//! metadata is buffered, the crate file is passed on chunk by chunk.
+
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::config::PublishConfig;
+use crate::models::{MetaData, RegistryError};
+
+enum State {
+    JsonLength,
+    Json(usize),
+    CrateLength,
+    /// bytes of the crate file still to come
+    Crate(usize),
+    Done,
+}
+
+pub struct PublishBodyParser {
+    max_size: u64,
+    max_crate_size: u64,
+    /// bytes received so far
+    received: u64,
+    /// size of the body as far as its lengths are known
+    announced: u64,
+    state: State,
+    buffer: BytesMut,
+    meta: Option<MetaData>,
+}
+
+impl PublishBodyParser {
+    pub fn new(config: &PublishConfig) -> Self {
+        Self {
+            max_size: config.max_upload_size(),
+            max_crate_size: config.max_crate_size(),
+            received: 0,
+            announced: 4,
+            state: State::JsonLength,
+            buffer: BytesMut::with_capacity(4),
+            meta: None,
+        }
+    }
+
+    /// Parses the next chunk of the body, returns the part of the crate file it contains.
+    pub fn push(&mut self, mut chunk: Bytes) -> Result<Option<Bytes>, RegistryError> {
+        self.received += chunk.len() as u64;
+        if self.received > self.max_size {
+            return Err(self.too_large());
+        }
+
+        loop {
+            let wanted = match self.state {
+                State::JsonLength | State::CrateLength => 4,
+                State::Json(length) => length,
+                State::Crate(remaining) => {
+                    let data = chunk.split_to(remaining.min(chunk.len()));
+                    self.state = match remaining - data.len() {
+                        0 => State::Done,
+                        remaining => State::Crate(remaining),
+                    };
+                    if !chunk.is_empty() {
+                        return Err(trailing(&chunk));
+                    }
+                    return Ok(Some(data).filter(|data| !data.is_empty()));
+                }
+                State::Done if chunk.is_empty() => return Ok(None),
+                State::Done => return Err(trailing(&chunk)),
+            };
+            let take = (wanted - self.buffer.len()).min(chunk.len());
+            self.buffer.extend_from_slice(&chunk.split_to(take));
+            if self.buffer.len() < wanted {
+                return Ok(None);
+            }
+            self.complete()?;
+        }
+    }
+
+    /// metadata of the crate, known before the crate file starts
+    pub fn meta(&self) -> Option<&MetaData> {
+        self.meta.as_ref()
+    }
+
+    /// Returns the metadata once the whole body was pushed.
+    pub fn finish(&mut self) -> Result<MetaData, RegistryError> {
+        match (&self.state, self.meta.take()) {
+            (State::Done, Some(meta)) => Ok(meta),
+            _ => Err(malformed(format!(
+                "publish request is truncated, {} of {} bytes were received",
+                self.received, self.announced
+            ))),
+        }
+    }
+
+    /// handles the buffered field, which is complete
+    fn complete(&mut self) -> Result<(), RegistryError> {
+        let mut field = self.buffer.split().freeze();
+        self.state = match self.state {
+            State::JsonLength => {
+                let length = field.get_u32_le() as usize;
+                // the json is followed by the length of the crate file
+                self.announce(length as u64 + 4)?;
+                self.buffer.reserve(length);
+                State::Json(length)
+            }
+            State::Json(_) => {
+                let meta = serde_json::from_slice(&field)
+                    .map_err(|e| malformed(format!("invalid crate metadata: {}", e)))?;
+                crate::validation::validate_meta(&meta)?;
+                self.meta = Some(meta);
+                self.buffer.reserve(4);
+                State::CrateLength
+            }
+            State::CrateLength => {
+                let length = field.get_u32_le() as usize;
+                if length as u64 > self.max_crate_size {
+                    return Err(RegistryError::TooLarge(format!(
+                        "crate file has {} bytes, at most {} are allowed",
+                        length, self.max_crate_size
+                    )));
+                }
+                self.announce(length as u64)?;
+                match length {
+                    0 => State::Done,
+                    length => State::Crate(length),
+                }
+            }
+            State::Crate(length) => State::Crate(length),
+            State::Done => State::Done,
+        };
+        Ok(())
+    }
+
+    fn announce(&mut self, length: u64) -> Result<(), RegistryError> {
+        self.announced += length;
+        if self.announced > self.max_size {
+            return Err(self.too_large());
+        }
+        Ok(())
+    }
+
+    fn too_large(&self) -> RegistryError {
+        RegistryError::TooLarge(format!(
+            "publish request exceeds the maximum upload size of {} bytes",
+            self.max_size
+        ))
+    }
+}
+
+fn malformed(detail: String) -> RegistryError {
+    RegistryError::MalformedRequest(detail)
+}
+
+fn trailing(chunk: &[u8]) -> RegistryError {
+    malformed(format!("{} bytes follow the crate file", chunk.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &[u8] = br#"{"name":"foo","vers":"0.1.0","deps":[],"features":{},"authors":[],"description":null,"documentation":null,"homepage":null,"readme":null,"readme_file":null,"keywords":[],"categories":[],"license":null,"license_file":null,"repository":null,"badges":{},"links":null}"#;
+
+    fn body(json: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        body.extend_from_slice(json);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        body
+    }
+
+    fn config(max_upload_size: u64) -> PublishConfig {
+        PublishConfig {
+            max_upload_size: Some(max_upload_size),
+            ..Default::default()
+        }
+    }
+
+    /// returns the metadata and the crate file collected from the parsed chunks
+    fn parse(
+        body: &[u8],
+        chunk_size: usize,
+        config: &PublishConfig,
+    ) -> Result<(MetaData, Vec<u8>), RegistryError> {
+        let mut parser = PublishBodyParser::new(config);
+        let mut data = Vec::new();
+        for chunk in body.chunks(chunk_size) {
+            if let Some(part) = parser.push(Bytes::copy_from_slice(chunk))? {
+                assert!(parser.meta().is_some());
+                data.extend_from_slice(&part);
+            }
+        }
+        parser.finish().map(|meta| (meta, data))
+    }
+
+    #[test]
+    fn test_parse_in_chunks() {
+        let body = body(JSON, b"crate file");
+
+        for chunk_size in [1, 3, 7, body.len()] {
+            let (meta, data) = parse(&body, chunk_size, &config(body.len() as u64)).unwrap();
+            assert_eq!(meta.name, "foo");
+            assert_eq!(&data[..], b"crate file");
+        }
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        let body = body(b"{}", b"");
+        assert!(matches!(
+            parse(&body[..3], 1, &config(1024)),
+            Err(RegistryError::MalformedRequest(_))
+        ));
+        assert!(matches!(
+            parse(&body, 1, &config(1024)),
+            Err(RegistryError::MalformedRequest(_))
+        ));
+
+        let mut trailing = self::body(JSON, b"crate file");
+        trailing.push(0);
+        assert!(matches!(
+            parse(&trailing, trailing.len(), &config(1024)),
+            Err(RegistryError::MalformedRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_too_large() {
+        // announced lengths are checked before their data arrives
+        let mut parser = PublishBodyParser::new(&config(1024));
+        assert!(matches!(
+            parser.push(Bytes::copy_from_slice(&u32::MAX.to_le_bytes())),
+            Err(RegistryError::TooLarge(_))
+        ));
+        assert!(matches!(
+            parse(&[0; 2048], 2048, &config(1024)),
+            Err(RegistryError::TooLarge(_))
+        ));
+
+        let config = PublishConfig {
+            max_crate_size: Some(9),
+            ..Default::default()
+        };
+        let body = body(JSON, b"crate file");
+        assert!(matches!(
+            parse(&body[..body.len() - 10], body.len(), &config),
+            Err(RegistryError::TooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+//! Checks of crate names and versions following the rules of crates.io.
+
+use crate::models::{MetaData, RegistryError};
+
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// names of the standard library and of Windows devices which can't be used as file names
+const RESERVED_NAMES: &[&str] = &[
+    "alloc",
+    "core",
+    "proc_macro",
+    "std",
+    "test",
+    "aux",
+    "con",
+    "nul",
+    "prn",
+    "com1",
+    "com2",
+    "com3",
+    "com4",
+    "com5",
+    "com6",
+    "com7",
+    "com8",
+    "com9",
+    "lpt1",
+    "lpt2",
+    "lpt3",
+    "lpt4",
+    "lpt5",
+    "lpt6",
+    "lpt7",
+    "lpt8",
+    "lpt9",
+];
+
+/// crate names are considered equal if they only differ in case or in `-` and `_`
+pub fn canonical_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+pub fn validate_name(name: &str) -> Result<(), RegistryError> {
+    let invalid = |reason: &str| Err(RegistryError::InvalidName(name.into(), reason.into()));
+
+    if name.is_empty() {
+        return invalid("name must not be empty");
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return invalid(&format!(
+            "name must not be longer than {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return invalid("name must start with an ASCII letter");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return invalid("name may only contain ASCII letters, digits, `-` and `_`");
+    }
+    if RESERVED_NAMES.contains(&canonical_name(name).as_str()) {
+        return invalid("name is reserved");
+    }
+
+    Ok(())
+}
+
+pub fn validate_version(vers: &str) -> Result<semver::Version, RegistryError> {
+    semver::Version::parse(vers)
+        .map_err(|e| RegistryError::InvalidVersion(vers.into(), e.to_string()))
+}
+
+/// rust versions are given as `major.minor` or `major.minor.patch` without pre-release
+pub fn validate_rust_version(rust_version: &str) -> Result<(), RegistryError> {
+    let parts = rust_version.split('.').collect::<Vec<_>>();
+    let valid = (2..=3).contains(&parts.len())
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(RegistryError::InvalidVersion(
+            rust_version.into(),
+            "rust-version must look like `1.60` or `1.60.0`".into(),
+        ))
+    }
+}
+
+pub fn validate_meta(meta: &MetaData) -> Result<(), RegistryError> {
+    validate_name(&meta.name)?;
+    validate_version(&meta.vers)?;
+    if let Some(rust_version) = &meta.rust_version {
+        validate_rust_version(rust_version)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("serde").is_ok());
+        assert!(validate_name("serde_json").is_ok());
+        assert!(validate_name("tokio-util2").is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LENGTH)).is_ok());
+
+        assert!(validate_name("").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LENGTH + 1)).is_err());
+        assert!(validate_name("1password").is_err());
+        assert!(validate_name("_serde").is_err());
+        assert!(validate_name("sérde").is_err());
+        assert!(validate_name("serde json").is_err());
+        assert!(validate_name("Proc-Macro").is_err());
+        assert!(validate_name("nul").is_err());
+    }
+
+    #[test]
+    fn test_validate_version() {
+        assert!(validate_version("1.0.0").is_ok());
+        assert!(validate_version("0.1.0-alpha.1+build.5").is_ok());
+
+        assert!(validate_version("1.0").is_err());
+        assert!(validate_version("v1.0.0").is_err());
+        assert!(validate_version("01.0.0").is_err());
+    }
+
+    #[test]
+    fn test_validate_rust_version() {
+        assert!(validate_rust_version("1.60").is_ok());
+        assert!(validate_rust_version("1.56.1").is_ok());
+
+        assert!(validate_rust_version("1").is_err());
+        assert!(validate_rust_version("1.60.0-nightly").is_err());
+        assert!(validate_rust_version("1.60.0.1").is_err());
+        assert!(validate_rust_version("1..0").is_err());
+    }
+
+    #[test]
+    fn test_canonical_name() {
+        assert_eq!(canonical_name("Serde-JSON"), canonical_name("serde_json"));
+        assert_ne!(canonical_name("serde"), canonical_name("serdx"));
+    }
+}
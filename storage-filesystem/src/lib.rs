@@ -1,7 +1,9 @@
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
+use cargolifter_core::migration::MigrationReport;
+use cargolifter_core::models::StorageError;
 
 pub struct FileSystemStorage {
     root_folder: String,
@@ -13,23 +15,77 @@ impl FileSystemStorage {
             root_folder: root_folder.into(),
         }
     }
+
+    /// moves all crate files of the legacy layout to their current storage key
+    ///
+    /// Files are copied first and only removed once the copy is complete. Files
+    /// that can't be identified or whose target already holds different data are
+    /// left in place and counted as skipped.
+    pub fn migrate(&self) -> Result<MigrationReport, StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let mut files = Vec::new();
+        collect_files(root_path, &mut files)?;
+
+        let mut report = MigrationReport::default();
+        for path in files {
+            let key = match path.strip_prefix(root_path).ok().and_then(|p| p.to_str()) {
+                Some(key) => key.replace(std::path::MAIN_SEPARATOR, "/"),
+                None => continue,
+            };
+            if !cargolifter_core::migration::is_legacy_storage_key(&key) {
+                continue;
+            }
+
+            let data = read_file(&path)?;
+            let new_key = match cargolifter_core::migration::migrated_storage_key(&key, &data) {
+                Ok(new_key) => new_key,
+                Err(e) => {
+                    tracing::warn!("skipping '{}': {}", key, e);
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+
+            let new_path = root_path.join(&new_key);
+            if new_path.exists() {
+                if read_file(&new_path)? != data {
+                    tracing::warn!(
+                        "skipping '{}': '{}' exists with different content",
+                        key,
+                        new_key
+                    );
+                    report.skipped += 1;
+                    continue;
+                }
+            } else {
+                write_file(&new_path, &data)?;
+            }
+            std::fs::remove_file(&path).map_err(|e| map_io_error(&path, e))?;
+            tracing::info!("migrated '{}' to '{}'", key, new_key);
+            report.migrated += 1;
+        }
+
+        Ok(report)
+    }
 }
 
 #[async_trait]
 impl cargolifter_core::Storage for FileSystemStorage {
-    async fn get(
-        &self,
-        crate_name: &str,
-        crate_version: &str,
-    ) -> Result<Vec<u8>, cargolifter_core::models::StorageError> {
+    async fn get(&self, crate_name: &str, crate_version: &str) -> Result<Vec<u8>, StorageError> {
         let root_path = Path::new(&self.root_folder);
-        let path = root_path.join(cargolifter_core::get_crate_path(crate_name));
-        let path = path.join(crate_version);
+        let path = root_path.join(cargolifter_core::get_crate_storage_key(
+            crate_name,
+            crate_version,
+        ));
         tracing::info!("trying to get '{}'", path.to_str().unwrap());
 
-        let mut data = Vec::new();
-        let mut file = std::fs::File::open(path)?;
-        file.read_to_end(&mut data)?;
+        let data = read_file(&path)?;
+        if data.is_empty() {
+            return Err(StorageError::Corrupt(format!(
+                "'{}' is empty",
+                path.display()
+            )));
+        }
 
         Ok(data)
     }
@@ -39,16 +95,197 @@ impl cargolifter_core::Storage for FileSystemStorage {
         crate_name: &str,
         crate_version: &str,
         data: &[u8],
-    ) -> Result<(), cargolifter_core::models::StorageError> {
+    ) -> Result<(), StorageError> {
         let root_path = Path::new(&self.root_folder);
-        let path = root_path.join(cargolifter_core::get_crate_path(crate_name));
-        std::fs::create_dir_all(path.clone()).unwrap();
-        let path = path.join(crate_version);
+        let path = root_path.join(cargolifter_core::get_crate_storage_key(
+            crate_name,
+            crate_version,
+        ));
         tracing::info!("adding '{}' to storage", path.to_str().unwrap());
 
-        let mut file = std::fs::File::create(path)?;
-        file.write_all(data)?;
+        write_file(&path, data)
+    }
+
+    async fn delete(&mut self, crate_name: &str, crate_version: &str) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_crate_storage_key(
+            crate_name,
+            crate_version,
+        ));
+        tracing::info!("removing '{}' from storage", path.to_str().unwrap());
+
+        std::fs::remove_file(&path).map_err(|e| map_io_error(&path, e))
+    }
+
+    async fn get_mirror(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_mirror_storage_key(
+            crate_name,
+            crate_version,
+        ));
+
+        read_file(&path)
+    }
+
+    async fn put_mirror(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_mirror_storage_key(
+            crate_name,
+            crate_version,
+        ));
+        tracing::info!("adding mirrored '{}' to storage", path.to_str().unwrap());
+
+        write_file(&path, data)
+    }
+
+    async fn append_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_staging_storage_key(
+            crate_name,
+            crate_version,
+            staging_id,
+        ));
+        tracing::debug!(
+            "staging {} bytes to '{}'",
+            data.len(),
+            path.to_str().unwrap()
+        );
+
+        // the staged file is only promoted once complete, so it needs no temporary file
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| map_io_error(parent, e))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| map_io_error(&path, e))?;
+        file.write_all(data).map_err(|e| map_io_error(&path, e))
+    }
+
+    async fn complete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_staging_storage_key(
+            crate_name,
+            crate_version,
+            staging_id,
+        ));
+        tracing::info!("staged '{}'", path.to_str().unwrap());
+
+        let file = std::fs::File::open(&path).map_err(|e| map_io_error(&path, e))?;
+        file.sync_all().map_err(|e| map_io_error(&path, e))
+    }
+
+    async fn promote(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let staged_path = root_path.join(cargolifter_core::get_staging_storage_key(
+            crate_name,
+            crate_version,
+            staging_id,
+        ));
+        let path = root_path.join(cargolifter_core::get_crate_storage_key(
+            crate_name,
+            crate_version,
+        ));
+        tracing::info!(
+            "promoting '{}' to '{}'",
+            staged_path.to_str().unwrap(),
+            path.to_str().unwrap()
+        );
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| map_io_error(parent, e))?;
+        }
+        std::fs::rename(&staged_path, &path).map_err(|e| map_io_error(&staged_path, e))
+    }
 
-        Ok(())
+    async fn delete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let root_path = Path::new(&self.root_folder);
+        let path = root_path.join(cargolifter_core::get_staging_storage_key(
+            crate_name,
+            crate_version,
+            staging_id,
+        ));
+        tracing::info!("removing staged '{}'", path.to_str().unwrap());
+
+        std::fs::remove_file(&path).map_err(|e| map_io_error(&path, e))
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, StorageError> {
+    let mut data = Vec::new();
+    let mut file = std::fs::File::open(path).map_err(|e| map_io_error(path, e))?;
+    file.read_to_end(&mut data)
+        .map_err(|e| map_io_error(path, e))?;
+    Ok(data)
+}
+
+/// writes to a temporary file first so a crate file is never seen half written
+fn write_file(path: &Path, data: &[u8]) -> Result<(), StorageError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| map_io_error(parent, e))?;
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&temp_path).map_err(|e| map_io_error(&temp_path, e))?;
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| map_io_error(&temp_path, e))?;
+    std::fs::rename(&temp_path, path).map_err(|e| map_io_error(path, e))
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), StorageError> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(map_io_error(path, e)),
+    };
+    for entry in entries {
+        let entry_path = entry.map_err(|e| map_io_error(path, e))?.path();
+        if entry_path.is_dir() {
+            collect_files(&entry_path, files)?;
+        } else {
+            files.push(entry_path);
+        }
+    }
+    Ok(())
+}
+
+fn map_io_error(path: &Path, e: std::io::Error) -> StorageError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => StorageError::NotFound(path.display().to_string()),
+        std::io::ErrorKind::PermissionDenied => {
+            StorageError::AccessDenied(format!("'{}': {}", path.display(), e))
+        }
+        _ => StorageError::Io(e),
     }
 }
@@ -1,13 +1,79 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
 
 use async_trait::async_trait;
+use aws_sdk_s3::model::{CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::ByteStream;
 use bytes::Buf;
 use cargolifter_core::config::S3Config;
+use cargolifter_core::migration::MigrationReport;
+use cargolifter_core::models::StorageError;
+
+/// S3 requires all parts of a multipart upload but the last to have at least 5 MiB
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
 
 pub struct S3Storage {
     client: aws_sdk_s3::Client,
     config: S3Config,
+    /// staged crate files that are still appended to, by key
+    uploads: HashMap<String, StagedUpload>,
+}
+
+/// Staged crate file uploaded in parts as its chunks arrive.
+///
+/// Chunks are buffered until they fill a part, a file that never does is
+/// uploaded as a single object once it is complete.
+#[derive(Default)]
+struct StagedUpload {
+    upload_id: Option<String>,
+    parts: Vec<CompletedPart>,
+    buffer: Vec<u8>,
+}
+
+impl StagedUpload {
+    /// uploads the buffered chunks as the next part, starting the multipart upload first
+    async fn upload_part(
+        &mut self,
+        client: &aws_sdk_s3::Client,
+        bucket: &str,
+        key: &str,
+    ) -> Result<(), StorageError> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let response = client
+                    .create_multipart_upload()
+                    .set_bucket(Some(bucket.into()))
+                    .set_key(Some(key.into()))
+                    .send()
+                    .await
+                    .map_err(|e| StorageError::Backend(format!("'{}': {}", key, e)))?;
+                let upload_id = response.upload_id.ok_or_else(|| {
+                    StorageError::Backend(format!("'{}': no multipart upload id", key))
+                })?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+
+        let part_number = self.parts.len() as i32 + 1;
+        let response = client
+            .upload_part()
+            .set_bucket(Some(bucket.into()))
+            .set_key(Some(key.into()))
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .set_body(Some(ByteStream::from(std::mem::take(&mut self.buffer))))
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(format!("'{}' part {}: {}", key, part_number, e)))?;
+        self.parts.push(
+            CompletedPart::builder()
+                .set_e_tag(response.e_tag)
+                .part_number(part_number)
+                .build(),
+        );
+        Ok(())
+    }
 }
 
 impl S3Storage {
@@ -25,71 +91,414 @@ impl S3Storage {
             Self {
                 client: aws_sdk_s3::Client::new(&config),
                 config: s3config,
+                uploads: HashMap::new(),
             }
         } else {
             let config = loader.load().await;
             Self {
                 client: aws_sdk_s3::Client::new(&config),
                 config: s3config,
+                uploads: HashMap::new(),
             }
         }
     }
-}
 
-#[async_trait]
-impl cargolifter_core::Storage for S3Storage {
-    async fn get(
-        &self,
-        crate_name: &str,
-        crate_version: &str,
-    ) -> Result<Vec<u8>, cargolifter_core::models::StorageError> {
-        let path = PathBuf::new();
-        let path = path.join(cargolifter_core::get_crate_path(crate_name));
-        let path = path.join(crate_version);
-        tracing::info!("trying to get '{}'", path.to_str().unwrap());
+    /// moves all crate files of the legacy layout to their current storage key
+    ///
+    /// Objects are copied first and only deleted once the copy was read back
+    /// intact. Objects that can't be identified or whose target already holds
+    /// different data are left in place and counted as skipped.
+    pub async fn migrate(&self) -> Result<MigrationReport, StorageError> {
+        let mut report = MigrationReport::default();
+        for key in self.list_keys().await? {
+            if !cargolifter_core::migration::is_legacy_storage_key(&key) {
+                continue;
+            }
+
+            let data = self.get_object(&key).await?;
+            let new_key = match cargolifter_core::migration::migrated_storage_key(&key, &data) {
+                Ok(new_key) => new_key,
+                Err(e) => {
+                    tracing::warn!("skipping '{}': {}", key, e);
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+
+            match self.get_object(&new_key).await {
+                Ok(existing) if existing != data => {
+                    tracing::warn!(
+                        "skipping '{}': '{}' exists with different content",
+                        key,
+                        new_key
+                    );
+                    report.skipped += 1;
+                    continue;
+                }
+                Ok(_) => {}
+                Err(StorageError::NotFound(_)) => {
+                    self.put_object(&new_key, &data).await?;
+                    // the legacy object is the only intact copy until the new one is confirmed
+                    let copied = self.get_object(&new_key).await?;
+                    if copied != data {
+                        tracing::warn!(
+                            "skipping '{}': '{}' has {} bytes after copying {}",
+                            key,
+                            new_key,
+                            copied.len(),
+                            data.len()
+                        );
+                        report.skipped += 1;
+                        continue;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            self.delete_object(&key).await?;
+            tracing::info!("migrated '{}' to '{}'", key, new_key);
+            report.migrated += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// aborts the multipart uploads of staged crate files a previous run left open
+    ///
+    /// Must be called before the storage is used, as uploads of the running
+    /// process can't be told apart from those of a crashed one.
+    pub async fn abort_staged_uploads(&self) -> Result<usize, StorageError> {
+        let uploads = self.list_uploads("staging/").await?;
+        let count = uploads.len();
+        for (key, upload_id) in uploads {
+            tracing::warn!("aborting unfinished upload of '{}'", key);
+            self.abort_upload(&key, upload_id).await?;
+        }
+        Ok(count)
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>, StorageError> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let response = self
+                .client
+                .list_objects_v2()
+                .set_bucket(Some(self.config.bucket.clone()))
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| StorageError::Backend(format!("failed to list objects: {}", e)))?;
+
+            keys.extend(
+                response
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+            if !response.is_truncated {
+                return Ok(keys);
+            }
+            continuation_token = response.next_continuation_token;
+        }
+    }
 
+    async fn get_object(&self, key: &str) -> Result<Vec<u8>, StorageError> {
         match self
             .client
             .get_object()
             .set_bucket(Some(self.config.bucket.clone()))
-            .set_key(Some(path.to_str().unwrap().into()))
+            .set_key(Some(key.into()))
             .send()
             .await
         {
             Ok(resp) => {
                 let stream = resp.body;
                 match stream.collect().await {
-                    Ok(bytes) => Ok(bytes.chunk().to_vec()),
-                    Err(_) => Err(cargolifter_core::models::StorageError::DetailMeLater),
+                    Ok(bytes) if bytes.remaining() == 0 => {
+                        Err(StorageError::Corrupt(format!("'{}' is empty", key)))
+                    }
+                    Ok(bytes) => Ok(bytes.into_bytes().to_vec()),
+                    Err(e) => Err(StorageError::Backend(format!(
+                        "failed to read '{}': {}",
+                        key, e
+                    ))),
                 }
             }
-            Err(_) => Err(cargolifter_core::models::StorageError::DetailMeLater),
+            Err(aws_sdk_s3::SdkError::ServiceError { err, .. }) if err.is_no_such_key() => {
+                Err(StorageError::NotFound(key.into()))
+            }
+            Err(aws_sdk_s3::SdkError::ServiceError { err, .. })
+                if err.code() == Some("AccessDenied") =>
+            {
+                Err(StorageError::AccessDenied(format!("'{}': {}", key, err)))
+            }
+            Err(e) => Err(StorageError::Backend(format!("'{}': {}", key, e))),
         }
     }
 
-    async fn put(
-        &mut self,
-        crate_name: &str,
-        crate_version: &str,
-        data: &[u8],
-    ) -> Result<(), cargolifter_core::models::StorageError> {
-        let path = PathBuf::new();
-        let path = path.join(cargolifter_core::get_crate_path(crate_name));
-        let path = path.join(crate_version);
-        tracing::info!("adding '{}' to storage", path.to_str().unwrap());
-
+    async fn put_object(&self, key: &str, data: &[u8]) -> Result<(), StorageError> {
         let byte_stream = ByteStream::from(data.to_vec());
         match self
             .client
             .put_object()
             .set_bucket(Some(self.config.bucket.clone()))
-            .set_key(Some(path.to_str().unwrap().into()))
+            .set_key(Some(key.into()))
             .set_body(Some(byte_stream))
             .send()
             .await
         {
             Ok(_) => Ok(()),
-            Err(_) => Err(cargolifter_core::models::StorageError::DetailMeLater),
+            Err(aws_sdk_s3::SdkError::ServiceError { err, .. })
+                if err.code() == Some("AccessDenied") =>
+            {
+                Err(StorageError::AccessDenied(format!("'{}': {}", key, err)))
+            }
+            Err(e) => Err(StorageError::Backend(format!("'{}': {}", key, e))),
+        }
+    }
+
+    async fn copy_object(&self, source: &str, key: &str) -> Result<(), StorageError> {
+        match self
+            .client
+            .copy_object()
+            .set_bucket(Some(self.config.bucket.clone()))
+            .copy_source(format!("{}/{}", self.config.bucket, source))
+            .set_key(Some(key.into()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(aws_sdk_s3::SdkError::ServiceError { err, .. })
+                if err.code() == Some("NoSuchKey") =>
+            {
+                Err(StorageError::NotFound(source.into()))
+            }
+            Err(aws_sdk_s3::SdkError::ServiceError { err, .. })
+                if err.code() == Some("AccessDenied") =>
+            {
+                Err(StorageError::AccessDenied(format!("'{}': {}", key, err)))
+            }
+            Err(e) => Err(StorageError::Backend(format!(
+                "failed to copy '{}' to '{}': {}",
+                source, key, e
+            ))),
+        }
+    }
+
+    /// keys and ids of the multipart uploads below `prefix` that were never completed
+    async fn list_uploads(&self, prefix: &str) -> Result<Vec<(String, String)>, StorageError> {
+        let mut uploads = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let response = self
+                .client
+                .list_multipart_uploads()
+                .set_bucket(Some(self.config.bucket.clone()))
+                .set_prefix(Some(prefix.into()))
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await
+                .map_err(|e| {
+                    StorageError::Backend(format!("failed to list uploads of '{}': {}", prefix, e))
+                })?;
+
+            uploads.extend(
+                response
+                    .uploads
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|upload| upload.key.zip(upload.upload_id)),
+            );
+            if !response.is_truncated {
+                return Ok(uploads);
+            }
+            key_marker = response.next_key_marker;
+            upload_id_marker = response.next_upload_id_marker;
+        }
+    }
+
+    async fn abort_upload(&self, key: &str, upload_id: String) -> Result<(), StorageError> {
+        self.client
+            .abort_multipart_upload()
+            .set_bucket(Some(self.config.bucket.clone()))
+            .set_key(Some(key.into()))
+            .upload_id(upload_id)
+            .send()
+            .await
+            .map_err(|e| {
+                StorageError::Backend(format!("failed to abort upload of '{}': {}", key, e))
+            })?;
+        Ok(())
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), StorageError> {
+        match self
+            .client
+            .delete_object()
+            .set_bucket(Some(self.config.bucket.clone()))
+            .set_key(Some(key.into()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(StorageError::Backend(format!(
+                "failed to delete '{}': {}",
+                key, e
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl cargolifter_core::Storage for S3Storage {
+    async fn get(&self, crate_name: &str, crate_version: &str) -> Result<Vec<u8>, StorageError> {
+        let key = cargolifter_core::get_crate_storage_key(crate_name, crate_version);
+        tracing::info!("trying to get '{}'", key);
+
+        self.get_object(&key).await
+    }
+
+    async fn put(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_crate_storage_key(crate_name, crate_version);
+        tracing::info!("adding '{}' to storage", key);
+
+        self.put_object(&key, data).await
+    }
+
+    async fn delete(&mut self, crate_name: &str, crate_version: &str) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_crate_storage_key(crate_name, crate_version);
+        tracing::info!("removing '{}' from storage", key);
+
+        self.delete_object(&key).await
+    }
+
+    async fn get_mirror(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        let key = cargolifter_core::get_mirror_storage_key(crate_name, crate_version);
+        self.get_object(&key).await
+    }
+
+    async fn put_mirror(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_mirror_storage_key(crate_name, crate_version);
+        tracing::info!("adding mirrored '{}' to storage", key);
+
+        self.put_object(&key, data).await
+    }
+
+    async fn append_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_staging_storage_key(crate_name, crate_version, staging_id);
+        tracing::debug!("staging {} bytes to '{}'", data.len(), key);
+
+        let upload = self.uploads.entry(key.clone()).or_default();
+        upload.buffer.extend_from_slice(data);
+        if upload.buffer.len() >= MIN_PART_SIZE {
+            upload
+                .upload_part(&self.client, &self.config.bucket, &key)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn complete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_staging_storage_key(crate_name, crate_version, staging_id);
+        tracing::info!("staged '{}'", key);
+
+        // a failed upload is kept, so `delete_staged` can abort it
+        let upload = self.uploads.entry(key.clone()).or_default();
+        let upload_id = match upload.upload_id.clone() {
+            Some(upload_id) => upload_id,
+            None => {
+                let data = std::mem::take(&mut upload.buffer);
+                self.put_object(&key, &data).await?;
+                self.uploads.remove(&key);
+                return Ok(());
+            }
+        };
+        if !upload.buffer.is_empty() {
+            upload
+                .upload_part(&self.client, &self.config.bucket, &key)
+                .await?;
+        }
+        let parts = CompletedMultipartUpload::builder()
+            .set_parts(Some(upload.parts.clone()))
+            .build();
+        self.client
+            .complete_multipart_upload()
+            .set_bucket(Some(self.config.bucket.clone()))
+            .set_key(Some(key.clone()))
+            .upload_id(upload_id)
+            .multipart_upload(parts)
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(format!("'{}': {}", key, e)))?;
+        self.uploads.remove(&key);
+        Ok(())
+    }
+
+    /// S3 can't rename objects, so the staged object is copied and deleted afterwards
+    async fn promote(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let staged_key =
+            cargolifter_core::get_staging_storage_key(crate_name, crate_version, staging_id);
+        let key = cargolifter_core::get_crate_storage_key(crate_name, crate_version);
+        tracing::info!("promoting '{}' to '{}'", staged_key, key);
+
+        self.copy_object(&staged_key, &key).await?;
+        self.delete_object(&staged_key).await
+    }
+
+    async fn delete_staged(
+        &mut self,
+        crate_name: &str,
+        crate_version: &str,
+        staging_id: &str,
+    ) -> Result<(), StorageError> {
+        let key = cargolifter_core::get_staging_storage_key(crate_name, crate_version, staging_id);
+        tracing::info!("removing staged '{}'", key);
+
+        // uploads of a previous run are only known to S3
+        let uploads = match self
+            .uploads
+            .remove(&key)
+            .and_then(|upload| upload.upload_id)
+        {
+            Some(upload_id) => vec![(key.clone(), upload_id)],
+            None => self.list_uploads(&key).await?,
+        };
+        for (key, upload_id) in uploads {
+            self.abort_upload(&key, upload_id).await?;
         }
+        self.delete_object(&key).await
     }
 }
@@ -1,8 +1,18 @@
 pub mod download;
+pub mod index;
+pub mod metrics;
+pub mod mirror;
+pub mod owners;
 pub mod publish;
+pub mod search;
+pub mod webhook;
 pub mod yanking;
 
 pub use download::download;
+pub use metrics::metrics;
+pub use owners::{add_owners, list_owners, remove_owners};
 pub use publish::publish;
+pub use search::search;
+pub use webhook::webhook;
 pub use yanking::unyank;
 pub use yanking::yank;
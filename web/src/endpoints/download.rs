@@ -1,14 +1,24 @@
+use cargolifter_core::models::RegistryError;
+
+use crate::error::ApiError;
+
 pub async fn download(
     axum::extract::Path((crate_name, crate_version)): axum::extract::Path<(String, String)>,
     storage: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>>,
-) -> Result<Vec<u8>, axum::http::StatusCode> {
+) -> Result<Vec<u8>, ApiError> {
     tracing::info!(
         "requtested download of '{}' in version '{}'",
         crate_name,
         crate_version
     );
 
-    let (tx, rx) = tokio::sync::oneshot::channel::<Option<Vec<u8>>>();
+    if cargolifter_core::validation::validate_name(&crate_name).is_err() {
+        return Err(RegistryError::NotFound(format!("crate '{}' not found", crate_name)).into());
+    }
+
+    // storage keys are built from the lowercased name, so any casing of the
+    // canonical name stored in the index resolves to the same crate file
+    let (tx, rx) = tokio::sync::oneshot::channel();
     let request = cargolifter_core::models::StorageGetRequest {
         crate_name,
         crate_version,
@@ -20,18 +30,18 @@ pub async fn download(
         .await
     {
         Ok(_) => match rx.await {
-            Ok(result) => match result {
-                Some(data) => Ok(data),
-                None => Err(axum::http::StatusCode::NOT_FOUND),
-            },
+            Ok(result) => result.map_err(|e| {
+                tracing::error!("Failed to get crate from storage: {}", e);
+                RegistryError::from(e).into()
+            }),
             Err(e) => {
                 tracing::error!("Failed to receive storage response: {}", e);
-                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                Err(ApiError::internal("failed to receive storage response"))
             }
         },
         Err(e) => {
             tracing::error!("Failed to send storage request: {}", e);
-            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::internal("failed to send storage request"))
         }
     }
 }
@@ -0,0 +1,281 @@
+use crate::error::{get_token, ApiError};
+use crate::index_cache::{CachedIndexFile, IndexCache};
+use cargolifter_core::models::RegistryError;
+use cargolifter_core::BackendCommand;
+
+pub async fn config(
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+    cache: axum::extract::Extension<IndexCache>,
+) -> Result<axum::http::Response<hyper::Body>, ApiError> {
+    serve(
+        "config.json",
+        "application/json",
+        headers,
+        backend.0,
+        cache.0,
+    )
+    .await
+}
+
+pub async fn crate_file(
+    axum::extract::Path((prefix, crate_name)): axum::extract::Path<(String, String)>,
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+    cache: axum::extract::Extension<IndexCache>,
+) -> Result<axum::http::Response<hyper::Body>, ApiError> {
+    serve_crate(&prefix, &crate_name, headers, backend.0, cache.0).await
+}
+
+pub async fn nested_crate_file(
+    axum::extract::Path((first, second, crate_name)): axum::extract::Path<(String, String, String)>,
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+    cache: axum::extract::Extension<IndexCache>,
+) -> Result<axum::http::Response<hyper::Body>, ApiError> {
+    let prefix = format!("{}/{}", first, second);
+    serve_crate(&prefix, &crate_name, headers, backend.0, cache.0).await
+}
+
+async fn serve_crate(
+    prefix: &str,
+    crate_name: &str,
+    headers: axum::http::HeaderMap,
+    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    cache: IndexCache,
+) -> Result<axum::http::Response<hyper::Body>, ApiError> {
+    if !is_index_path(prefix, crate_name) {
+        tracing::info!("'{}/{}' is not a valid index path", prefix, crate_name);
+        return Err(RegistryError::NotFound(format!("crate '{}' not found", crate_name)).into());
+    }
+
+    let path = cargolifter_core::get_crate_file_path(crate_name);
+    serve(&path, "text/plain; charset=utf-8", headers, backend, cache).await
+}
+
+async fn serve(
+    path: &str,
+    content_type: &str,
+    headers: axum::http::HeaderMap,
+    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    cache: IndexCache,
+) -> Result<axum::http::Response<hyper::Body>, ApiError> {
+    tracing::info!("requested index file '{}'", path);
+
+    let token = match headers.get("authorization") {
+        Some(_) => Some(get_token(&headers)?),
+        None => None,
+    };
+    // without token the index is read with the token of cargolifter, which is
+    // refused if config.json requires cargo to authenticate
+    if token.is_none() {
+        let config = cached_file(&backend, &cache, None, "config.json").await?;
+        if requires_auth(&config.content)? {
+            tracing::info!("refused anonymous request for '{}'", path);
+            return Err(RegistryError::Unauthorized(
+                "the registry requires authentication, please run `cargo login`".into(),
+            )
+            .into());
+        }
+    }
+
+    let file = cached_file(&backend, &cache, token, path).await?;
+    respond(file, content_type, &headers)
+        .map_err(|_| ApiError::internal("failed to build index response"))
+}
+
+async fn cached_file(
+    backend: &tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    cache: &IndexCache,
+    token: Option<&str>,
+    path: &str,
+) -> Result<CachedIndexFile, ApiError> {
+    if let Some(file) = cache.get(token, path) {
+        return Ok(file);
+    }
+
+    let file = get_index_file(backend.clone(), token.map(String::from), path).await?;
+    Ok(cache.insert(token, path, file))
+}
+
+fn requires_auth(config: &str) -> Result<bool, ApiError> {
+    let config: serde_json::Value = serde_json::from_str(config).map_err(|e| {
+        tracing::error!("config.json is invalid: {}", e);
+        ApiError::internal("config.json of the index is invalid")
+    })?;
+    Ok(config.get("auth-required") == Some(&serde_json::Value::Bool(true)))
+}
+
+pub(crate) fn is_index_path(prefix: &str, crate_name: &str) -> bool {
+    crate_name.is_ascii()
+        && cargolifter_core::get_crate_path(crate_name) == prefix.to_ascii_lowercase()
+}
+
+/// answers with the file or with 304 if the client already has the current version
+pub(crate) fn respond(
+    file: CachedIndexFile,
+    content_type: &str,
+    headers: &axum::http::HeaderMap,
+) -> Result<axum::http::Response<hyper::Body>, axum::http::StatusCode> {
+    let response = axum::http::Response::builder()
+        .header(axum::http::header::ETAG, &file.etag)
+        .header(
+            axum::http::header::LAST_MODIFIED,
+            httpdate::fmt_http_date(file.last_modified),
+        );
+    let response = if is_not_modified(headers, &file) {
+        response
+            .status(axum::http::StatusCode::NOT_MODIFIED)
+            .body(hyper::Body::empty())
+    } else {
+        response
+            .header(axum::http::header::CONTENT_TYPE, content_type)
+            .body(hyper::Body::from(file.content))
+    };
+
+    response.map_err(|e| {
+        tracing::error!("Failed to build index response: {}", e);
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+fn is_not_modified(headers: &axum::http::HeaderMap, file: &CachedIndexFile) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(etags) = headers
+        .get(axum::http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        return etags
+            .split(',')
+            .map(|etag| etag.trim().trim_start_matches("W/"))
+            .any(|etag| etag == "*" || etag == file.etag);
+    }
+
+    headers
+        .get(axum::http::header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .map(|since| file.last_modified <= since)
+        .unwrap_or(false)
+}
+
+async fn get_index_file(
+    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    token: Option<String>,
+    path: &str,
+) -> Result<cargolifter_core::models::IndexFile, ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    match backend
+        .send(BackendCommand::GetIndexFile(token, path.into(), tx))
+        .await
+    {
+        Ok(_) => match rx.await {
+            Ok(result) => result.map_err(ApiError::from),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Extension, Path};
+    use axum::http::{header, HeaderMap, StatusCode};
+
+    /// backend with an index that doesn't require authentication
+    fn backend() -> tokio::sync::mpsc::Sender<BackendCommand> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Some(command) = rx.recv().await {
+                if let BackendCommand::GetIndexFile(_, path, result_sender) = command {
+                    let content = match path.as_str() {
+                        "config.json" => r#"{"dl":"http://localhost/api/v1/crates"}"#,
+                        _ => r#"{"name":"foo","vers":"0.1.0"}"#,
+                    };
+                    let _ = result_sender.send(Ok(cargolifter_core::models::IndexFile {
+                        content: content.into(),
+                        sha: "abc".into(),
+                    }));
+                }
+            }
+        });
+        tx
+    }
+
+    fn headers(values: &[(header::HeaderName, &str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+            .collect()
+    }
+
+    async fn get(
+        backend: &tokio::sync::mpsc::Sender<BackendCommand>,
+        cache: &IndexCache,
+        headers: HeaderMap,
+    ) -> axum::http::Response<hyper::Body> {
+        nested_crate_file(
+            Path(("3".into(), "f".into(), "foo".into())),
+            headers,
+            Extension(backend.clone()),
+            Extension(cache.clone()),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_not_modified() {
+        let backend = backend();
+        let cache = IndexCache::new(std::time::Duration::from_secs(60));
+
+        let response = get(&backend, &cache, HeaderMap::new()).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("\"abc\"", response.headers()[header::ETAG]);
+        let last_modified = response.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let earlier = httpdate::fmt_http_date(
+            httpdate::parse_http_date(&last_modified).unwrap() - std::time::Duration::from_secs(60),
+        );
+
+        let cases = [
+            (header::IF_NONE_MATCH, "\"abc\"", StatusCode::NOT_MODIFIED),
+            (
+                header::IF_NONE_MATCH,
+                "\"xyz\", W/\"abc\"",
+                StatusCode::NOT_MODIFIED,
+            ),
+            (header::IF_NONE_MATCH, "*", StatusCode::NOT_MODIFIED),
+            (header::IF_NONE_MATCH, "\"xyz\"", StatusCode::OK),
+            (
+                header::IF_MODIFIED_SINCE,
+                &last_modified,
+                StatusCode::NOT_MODIFIED,
+            ),
+            (header::IF_MODIFIED_SINCE, &earlier, StatusCode::OK),
+            (header::IF_MODIFIED_SINCE, "yesterday", StatusCode::OK),
+        ];
+        for (name, value, status) in cases {
+            let response = get(&backend, &cache, headers(&[(name.clone(), value)])).await;
+            assert_eq!(status, response.status(), "{}: {}", name, value);
+            assert_eq!("\"abc\"", response.headers()[header::ETAG]);
+        }
+
+        // If-None-Match takes precedence over If-Modified-Since
+        let both = headers(&[
+            (header::IF_NONE_MATCH, "\"xyz\""),
+            (header::IF_MODIFIED_SINCE, &last_modified),
+        ]);
+        let response = get(&backend, &cache, both).await;
+        assert_eq!(StatusCode::OK, response.status());
+    }
+}
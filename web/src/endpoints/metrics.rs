@@ -0,0 +1,48 @@
+use cargolifter_core::models::ServiceMetrics;
+use cargolifter_core::BackendCommand;
+
+/// backend service metrics in the Prometheus text format
+pub async fn metrics(
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+) -> Result<String, axum::http::StatusCode> {
+    let (tx, rx) = tokio::sync::oneshot::channel::<ServiceMetrics>();
+    match backend.send(BackendCommand::Metrics(tx)).await {
+        Ok(_) => match rx.await {
+            Ok(result) => Ok(render(&result)),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn render(metrics: &ServiceMetrics) -> String {
+    let mut text = String::from(
+        "# HELP cargolifter_backend_queue_depth Commands waiting for a backend worker.\n\
+         # TYPE cargolifter_backend_queue_depth gauge\n",
+    );
+    for (worker, depth) in metrics.queue_depth.iter().enumerate() {
+        text.push_str(&format!(
+            "cargolifter_backend_queue_depth{{worker=\"{}\"}} {}\n",
+            worker, depth
+        ));
+    }
+    text.push_str(&format!(
+        "# HELP cargolifter_backend_commands_total Commands handled by the backend workers.\n\
+         # TYPE cargolifter_backend_commands_total counter\n\
+         cargolifter_backend_commands_total {}\n\
+         # HELP cargolifter_backend_queue_seconds_total Time handled commands waited for a worker.\n\
+         # TYPE cargolifter_backend_queue_seconds_total counter\n\
+         cargolifter_backend_queue_seconds_total {}\n\
+         # HELP cargolifter_backend_handle_seconds_total Time it took to handle the commands.\n\
+         # TYPE cargolifter_backend_handle_seconds_total counter\n\
+         cargolifter_backend_handle_seconds_total {}\n",
+        metrics.handled, metrics.queue_seconds, metrics.handle_seconds
+    ));
+    text
+}
@@ -0,0 +1,345 @@
+use cargolifter_core::mirror::Upstream;
+use cargolifter_core::models::{BackendError, IndexFile, RegistryError, StorageError};
+use cargolifter_core::StorageCommand;
+
+use crate::error::ApiError;
+use crate::index_cache::{CachedIndexFile, IndexCache};
+
+/// State of the read-only mirror of the upstream registry.
+#[derive(Clone)]
+pub struct Mirror {
+    upstream: Upstream,
+    cache: IndexCache,
+    dl: String,
+}
+
+impl Mirror {
+    pub fn new(config: &cargolifter_core::config::MirrorConfig, cache: IndexCache) -> Self {
+        Self {
+            upstream: Upstream::new(config),
+            cache,
+            dl: format!(
+                "{}/mirror/api/v1/crates",
+                config.public_url.trim_end_matches('/')
+            ),
+        }
+    }
+
+    /// cached index file, the upstream is only asked once the cache entry expired
+    /// and a stale entry is used if the upstream can't be reached
+    async fn index_file(&self, path: &str) -> Result<CachedIndexFile, axum::http::StatusCode> {
+        if let Some(file) = self.cache.get(None, path) {
+            return Ok(file);
+        }
+
+        match self.upstream.get_index_file(path).await {
+            Ok(Some(content)) => {
+                let sha = sha(&content);
+                Ok(self.cache.insert(None, path, IndexFile { content, sha }))
+            }
+            Ok(None) => {
+                self.cache.invalidate(path);
+                Err(axum::http::StatusCode::NOT_FOUND)
+            }
+            Err(e) => match self.cache.get_stale(None, path) {
+                Some(file) => {
+                    tracing::warn!("upstream failed, serving cached '{}': {}", path, e);
+                    Ok(file)
+                }
+                None => {
+                    tracing::error!("Failed to get '{}' from upstream: {}", path, e);
+                    Err(axum::http::StatusCode::BAD_GATEWAY)
+                }
+            },
+        }
+    }
+}
+
+type MirrorExtension = axum::extract::Extension<Option<Mirror>>;
+
+pub async fn config(
+    mirror: MirrorExtension,
+) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+    let mirror = mirror.0.ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    Ok(axum::Json(serde_json::json!({ "dl": mirror.dl })))
+}
+
+pub async fn crate_file(
+    axum::extract::Path((prefix, crate_name)): axum::extract::Path<(String, String)>,
+    headers: axum::http::HeaderMap,
+    mirror: MirrorExtension,
+) -> Result<axum::http::Response<hyper::Body>, axum::http::StatusCode> {
+    serve_crate(&prefix, &crate_name, headers, mirror.0).await
+}
+
+pub async fn nested_crate_file(
+    axum::extract::Path((first, second, crate_name)): axum::extract::Path<(String, String, String)>,
+    headers: axum::http::HeaderMap,
+    mirror: MirrorExtension,
+) -> Result<axum::http::Response<hyper::Body>, axum::http::StatusCode> {
+    let prefix = format!("{}/{}", first, second);
+    serve_crate(&prefix, &crate_name, headers, mirror.0).await
+}
+
+async fn serve_crate(
+    prefix: &str,
+    crate_name: &str,
+    headers: axum::http::HeaderMap,
+    mirror: Option<Mirror>,
+) -> Result<axum::http::Response<hyper::Body>, axum::http::StatusCode> {
+    let mirror = mirror.ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    if !super::index::is_index_path(prefix, crate_name) {
+        tracing::info!("'{}/{}' is not a valid index path", prefix, crate_name);
+        return Err(axum::http::StatusCode::NOT_FOUND);
+    }
+
+    let path = cargolifter_core::get_crate_file_path(crate_name);
+    tracing::info!("requested mirrored index file '{}'", path);
+    let file = mirror.index_file(&path).await?;
+    super::index::respond(file, "text/plain; charset=utf-8", &headers)
+}
+
+pub async fn download(
+    axum::extract::Path((crate_name, crate_version)): axum::extract::Path<(String, String)>,
+    mirror: MirrorExtension,
+    storage: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>>,
+) -> Result<Vec<u8>, ApiError> {
+    tracing::info!(
+        "requested mirrored download of '{}' in version '{}'",
+        crate_name,
+        crate_version
+    );
+    let not_found = || {
+        ApiError::from(RegistryError::NotFound(format!(
+            "crate '{}' version '{}' not found",
+            crate_name, crate_version
+        )))
+    };
+    let mirror = mirror.0.ok_or_else(not_found)?;
+    if cargolifter_core::validation::validate_name(&crate_name).is_err() {
+        return Err(not_found());
+    }
+
+    match get_mirrored(storage.0.clone(), &crate_name, &crate_version).await? {
+        Ok(data) => return Ok(data),
+        Err(StorageError::NotFound(_)) => {}
+        Err(e) => return Err(RegistryError::from(e).into()),
+    }
+
+    let data = match mirror.upstream.download(&crate_name, &crate_version).await {
+        Ok(data) => data,
+        Err(BackendError::NotFound(_)) => return Err(not_found()),
+        Err(e) => {
+            tracing::error!("Failed to download from upstream: {}", e);
+            return Err(RegistryError::from(e).into());
+        }
+    };
+
+    // the crate is served even if it can't be stored, it is fetched again next time
+    if let Err(e) = put_mirrored(storage.0, &crate_name, &crate_version, data.clone()).await? {
+        tracing::error!("Failed to store mirrored crate: {}", e);
+    }
+
+    Ok(data)
+}
+
+async fn get_mirrored(
+    storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
+    crate_name: &str,
+    crate_version: &str,
+) -> Result<Result<Vec<u8>, StorageError>, ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let request = cargolifter_core::models::StorageGetRequest {
+        crate_name: crate_name.into(),
+        crate_version: crate_version.into(),
+        result_sender: tx,
+    };
+
+    match storage.send(StorageCommand::GetMirror(request)).await {
+        Ok(_) => rx.await.map_err(|e| {
+            tracing::error!("Failed to receive storage response: {}", e);
+            ApiError::internal("failed to receive storage response")
+        }),
+        Err(e) => {
+            tracing::error!("Failed to send storage request: {}", e);
+            Err(ApiError::internal("failed to send storage request"))
+        }
+    }
+}
+
+async fn put_mirrored(
+    storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
+    crate_name: &str,
+    crate_version: &str,
+    data: Vec<u8>,
+) -> Result<Result<(), StorageError>, ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let request = cargolifter_core::models::StoragePutRequest {
+        crate_name: crate_name.into(),
+        crate_version: crate_version.into(),
+        data: data.into(),
+        result_sender: tx,
+    };
+
+    match storage.send(StorageCommand::PutMirror(request)).await {
+        Ok(_) => rx.await.map_err(|e| {
+            tracing::error!("Failed to receive storage response: {}", e);
+            ApiError::internal("failed to receive storage response")
+        }),
+        Err(e) => {
+            tracing::error!("Failed to send storage request: {}", e);
+            Err(ApiError::internal("failed to send storage request"))
+        }
+    }
+}
+
+/// etag of upstream files, which are not identified by a commit sha
+fn sha(content: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Extension, Path};
+    use axum::http::StatusCode;
+    use sha2::Digest;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CRATE_FILE: &[u8] = b"crate file of foo";
+
+    async fn upstream() -> (MockServer, Mirror) {
+        let server = MockServer::start().await;
+        let line = format!(
+            r#"{{"name":"foo","vers":"0.1.0","deps":[],"cksum":"{}","features":{{}},"yanked":false,"links":null}}"#,
+            hex::encode(sha2::Sha256::digest(CRATE_FILE))
+        );
+        Mock::given(method("GET"))
+            .and(path("/3/f/foo"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(line))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/config.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(format!(r#"{{"dl":"{}/dl"}}"#, server.uri())),
+            )
+            .mount(&server)
+            .await;
+
+        let config = cargolifter_core::config::MirrorConfig {
+            public_url: "http://localhost:8080/".into(),
+            upstream_index: Some(server.uri()),
+        };
+        let cache = IndexCache::new(std::time::Duration::from_secs(60));
+        (server, Mirror::new(&config, cache))
+    }
+
+    type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// storage that keeps mirrored crate files in memory
+    fn storage() -> (tokio::sync::mpsc::Sender<StorageCommand>, Files) {
+        let files = Arc::new(Mutex::new(HashMap::new()));
+        let stored = files.clone();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Some(command) = rx.recv().await {
+                match command {
+                    StorageCommand::GetMirror(request) => {
+                        let key = format!("{}-{}", request.crate_name, request.crate_version);
+                        let result = files
+                            .lock()
+                            .unwrap()
+                            .get(&key)
+                            .cloned()
+                            .ok_or(StorageError::NotFound(key));
+                        let _ = request.result_sender.send(result);
+                    }
+                    StorageCommand::PutMirror(request) => {
+                        let key = format!("{}-{}", request.crate_name, request.crate_version);
+                        files.lock().unwrap().insert(key, request.data.to_vec());
+                        let _ = request.result_sender.send(Ok(()));
+                    }
+                    _ => panic!("unexpected storage command"),
+                }
+            }
+        });
+        (tx, stored)
+    }
+
+    #[tokio::test]
+    async fn test_index_file() {
+        let (_server, mirror) = upstream().await;
+
+        let response = nested_crate_file(
+            Path(("3".into(), "f".into(), "foo".into())),
+            axum::http::HeaderMap::new(),
+            Extension(Some(mirror.clone())),
+        )
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        let content = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(content.starts_with(br#"{"name":"foo","vers":"0.1.0""#));
+
+        let missing = nested_crate_file(
+            Path(("3".into(), "b".into(), "bar".into())),
+            axum::http::HeaderMap::new(),
+            Extension(Some(mirror.clone())),
+        )
+        .await;
+        assert_eq!(Some(StatusCode::NOT_FOUND), missing.err());
+
+        let mirror_config = config(Extension(Some(mirror))).await.unwrap();
+        assert_eq!(
+            "http://localhost:8080/mirror/api/v1/crates",
+            mirror_config.0["dl"]
+        );
+        assert_eq!(
+            Some(StatusCode::NOT_FOUND),
+            config(Extension(None)).await.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let (server, mirror) = upstream().await;
+        Mock::given(method("GET"))
+            .and(path("/dl/foo/0.1.0/download"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CRATE_FILE))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let (storage, files) = storage();
+        let get = || {
+            download(
+                Path(("foo".into(), "0.1.0".into())),
+                Extension(Some(mirror.clone())),
+                Extension(storage.clone()),
+            )
+        };
+
+        // fetched from upstream once and served from the storage afterwards
+        assert_eq!(CRATE_FILE, get().await.unwrap());
+        assert_eq!(
+            Some(CRATE_FILE),
+            files.lock().unwrap().get("foo-0.1.0").map(Vec::as_slice)
+        );
+        assert_eq!(CRATE_FILE, get().await.unwrap());
+
+        let missing = download(
+            Path(("foo".into(), "0.2.0".into())),
+            Extension(Some(mirror)),
+            Extension(storage),
+        )
+        .await;
+        assert!(matches!(missing, Err(ApiError(RegistryError::NotFound(_)))));
+    }
+}
@@ -0,0 +1,95 @@
+use cargolifter_core::models::{
+    Owners, OwnersChangeResponse, OwnersRequest, OwnersResponse, RegistryError,
+};
+use cargolifter_core::BackendCommand;
+
+use crate::error::{get_token, ApiError};
+
+pub async fn list_owners(
+    axum::extract::Path(crate_name): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+) -> Result<axum::Json<OwnersResponse>, ApiError> {
+    tracing::info!("listing owners of '{}'", crate_name);
+    let token = get_token(&headers)?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<Result<Owners, RegistryError>>();
+    match backend
+        .send(BackendCommand::ListOwners(token.into(), crate_name, tx))
+        .await
+    {
+        Ok(_) => match rx.await {
+            Ok(result) => Ok(axum::Json(result?.into())),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
+        }
+    }
+}
+
+pub async fn add_owners(
+    axum::extract::Path(crate_name): axum::extract::Path<String>,
+    axum::Json(request): axum::Json<OwnersRequest>,
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+) -> Result<axum::Json<OwnersChangeResponse>, ApiError> {
+    tracing::info!("adding owners {:?} to '{}'", request.users, crate_name);
+    let token = get_token(&headers)?;
+
+    let msg = format!(
+        "{} added as owners of crate {}",
+        request.users.join(", "),
+        crate_name
+    );
+    let (tx, rx) = tokio::sync::oneshot::channel::<Result<(), RegistryError>>();
+    let command = BackendCommand::AddOwners(token.into(), crate_name, request.users, tx);
+    change_owners_at_backend(backend.0, command, rx).await?;
+
+    Ok(axum::Json(OwnersChangeResponse { ok: true, msg }))
+}
+
+pub async fn remove_owners(
+    axum::extract::Path(crate_name): axum::extract::Path<String>,
+    axum::Json(request): axum::Json<OwnersRequest>,
+    headers: axum::http::HeaderMap,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+) -> Result<axum::Json<OwnersChangeResponse>, ApiError> {
+    tracing::info!("removing owners {:?} from '{}'", request.users, crate_name);
+    let token = get_token(&headers)?;
+
+    let msg = format!(
+        "{} removed as owners of crate {}",
+        request.users.join(", "),
+        crate_name
+    );
+    let (tx, rx) = tokio::sync::oneshot::channel::<Result<(), RegistryError>>();
+    let command = BackendCommand::RemoveOwners(token.into(), crate_name, request.users, tx);
+    change_owners_at_backend(backend.0, command, rx).await?;
+
+    Ok(axum::Json(OwnersChangeResponse { ok: true, msg }))
+}
+
+async fn change_owners_at_backend(
+    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    command: BackendCommand,
+    rx: tokio::sync::oneshot::Receiver<Result<(), RegistryError>>,
+) -> Result<(), ApiError> {
+    match backend.send(command).await {
+        Ok(_) => match rx.await {
+            Ok(result) => Ok(result?),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
+        }
+    }
+}
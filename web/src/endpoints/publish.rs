@@ -1,153 +1,168 @@
-use cargolifter_core::{models::PublishRequest, BackendCommand, StorageCommand};
+use cargolifter_core::models::{PublishResponse, PublishStatus};
+use cargolifter_core::transaction::Upload;
+use cargolifter_core::BackendCommand;
+
+use crate::error::{get_token, ApiError};
 
 pub async fn publish(
     request: crate::RequestExtractor,
     headers: axum::http::HeaderMap,
     backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
     storage: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>>,
-) -> Result<(), axum::http::StatusCode> {
-    let request = request.0;
-    tracing::info!(
-        "publishing '{}' in version '{}'",
-        request.meta.name,
-        request.meta.vers
-    );
-    let token = match headers.get("authorization") {
-        Some(token) => token.to_str().unwrap(),
-        None => {
-            return Err(axum::http::StatusCode::UNAUTHORIZED);
-        }
-    };
+    cache: axum::extract::Extension<crate::index_cache::IndexCache>,
+    journal: axum::extract::Extension<std::sync::Arc<cargolifter_core::journal::Journal>>,
+    config: axum::extract::Extension<cargolifter_core::config::PublishConfig>,
+) -> Result<axum::Json<PublishResponse>, ApiError> {
+    // the body is neither read nor unpacked for unknown users
+    let token = get_token(&headers)?;
+    authenticate(&backend, token).await?;
 
-    if is_version_published(
-        backend.0.clone(),
-        &request.meta.name,
-        &request.meta.vers,
-        token,
-    )
-    .await?
-    {
-        // TODO: return 200 with json like
-        // {
-        //     // Array of errors to display to the user.
-        //     "errors": [
-        //         {
-        //             // The error message as a string.
-        //             "detail": "error message text"
-        //         }
-        //     ]
-        // }
+    let upload = Upload::new(storage.0.clone(), journal.0.clone(), config.0);
+    let upload = request.upload(upload).await?;
+    let meta = &upload.request().meta;
+    tracing::info!("publishing '{}' in version '{}'", meta.name, meta.vers);
 
-        Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
-    } else {
-        // TODO: store to storage
-        publish_to_storage(
-            storage.0,
-            &request.meta.name,
-            &request.meta.vers,
-            request.data.clone(),
-        )
-        .await?;
-        publish_to_backend(backend.0, request, token).await?;
+    // a retried publish with identical contents passes the owner check first and
+    // then only promotes its crate file again, restoring it if it went missing
+    let crate_path = meta.crate_file_path();
+    let status = cargolifter_core::transaction::publish(
+        backend.0,
+        storage.0,
+        journal.0,
+        token.into(),
+        upload,
+    )
+    .await?;
 
-        Ok(())
+    let mut response = PublishResponse::default();
+    match status {
+        PublishStatus::Published(_) => cache.invalidate(&crate_path),
+        PublishStatus::Pending(pending) => response.warnings.other.push(format!(
+            "the version is only available once pull request {} on branch '{}' got merged",
+            pending.pull_request, pending.branch
+        )),
     }
+
+    Ok(axum::Json(response))
 }
 
-async fn is_version_published(
-    storage: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
-    crate_name: &str,
-    crate_version: &str,
+async fn authenticate(
+    backend: &tokio::sync::mpsc::Sender<BackendCommand>,
     token: &str,
-) -> Result<bool, axum::http::StatusCode> {
-    let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
-
-    match storage
-        .send(BackendCommand::IsVersionPublished(
-            token.into(),
-            crate_name.into(),
-            crate_version.into(),
-            tx,
-        ))
+) -> Result<String, ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    match backend
+        .send(BackendCommand::Authenticate(token.into(), tx))
         .await
     {
         Ok(_) => match rx.await {
-            Ok(result) => Ok(result),
+            Ok(result) => result.map_err(ApiError::from),
             Err(e) => {
-                tracing::error!("Failed to receive storage response: {}", e);
-                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
             }
         },
         Err(e) => {
-            tracing::error!("Failed to send storage command: {}", e);
-            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
         }
     }
 }
 
-async fn publish_to_storage(
-    storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
-    crate_name: &str,
-    crate_version: &str,
-    data: Vec<u8>,
-) -> Result<(), axum::http::StatusCode> {
-    let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
-    let put_request = cargolifter_core::models::StoragePutRequest {
-        crate_name: crate_name.into(),
-        crate_version: crate_version.into(),
-        data,
-        result_sender: tx,
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Extension, FromRequest, RequestParts};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
 
-    match storage.send(StorageCommand::Put(put_request)).await {
-        Ok(_) => match rx.await {
-            Ok(result) => {
-                if result {
-                    Ok(())
-                } else {
-                    tracing::error!("Failed store crate");
-                    Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+    /// backend that accepts every token
+    fn backend() -> tokio::sync::mpsc::Sender<BackendCommand> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Some(command) = rx.recv().await {
+                if let BackendCommand::Authenticate(_, result_sender) = command {
+                    let _ = result_sender.send(Ok("alice".into()));
                 }
             }
-            Err(e) => {
-                tracing::error!("Failed to receive storage response: {}", e);
-                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        },
-        Err(e) => {
-            tracing::error!("Failed to send storage command: {}", e);
-            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+        });
+        tx
+    }
+
+    /// publish request with `json` as metadata followed by `crate_length`
+    fn body(json: &[u8], crate_length: Option<u32>) -> Vec<u8> {
+        let mut body = (json.len() as u32).to_le_bytes().to_vec();
+        body.extend_from_slice(json);
+        if let Some(length) = crate_length {
+            body.extend_from_slice(&length.to_le_bytes());
         }
+        body
     }
-}
 
-async fn publish_to_backend(
-    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
-    request: PublishRequest,
-    token: &str,
-) -> Result<(), axum::http::StatusCode> {
-    let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
-    match backend
-        .send(BackendCommand::Publish(token.into(), Box::new(request), tx))
+    async fn put(body: Vec<u8>, content_length: Option<usize>) -> StatusCode {
+        let config = cargolifter_core::config::PublishConfig {
+            max_upload_size: Some(1024),
+            ..Default::default()
+        };
+        let mut request = axum::http::Request::builder()
+            .method("PUT")
+            .uri("/api/v1/crates/new")
+            .header("authorization", "secret");
+        if let Some(length) = content_length {
+            request = request.header(hyper::header::CONTENT_LENGTH, length);
+        }
+        let mut request = request.body(axum::body::Body::from(body)).unwrap();
+        request.extensions_mut().insert(config.clone());
+
+        let mut parts = RequestParts::new(request);
+        let extractor = match crate::RequestExtractor::from_request(&mut parts).await {
+            Ok(extractor) => extractor,
+            Err(e) => return e.into_response().status(),
+        };
+        let headers = parts.headers().cloned().unwrap();
+        // no crate file gets staged, so the storage is never asked
+        let (storage, _storage) = tokio::sync::mpsc::channel(8);
+        let cache = crate::index_cache::IndexCache::new(std::time::Duration::from_secs(60));
+        let journal = std::sync::Arc::new(cargolifter_core::journal::Journal::new());
+
+        match publish(
+            extractor,
+            headers,
+            Extension(backend()),
+            Extension(storage),
+            Extension(cache),
+            Extension(journal),
+            Extension(config),
+        )
         .await
-    {
-        Ok(_) => match rx.await {
-            Ok(result) => {
-                if result {
-                    Ok(())
-                } else {
-                    tracing::error!("Failed publish crate");
-                    Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to receive backend response: {}", e);
-                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        },
-        Err(e) => {
-            tracing::error!("Failed to send backend command: {}", e);
-            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+        {
+            Ok(_) => StatusCode::OK,
+            Err(e) => e.into_response().status(),
         }
     }
+
+    #[tokio::test]
+    async fn test_malformed_body() {
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            put(body(b"{x", Some(0)), None).await
+        );
+        // the body ends within the metadata
+        let mut truncated = body(br#"{"name":"foo"}"#, None);
+        truncated.truncate(8);
+        assert_eq!(StatusCode::BAD_REQUEST, put(truncated, None).await);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body() {
+        // refused by its announced length before it is read
+        assert_eq!(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            put(body(b"{}", Some(0)), Some(2048)).await
+        );
+        // refused as soon as the lengths in the body exceed the limit
+        let mut streamed = 4096u32.to_le_bytes().to_vec();
+        streamed.extend_from_slice(b"{}");
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, put(streamed, None).await);
+    }
 }
@@ -0,0 +1,31 @@
+use cargolifter_core::models::{RegistryError, SearchRequest, SearchResponse};
+use cargolifter_core::BackendCommand;
+
+use crate::error::ApiError;
+
+pub async fn search(
+    request: Result<axum::extract::Query<SearchRequest>, axum::extract::rejection::QueryRejection>,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+) -> Result<axum::Json<SearchResponse>, ApiError> {
+    let axum::extract::Query(request) = request
+        .map_err(|e| RegistryError::MalformedRequest(format!("invalid search query: {}", e)))?;
+    tracing::info!("searching for '{}'", request.q);
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<SearchResponse>();
+    match backend
+        .send(BackendCommand::Search(Box::new(request), tx))
+        .await
+    {
+        Ok(_) => match rx.await {
+            Ok(result) => Ok(axum::Json(result)),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
+        }
+    }
+}
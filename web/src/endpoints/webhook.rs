@@ -0,0 +1,205 @@
+use cargolifter_core::{
+    models::{PendingVersion, RegistryError, ReviewEvent},
+    BackendCommand, StorageCommand,
+};
+
+use crate::error::ApiError;
+
+/// receives pull request events of the forge to finalize versions waiting for review
+pub async fn webhook(
+    headers: axum::http::HeaderMap,
+    body: bytes::Bytes,
+    secret: axum::extract::Extension<Option<crate::WebhookSecret>>,
+    backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
+    storage: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>>,
+    cache: axum::extract::Extension<crate::index_cache::IndexCache>,
+) -> Result<(), ApiError> {
+    let secret = match &secret.0 {
+        Some(secret) => secret,
+        None => {
+            return Err(RegistryError::NotFound("no webhook secret is configured".into()).into())
+        }
+    };
+
+    let event = match cargolifter_core::review::review_event(&headers, &body, &secret.0)? {
+        Some(event) => event,
+        None => return Ok(()),
+    };
+    tracing::info!(
+        "pull request {} got {}",
+        event.pull_request,
+        if event.merged { "merged" } else { "closed" }
+    );
+
+    let merged = event.merged;
+    let pending = match review_to_backend(backend.0, event).await? {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+
+    if merged {
+        cache.invalidate(&pending.meta.crate_file_path());
+    } else {
+        delete_from_storage(storage.0, &pending.meta.name, &pending.meta.vers).await?;
+    }
+
+    Ok(())
+}
+
+async fn review_to_backend(
+    backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
+    event: ReviewEvent,
+) -> Result<Option<PendingVersion>, ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    match backend.send(BackendCommand::Review(event, tx)).await {
+        Ok(_) => match rx.await {
+            Ok(result) => result.map_err(ApiError::from),
+            Err(e) => {
+                tracing::error!("Failed to receive backend response: {}", e);
+                Err(ApiError::internal("failed to receive backend response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send backend command: {}", e);
+            Err(ApiError::internal("failed to send backend command"))
+        }
+    }
+}
+
+async fn delete_from_storage(
+    storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
+    crate_name: &str,
+    crate_version: &str,
+) -> Result<(), ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let delete_request = cargolifter_core::models::StorageFileRequest {
+        crate_name: crate_name.into(),
+        crate_version: crate_version.into(),
+        result_sender: tx,
+    };
+
+    match storage.send(StorageCommand::Delete(delete_request)).await {
+        Ok(_) => match rx.await {
+            Ok(result) => result.map_err(|e| {
+                tracing::error!("Failed to delete crate: {}", e);
+                RegistryError::from(e).into()
+            }),
+            Err(e) => {
+                tracing::error!("Failed to receive storage response: {}", e);
+                Err(ApiError::internal("failed to receive storage response"))
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to send storage command: {}", e);
+            Err(ApiError::internal("failed to send storage command"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Extension;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use hmac::{Mac, NewMac};
+
+    const BODY: &[u8] =
+        br#"{"action":"opened","pull_request":{"number":1,"merged":false,"head":{}}}"#;
+
+    fn signature(secret: &str) -> String {
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(BODY);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// status of a GitHub webhook with the given headers, and whether it reached the backend
+    async fn post(secret: Option<&str>, headers: &[(&'static str, &str)]) -> (StatusCode, bool) {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    axum::http::header::HeaderName::from_static(name),
+                    value.parse().unwrap(),
+                )
+            })
+            .collect::<HeaderMap>();
+        let (backend, mut backend_rx) = tokio::sync::mpsc::channel(8);
+        let (storage, _storage) = tokio::sync::mpsc::channel(8);
+        let cache = crate::index_cache::IndexCache::new(std::time::Duration::from_secs(60));
+
+        let status = match webhook(
+            headers,
+            bytes::Bytes::from_static(BODY),
+            Extension(secret.map(|secret| crate::WebhookSecret(secret.into()))),
+            Extension(backend),
+            Extension(storage),
+            Extension(cache),
+        )
+        .await
+        {
+            Ok(()) => StatusCode::OK,
+            Err(e) => e.into_response().status(),
+        };
+        (status, backend_rx.try_recv().is_ok())
+    }
+
+    #[tokio::test]
+    async fn test_signature() {
+        let valid = signature("secret");
+        let forged = signature("guessed");
+        let event = ("x-github-event", "pull_request");
+
+        // opened pull requests are accepted but not passed on
+        assert_eq!(
+            (StatusCode::OK, false),
+            post(Some("secret"), &[event, ("x-hub-signature-256", &valid)]).await
+        );
+        assert_eq!(
+            (StatusCode::UNAUTHORIZED, false),
+            post(Some("secret"), &[event, ("x-hub-signature-256", &forged)]).await
+        );
+        assert_eq!(
+            (StatusCode::UNAUTHORIZED, false),
+            post(
+                Some("secret"),
+                &[event, ("x-hub-signature-256", "sha256=zz")]
+            )
+            .await
+        );
+        assert_eq!(
+            (StatusCode::UNAUTHORIZED, false),
+            post(Some("secret"), &[event]).await
+        );
+        assert_eq!(
+            (StatusCode::UNAUTHORIZED, false),
+            post(
+                Some("secret"),
+                &[
+                    ("x-gitlab-event", "Merge Request Hook"),
+                    ("x-gitlab-token", "guessed")
+                ]
+            )
+            .await
+        );
+        assert_eq!(
+            (StatusCode::BAD_REQUEST, false),
+            post(Some("secret"), &[("x-hub-signature-256", &valid)]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_secret() {
+        let signed = signature("");
+        let headers = [
+            ("x-github-event", "pull_request"),
+            ("x-hub-signature-256", signed.as_str()),
+        ];
+        assert_eq!((StatusCode::NOT_FOUND, false), post(None, &headers).await);
+        // an empty secret would accept any webhook signed with it
+        assert_eq!(
+            (StatusCode::UNAUTHORIZED, false),
+            post(Some(""), &headers).await
+        );
+    }
+}
@@ -1,17 +1,18 @@
-use cargolifter_core::{models::YankRequest, BackendCommand};
+use cargolifter_core::{
+    models::{RegistryError, YankRequest},
+    BackendCommand,
+};
+
+use crate::error::{get_token, ApiError};
 
 pub async fn yank(
     axum::extract::Path((crate_name, crate_version)): axum::extract::Path<(String, String)>,
     headers: axum::http::HeaderMap,
     backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
-) -> Result<(), axum::http::StatusCode> {
+    cache: axum::extract::Extension<crate::index_cache::IndexCache>,
+) -> Result<(), ApiError> {
     tracing::info!("yanking '{}' in version '{}'", crate_name, crate_version);
-    let token = match headers.get("authorization") {
-        Some(token) => token.to_str().unwrap(),
-        None => {
-            return Err(axum::http::StatusCode::UNAUTHORIZED);
-        }
-    };
+    let token = get_token(&headers)?;
 
     let request = YankRequest {
         name: crate_name.clone(),
@@ -20,6 +21,7 @@ pub async fn yank(
     };
 
     yank_at_backend(backend.0, request, token).await?;
+    cache.invalidate(&cargolifter_core::get_crate_file_path(&crate_name));
     Ok(())
 }
 
@@ -27,14 +29,10 @@ pub async fn unyank(
     axum::extract::Path((crate_name, crate_version)): axum::extract::Path<(String, String)>,
     headers: axum::http::HeaderMap,
     backend: axum::extract::Extension<tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>>,
-) -> Result<(), axum::http::StatusCode> {
+    cache: axum::extract::Extension<crate::index_cache::IndexCache>,
+) -> Result<(), ApiError> {
     tracing::info!("unyanking '{}' in version '{}'", crate_name, crate_version);
-    let token = match headers.get("authorization") {
-        Some(token) => token.to_str().unwrap(),
-        None => {
-            return Err(axum::http::StatusCode::UNAUTHORIZED);
-        }
-    };
+    let token = get_token(&headers)?;
 
     let request = YankRequest {
         name: crate_name.clone(),
@@ -43,6 +41,7 @@ pub async fn unyank(
     };
 
     yank_at_backend(backend.0, request, token).await?;
+    cache.invalidate(&cargolifter_core::get_crate_file_path(&crate_name));
     Ok(())
 }
 
@@ -50,29 +49,22 @@ async fn yank_at_backend(
     backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
     request: YankRequest,
     token: &str,
-) -> Result<(), axum::http::StatusCode> {
-    let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
+) -> Result<(), ApiError> {
+    let (tx, rx) = tokio::sync::oneshot::channel::<Result<(), RegistryError>>();
     match backend
         .send(BackendCommand::Yank(token.into(), Box::new(request), tx))
         .await
     {
         Ok(_) => match rx.await {
-            Ok(result) => {
-                if result {
-                    Ok(())
-                } else {
-                    tracing::error!("Failed yank/unyank crate");
-                    Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
+            Ok(result) => Ok(result?),
             Err(e) => {
                 tracing::error!("Failed to receive backend response: {}", e);
-                Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                Err(ApiError::internal("failed to receive backend response"))
             }
         },
         Err(e) => {
             tracing::error!("Failed to send backend command: {}", e);
-            Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::internal("failed to send backend command"))
         }
     }
 }
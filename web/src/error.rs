@@ -0,0 +1,86 @@
+use cargolifter_core::models::{BackendError, ErrorResponse, RegistryError, StorageError};
+
+/// registry error rendered as the json error body displayed by cargo
+#[derive(Debug)]
+pub struct ApiError(pub RegistryError);
+
+impl ApiError {
+    fn status(&self) -> axum::http::StatusCode {
+        match &self.0 {
+            RegistryError::Backend(BackendError::Forbidden(_)) => axum::http::StatusCode::FORBIDDEN,
+            RegistryError::Backend(BackendError::RateLimited(_)) => {
+                axum::http::StatusCode::SERVICE_UNAVAILABLE
+            }
+            RegistryError::Backend(BackendError::InvalidIndex(_)) => {
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+            RegistryError::Backend(_) => axum::http::StatusCode::BAD_GATEWAY,
+            RegistryError::Conflict(_) => axum::http::StatusCode::CONFLICT,
+            RegistryError::Internal(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            RegistryError::InvalidCrate(_) => axum::http::StatusCode::BAD_REQUEST,
+            RegistryError::InvalidName(_, _) => axum::http::StatusCode::BAD_REQUEST,
+            RegistryError::InvalidOwners(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            RegistryError::InvalidVersion(_, _) => axum::http::StatusCode::BAD_REQUEST,
+            RegistryError::LastOwner(_) => axum::http::StatusCode::BAD_REQUEST,
+            RegistryError::MalformedRequest(_) => axum::http::StatusCode::BAD_REQUEST,
+            RegistryError::NameTaken(_, _) => axum::http::StatusCode::CONFLICT,
+            RegistryError::NotAnOwner(_, _) => axum::http::StatusCode::FORBIDDEN,
+            RegistryError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            RegistryError::PendingReview(_, _) => axum::http::StatusCode::CONFLICT,
+            RegistryError::QueueFull(_) => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            RegistryError::Storage(StorageError::NotFound(_)) => axum::http::StatusCode::NOT_FOUND,
+            RegistryError::Storage(StorageError::AccessDenied(_)) => {
+                axum::http::StatusCode::FORBIDDEN
+            }
+            RegistryError::Storage(StorageError::Io(_))
+            | RegistryError::Storage(StorageError::Backend(_)) => {
+                axum::http::StatusCode::SERVICE_UNAVAILABLE
+            }
+            RegistryError::Storage(StorageError::Corrupt(_)) => {
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+            RegistryError::TooLarge(_) => axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            RegistryError::Unauthorized(_) => axum::http::StatusCode::UNAUTHORIZED,
+            RegistryError::VersionExists(_, _) => axum::http::StatusCode::CONFLICT,
+        }
+    }
+
+    pub fn internal(detail: impl std::fmt::Display) -> Self {
+        Self(RegistryError::Internal(detail.to_string()))
+    }
+}
+
+impl std::convert::From<RegistryError> for ApiError {
+    fn from(e: RegistryError) -> Self {
+        Self(e)
+    }
+}
+
+impl axum::response::IntoResponse for ApiError {
+    type Body = hyper::Body;
+    type BodyError = hyper::Error;
+
+    fn into_response(self) -> axum::http::Response<Self::Body> {
+        let body = serde_json::to_vec(&ErrorResponse::from(&self.0)).unwrap_or_default();
+        let mut response = axum::http::Response::new(hyper::Body::from(body));
+        *response.status_mut() = self.status();
+        response.headers_mut().insert(
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderValue::from_static("application/json"),
+        );
+        response
+    }
+}
+
+pub fn get_token(headers: &axum::http::HeaderMap) -> Result<&str, ApiError> {
+    match headers.get("authorization") {
+        Some(token) => token.to_str().map_err(|_| {
+            ApiError(RegistryError::MalformedRequest(
+                "authorization header is not valid".into(),
+            ))
+        }),
+        None => Err(ApiError(RegistryError::Unauthorized(
+            "no authorization token given, please run `cargo login`".into(),
+        ))),
+    }
+}
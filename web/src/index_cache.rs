@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct CachedIndexFile {
+    pub content: String,
+    pub etag: String,
+    pub last_modified: SystemTime,
+    fetched_at: Instant,
+}
+
+/// cached versions of one file by the token they were read with
+type Entries = HashMap<Option<String>, CachedIndexFile>;
+
+/// how long an expired entry is kept to be served while the source can't be reached
+const MAX_STALE_AGE: Duration = Duration::from_secs(60 * 60);
+
+struct Files {
+    entries: HashMap<String, Entries>,
+    pruned_at: Instant,
+}
+
+/// Local cache of index files read from the backend.
+///
+/// Entries are served without asking the backend for `ttl`. Files are kept per
+/// token, so a file is only served to the token it was read with. Last-Modified
+/// is the time a content change was first seen, as the backends don't report it.
+/// Entries older than `ttl` and an hour are dropped, so files of tokens that are
+/// no longer used don't pile up.
+#[derive(Clone)]
+pub struct IndexCache {
+    ttl: Duration,
+    files: Arc<Mutex<Files>>,
+}
+
+impl IndexCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            files: Arc::new(Mutex::new(Files {
+                entries: HashMap::new(),
+                pruned_at: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn get(&self, token: Option<&str>, path: &str) -> Option<CachedIndexFile> {
+        self.get_stale(token, path)
+            .filter(|file| file.fetched_at.elapsed() < self.ttl)
+    }
+
+    /// entry regardless of its age, for when the source can't be reached
+    pub fn get_stale(&self, token: Option<&str>, path: &str) -> Option<CachedIndexFile> {
+        let files = self.files.lock().unwrap();
+        files
+            .entries
+            .get(path)
+            .and_then(|entries| entries.get(&token.map(String::from)))
+            .cloned()
+    }
+
+    pub fn insert(
+        &self,
+        token: Option<&str>,
+        path: &str,
+        file: cargolifter_core::models::IndexFile,
+    ) -> CachedIndexFile {
+        let etag = format!("\"{}\"", file.sha);
+        let mut files = self.files.lock().unwrap();
+        if files.pruned_at.elapsed() >= self.ttl {
+            self.prune(&mut files);
+        }
+        let entries = files.entries.entry(path.into()).or_default();
+        let last_modified = match entries.values().find(|cached| cached.etag == etag) {
+            Some(cached) => cached.last_modified,
+            None => {
+                // http dates have a resolution of seconds
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                UNIX_EPOCH + Duration::from_secs(now.as_secs())
+            }
+        };
+
+        let cached = CachedIndexFile {
+            content: file.content,
+            etag,
+            last_modified,
+            fetched_at: Instant::now(),
+        };
+        entries.insert(token.map(String::from), cached.clone());
+        cached
+    }
+
+    pub fn invalidate(&self, path: &str) {
+        self.files.lock().unwrap().entries.remove(path);
+    }
+
+    fn prune(&self, files: &mut Files) {
+        let max_age = self.ttl + MAX_STALE_AGE;
+        files.entries.retain(|_, entries| {
+            entries.retain(|_, cached| cached.fetched_at.elapsed() < max_age);
+            !entries.is_empty()
+        });
+        files.pruned_at = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargolifter_core::models::IndexFile;
+
+    fn file(sha: &str) -> IndexFile {
+        IndexFile {
+            content: "{}".into(),
+            sha: sha.into(),
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let cache = IndexCache::new(Duration::from_secs(60));
+        cache.insert(Some("alice"), "3/f/foo", file("1"));
+
+        assert!(cache.get(Some("alice"), "3/f/foo").is_some());
+        assert!(cache.get(Some("bob"), "3/f/foo").is_none());
+        assert!(cache.get(None, "3/f/foo").is_none());
+
+        // an unchanged file keeps the time its content was first seen
+        let first = cache.get(Some("alice"), "3/f/foo").unwrap();
+        let second = cache.insert(Some("bob"), "3/f/foo", file("1"));
+        assert_eq!(first.last_modified, second.last_modified);
+        assert_eq!("\"1\"", second.etag);
+    }
+
+    #[test]
+    fn test_prune() {
+        let cache = IndexCache::new(Duration::ZERO);
+        cache.insert(Some("alice"), "3/f/foo", file("1"));
+        cache.insert(Some("bob"), "3/b/bar", file("2"));
+        {
+            // the file read by alice outlived the time it is kept for
+            let mut files = cache.files.lock().unwrap();
+            let old = Instant::now() - MAX_STALE_AGE - Duration::from_secs(1);
+            files
+                .entries
+                .get_mut("3/f/foo")
+                .unwrap()
+                .values_mut()
+                .for_each(|f| f.fetched_at = old);
+        }
+
+        cache.insert(Some("carol"), "3/b/bar", file("2"));
+        assert!(cache.get_stale(Some("alice"), "3/f/foo").is_none());
+        assert!(cache.get_stale(Some("bob"), "3/b/bar").is_some());
+        assert!(!cache.files.lock().unwrap().entries.contains_key("3/f/foo"));
+    }
+}
@@ -1,47 +1,99 @@
 mod endpoints;
+mod error;
+mod index_cache;
 
-use hyper::body::Buf;
+pub use error::ApiError;
+
+use hyper::body::HttpBody;
 
 pub struct WebService {
     backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
     storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
     config: cargolifter_core::config::WebServiceConfig,
+    mirror: Option<cargolifter_core::config::MirrorConfig>,
+    review: Option<cargolifter_core::config::ReviewConfig>,
+    journal: std::sync::Arc<cargolifter_core::journal::Journal>,
+    publish: cargolifter_core::config::PublishConfig,
 }
 
-pub struct RequestExtractor(cargolifter_core::models::PublishRequest);
+/// secret the forge webhooks are verified with
+#[derive(Clone)]
+pub struct WebhookSecret(String);
+
+/// body of a publish request, only read once the request is authenticated
+pub struct RequestExtractor(axum::body::Body);
 
 #[axum::async_trait]
 impl axum::extract::FromRequest for RequestExtractor {
-    type Rejection = axum::http::StatusCode;
+    type Rejection = ApiError;
 
     async fn from_request(req: &mut axum::extract::RequestParts) -> Result<Self, Self::Rejection> {
-        let mut data = hyper::body::to_bytes(req.body_mut().unwrap())
-            .await
-            .unwrap();
-        let json_length = data.get_u32_le() as usize;
-        let json_data = &data[0..json_length].to_vec();
-        data.advance(json_length);
-        let data_length = data.get_u32_le() as usize;
-
-        Ok(Self {
-            0: cargolifter_core::models::PublishRequest {
-                meta: serde_json::from_slice(json_data).unwrap(),
-                data: data[0..data_length].to_vec(),
-            },
-        })
+        let max_upload_size = req
+            .extensions()
+            .and_then(|extensions| extensions.get::<cargolifter_core::config::PublishConfig>())
+            .map(|config| config.max_upload_size())
+            .unwrap_or_else(|| {
+                cargolifter_core::config::PublishConfig::default().max_upload_size()
+            });
+
+        // reject oversized uploads before reading them
+        let content_length = req
+            .headers()
+            .and_then(|headers| headers.get(hyper::header::CONTENT_LENGTH))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if matches!(content_length, Some(length) if length > max_upload_size) {
+            return Err(cargolifter_core::models::RegistryError::TooLarge(format!(
+                "publish request exceeds the maximum upload size of {} bytes",
+                max_upload_size
+            ))
+            .into());
+        }
+
+        let body = req
+            .take_body()
+            .ok_or_else(|| ApiError::internal("request body already extracted"))?;
+        Ok(Self(body))
     }
 }
 
+impl RequestExtractor {
+    /// Streams the body into `upload`, which stages the crate file while it arrives.
+    pub async fn upload(
+        mut self,
+        mut upload: cargolifter_core::transaction::Upload,
+    ) -> Result<cargolifter_core::transaction::StagedUpload, ApiError> {
+        while let Some(chunk) = self.0.data().await {
+            let chunk =
+                chunk.map_err(|e| malformed(format!("failed to read request body: {}", e)))?;
+            upload.push(chunk).await?;
+        }
+        Ok(upload.finish().await?)
+    }
+}
+
+fn malformed(detail: String) -> ApiError {
+    cargolifter_core::models::RegistryError::MalformedRequest(detail).into()
+}
+
 impl WebService {
     pub fn new(
         backend: tokio::sync::mpsc::Sender<cargolifter_core::BackendCommand>,
         storage: tokio::sync::mpsc::Sender<cargolifter_core::StorageCommand>,
         config: cargolifter_core::config::WebServiceConfig,
+        mirror: Option<cargolifter_core::config::MirrorConfig>,
+        review: Option<cargolifter_core::config::ReviewConfig>,
+        journal: std::sync::Arc<cargolifter_core::journal::Journal>,
+        publish: cargolifter_core::config::PublishConfig,
     ) -> Self {
         Self {
             backend,
             storage,
             config,
+            mirror,
+            review,
+            journal,
+            publish,
         }
     }
 
@@ -49,7 +101,19 @@ impl WebService {
         let host = format!("0.0.0.0:{}", self.config.port); // TODO: confiure port
         tracing::info!("starting web service at: {}", host);
 
+        let index_cache_ttl =
+            std::time::Duration::from_secs(self.config.index_cache_ttl.unwrap_or(60));
+        let index_cache = index_cache::IndexCache::new(index_cache_ttl);
+        let mirror = self.mirror.as_ref().map(|config| {
+            endpoints::mirror::Mirror::new(config, index_cache::IndexCache::new(index_cache_ttl))
+        });
+        let webhook_secret = self
+            .review
+            .as_ref()
+            .map(|config| WebhookSecret(config.webhook_secret.clone()));
+
         let app = axum::Router::new()
+            .route("/api/v1/crates", axum::handler::get(endpoints::search))
             .route(
                 "/api/v1/crates/:crate_name/:crate_version/download",
                 axum::handler::get(endpoints::download),
@@ -63,7 +127,48 @@ impl WebService {
                 "/api/v1/crates/:name/:version/unyank",
                 axum::handler::put(endpoints::unyank),
             )
+            .route(
+                "/api/v1/crates/:name/owners",
+                axum::handler::get(endpoints::list_owners)
+                    .put(endpoints::add_owners)
+                    .delete(endpoints::remove_owners),
+            )
+            .route("/webhook", axum::handler::post(endpoints::webhook))
+            .route("/metrics", axum::handler::get(endpoints::metrics))
+            .route(
+                "/index/config.json",
+                axum::handler::get(endpoints::index::config),
+            )
+            .route(
+                "/index/:prefix/:name",
+                axum::handler::get(endpoints::index::crate_file),
+            )
+            .route(
+                "/index/:first/:second/:name",
+                axum::handler::get(endpoints::index::nested_crate_file),
+            )
+            .route(
+                "/mirror/api/v1/crates/:crate_name/:crate_version/download",
+                axum::handler::get(endpoints::mirror::download),
+            )
+            .route(
+                "/mirror/index/config.json",
+                axum::handler::get(endpoints::mirror::config),
+            )
+            .route(
+                "/mirror/index/:prefix/:name",
+                axum::handler::get(endpoints::mirror::crate_file),
+            )
+            .route(
+                "/mirror/index/:first/:second/:name",
+                axum::handler::get(endpoints::mirror::nested_crate_file),
+            )
             .layer(axum::AddExtensionLayer::new(self.backend.clone()))
+            .layer(axum::AddExtensionLayer::new(mirror))
+            .layer(axum::AddExtensionLayer::new(webhook_secret))
+            .layer(axum::AddExtensionLayer::new(index_cache))
+            .layer(axum::AddExtensionLayer::new(self.journal.clone()))
+            .layer(axum::AddExtensionLayer::new(self.publish.clone()))
             .layer(axum::AddExtensionLayer::new(self.storage.clone()));
 
         axum::Server::bind(&host.parse().unwrap())